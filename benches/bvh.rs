@@ -0,0 +1,96 @@
+// benches/bvh.rs
+// Compares World::hit through the BVH against the linear scan over every object.
+// Run with `cargo bench --bench bvh`.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use raytracer::geometry::Point3;
+use raytracer::material::{Color, Lambertian};
+use raytracer::math::Interval;
+use raytracer::ray::Ray;
+use raytracer::shapes::{Hittable, Shape, Sphere, World};
+use raytracer::texture::solid;
+use std::time::{Duration, Instant};
+
+const RAY_COUNT: usize = 100_000;
+
+// A cube of small random spheres, like the scattered spheres of a cover scene
+fn random_world(rng: &mut StdRng, sphere_count: usize) -> World {
+    let mut world = World::new();
+    world.add_material(Lambertian {
+        albedo: solid(Color::new(0.5, 0.5, 0.5)),
+    });
+    for _ in 0..sphere_count {
+        let sphere = Sphere::new(
+            rng.gen_range(-50.0..50.0),
+            rng.gen_range(-50.0..50.0),
+            rng.gen_range(-50.0..50.0),
+            rng.gen_range(0.1..0.5),
+        );
+        world.add_object(Shape::Sphere(sphere), 1);
+    }
+    world
+}
+
+fn random_rays(rng: &mut StdRng) -> Vec<Ray> {
+    let origin = Point3::new(0.0, 0.0, 120.0);
+    (0..RAY_COUNT)
+        .map(|_| {
+            let target = Point3::new(
+                rng.gen_range(-50.0..50.0),
+                rng.gen_range(-50.0..50.0),
+                0.0,
+            );
+            Ray {
+                origin,
+                direction: target - origin,
+                time: 0.0,
+            }
+        })
+        .collect()
+}
+
+// Returns the elapsed time and the sum of hit distances, which must agree between methods
+fn trace(world: &World, rays: &[Ray]) -> (Duration, f64) {
+    let mut hit_rec = world.new_hitrecord();
+    let mut t_sum = 0.0;
+    let start = Instant::now();
+    for ray in rays {
+        if world.hit(ray, &Interval::new(0.001, f64::INFINITY), &mut hit_rec) {
+            t_sum += hit_rec.t;
+        }
+    }
+    (start.elapsed(), t_sum)
+}
+
+fn main() {
+    let mut rng = StdRng::seed_from_u64(0);
+    println!(
+        "{:>8} {:>12} {:>12} {:>12} {:>9}",
+        "Spheres", "Build", "Linear", "BVH", "Speedup"
+    );
+    for sphere_count in [10, 100, 1_000, 10_000] {
+        let mut world = random_world(&mut rng, sphere_count);
+        let rays = random_rays(&mut rng);
+
+        let (linear_time, linear_sum) = trace(&world, &rays);
+        let start = Instant::now();
+        world.build_bvh();
+        let build_time = start.elapsed();
+        let (bvh_time, bvh_sum) = trace(&world, &rays);
+
+        assert!(
+            (linear_sum - bvh_sum).abs() <= 1e-6 * linear_sum.abs().max(1.0),
+            "BVH and linear scan disagree: {} vs {}",
+            bvh_sum,
+            linear_sum
+        );
+        println!(
+            "{:>8} {:>12.2?} {:>12.2?} {:>12.2?} {:>8.1}x",
+            sphere_count,
+            build_time,
+            linear_time,
+            bvh_time,
+            linear_time.as_secs_f64() / bvh_time.as_secs_f64()
+        );
+    }
+}
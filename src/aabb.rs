@@ -0,0 +1,138 @@
+// aabb.rs
+// Axis-aligned bounding boxes used to accelerate ray intersection
+use crate::geometry::{Point3, Vec3};
+use crate::math::Interval;
+use crate::ray::Ray;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Aabb {
+    // Builds the box spanned by two arbitrary corners
+    pub fn new(a: Point3, b: Point3) -> Self {
+        Aabb {
+            min: Vec3::min(a, b),
+            max: Vec3::max(a, b),
+        }
+    }
+
+    // The empty box: union with any other box returns that box unchanged
+    pub fn empty() -> Self {
+        Aabb {
+            min: Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    // False for boxes of unbounded shapes such as planes, which cannot go in a BVH
+    pub fn is_finite(&self) -> bool {
+        (0..3).all(|axis| self.min[axis].is_finite() && self.max[axis].is_finite())
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::min(self.min, other.min),
+            max: Vec3::max(self.max, other.max),
+        }
+    }
+
+    // The box both boxes cover, which is empty if they do not overlap
+    pub fn intersect(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::max(self.min, other.min),
+            max: Vec3::min(self.max, other.max),
+        }
+    }
+
+    pub fn grow(&self, p: Point3) -> Aabb {
+        Aabb {
+            min: Vec3::min(self.min, p),
+            max: Vec3::max(self.max, p),
+        }
+    }
+
+    // Pads degenerate (flat) dimensions so that slab tests never divide a zero-width interval
+    pub fn pad(&self, delta: f64) -> Aabb {
+        let extent = self.max - self.min;
+        let mut padded = *self;
+        for axis in 0..3 {
+            if extent[axis] < delta {
+                padded.min[axis] -= delta / 2.0;
+                padded.max[axis] += delta / 2.0;
+            }
+        }
+        padded
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    // Index of the axis along which the box is widest (0 = x, 1 = y, 2 = z)
+    pub fn longest_axis(&self) -> usize {
+        let d = self.max - self.min;
+        if d.x > d.y && d.x > d.z {
+            0
+        } else if d.y > d.z {
+            1
+        } else {
+            2
+        }
+    }
+
+    // The part of interval over which the ray is inside the box, if any
+    pub fn clip(&self, ray: &Ray, interval: &Interval) -> Option<(f64, f64)> {
+        let (mut t0, mut t1) = (interval.min, interval.max);
+        for axis in 0..3 {
+            let inverse = 1.0 / ray.direction[axis];
+            let a = (self.min[axis] - ray.origin[axis]) * inverse;
+            let b = (self.max[axis] - ray.origin[axis]) * inverse;
+            let (near, far) = if a < b { (a, b) } else { (b, a) };
+            // NaN from a ray in the plane of a face keeps the previous limits
+            t0 = if near > t0 { near } else { t0 };
+            t1 = if far < t1 { far } else { t1 };
+            if t1 <= t0 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+
+    // Slab test. inv_direction is the component-wise reciprocal of ray.direction, which
+    // the caller precomputes once per ray rather than once per box.
+    pub fn hit(&self, ray: &Ray, inv_direction: Vec3, time: &Interval) -> bool {
+        let mut t_min = time.min;
+        let mut t_max = time.max;
+        for axis in 0..3 {
+            let t0 = (self.min[axis] - ray.origin[axis]) * inv_direction[axis];
+            let t1 = (self.max[axis] - ray.origin[axis]) * inv_direction[axis];
+            let (near, far) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            // Written so that a NaN from 0 * inf leaves the interval untouched
+            if near > t_min {
+                t_min = near;
+            }
+            if far < t_max {
+                t_max = far;
+            }
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
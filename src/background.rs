@@ -0,0 +1,159 @@
+// background.rs
+// Radiance arriving along rays that leave the scene without hitting anything
+use crate::geometry::{Vec3, degrees_to_radians};
+use crate::image::Image;
+use crate::material::{BLACK, BLUE, Color, WHITE};
+use crate::ray::Ray;
+use crate::sampling::Distribution2D;
+use rand::Rng;
+use rand::rngs::StdRng;
+use std::f64::consts::PI;
+use std::fmt::Debug;
+
+pub trait Background: Debug + Sync + Send {
+    fn radiance(&self, ray: &Ray) -> Color;
+
+    // Picks a unit direction towards the background along with its density per unit solid
+    // angle. Backgrounds that are not worth sampling explicitly return None and are only
+    // found by scattered rays.
+    fn sample(&self, _rng: &mut StdRng) -> Option<(Vec3, f64)> {
+        None
+    }
+
+    // Density with which sample picks direction
+    fn pdf(&self, _direction: Vec3) -> f64 {
+        0.0
+    }
+}
+
+// The same colour in every direction
+#[derive(Debug)]
+pub struct SolidBackground {
+    pub color: Color,
+}
+
+impl Background for SolidBackground {
+    fn radiance(&self, _ray: &Ray) -> Color {
+        self.color
+    }
+}
+
+// Sky that blends from the horizon colour (looking straight down) to the zenith colour
+// (looking straight up)
+#[derive(Debug)]
+pub struct GradientBackground {
+    pub horizon: Color,
+    pub zenith: Color,
+}
+
+impl Default for GradientBackground {
+    fn default() -> Self {
+        GradientBackground {
+            horizon: WHITE,
+            zenith: BLUE,
+        }
+    }
+}
+
+impl Background for GradientBackground {
+    fn radiance(&self, ray: &Ray) -> Color {
+        let unit_direction = ray.direction.normalize();
+        let a = (unit_direction.y + 1.0) * 0.5;
+        (a) * self.zenith + (1.0 - a) * self.horizon
+    }
+}
+
+// No light from outside the scene, for interiors lit only by emissive materials
+#[derive(Debug)]
+pub struct VoidBackground;
+
+impl Background for VoidBackground {
+    fn radiance(&self, _ray: &Ray) -> Color {
+        BLACK
+    }
+}
+
+// Equirectangular environment map, e.g. a captured HDR panorama. The top row of the image
+// looks straight up and u runs once around the horizon; rotation turns the map about the
+// vertical axis. Directions are importance sampled by luminance so that small bright
+// sources such as the sun are found by light sampling rather than by chance.
+#[derive(Debug)]
+pub struct EnvironmentBackground {
+    image: Image,
+    rotation: f64, // Radians
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl EnvironmentBackground {
+    pub fn new(image: Image, rotation_degrees: f64, intensity: f64) -> Self {
+        // Rows near the poles cover less solid angle than rows at the horizon
+        let weights: Vec<f64> = image
+            .pixels
+            .iter()
+            .enumerate()
+            .map(|(i, color)| {
+                let theta = PI * ((i / image.width) as f64 + 0.5) / image.height as f64;
+                luminance(*color) * theta.sin()
+            })
+            .collect();
+        let distribution = Distribution2D::new(&weights, image.width, image.height);
+        EnvironmentBackground {
+            image,
+            rotation: degrees_to_radians(rotation_degrees),
+            intensity,
+            distribution,
+        }
+    }
+
+    fn direction_to_uv(&self, direction: Vec3) -> (f64, f64) {
+        let direction = direction.normalize();
+        let phi = direction.z.atan2(direction.x) - self.rotation;
+        let theta = direction.y.clamp(-1.0, 1.0).acos();
+        ((phi / (2.0 * PI)).rem_euclid(1.0), theta / PI)
+    }
+
+    fn uv_to_direction(&self, u: f64, v: f64) -> Vec3 {
+        let phi = u * 2.0 * PI + self.rotation;
+        let theta = v * PI;
+        Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin())
+    }
+
+    // Nearest texel, so radiance is constant wherever the sampling density is
+    fn lookup(&self, u: f64, v: f64) -> Color {
+        let x = ((u * self.image.width as f64) as usize).min(self.image.width - 1);
+        let y = ((v * self.image.height as f64) as usize).min(self.image.height - 1);
+        self.intensity * self.image.get(x, y)
+    }
+}
+
+impl Background for EnvironmentBackground {
+    fn radiance(&self, ray: &Ray) -> Color {
+        let (u, v) = self.direction_to_uv(ray.direction);
+        self.lookup(u, v)
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> Option<(Vec3, f64)> {
+        let ((u, v), pdf) = self.distribution.sample(rng.r#gen(), rng.r#gen());
+        let sin_theta = (v * PI).sin();
+        if pdf <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+        // The map covers 2 pi by pi radians, squeezed together towards the poles
+        Some((self.uv_to_direction(u, v), pdf / (2.0 * PI * PI * sin_theta)))
+    }
+
+    fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+// Rec. 709 relative luminance
+fn luminance(color: Color) -> f64 {
+    0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
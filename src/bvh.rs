@@ -0,0 +1,299 @@
+// bvh.rs
+// Bounding volume hierarchy over an arbitrary list of primitives.
+// The tree is built top-down with a binned surface area heuristic (SAH), then stored as a
+// flat array of nodes in depth-first order: the first child of an interior node always
+// sits directly after its parent, so only the second child's index needs to be stored.
+use crate::aabb::Aabb;
+use crate::geometry::Vec3;
+use crate::math::Interval;
+use crate::ray::Ray;
+
+const BIN_COUNT: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+// Cost of visiting a node relative to intersecting one primitive
+const TRAVERSAL_COST: f64 = 0.125;
+// Past this depth we fall back to median splits, which halve the primitive count at every
+// level and so bound the traversal stack size
+const MAX_SAH_DEPTH: usize = 32;
+const STACK_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+    bounds: Aabb,
+    // Leaf: index of the first primitive in `indices`. Interior: index of the second child.
+    offset: usize,
+    count: usize, // 0 for interior nodes
+    axis: usize,  // Split axis, used to visit the nearer child first
+}
+
+#[derive(Debug, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>, // Primitive indices, ordered so that every leaf covers a contiguous range
+}
+
+#[derive(Clone, Copy)]
+struct BuildPrimitive {
+    index: usize,
+    bounds: Aabb,
+    centroid: Vec3,
+}
+
+#[derive(Clone, Copy)]
+struct Bin {
+    bounds: Aabb,
+    count: usize,
+}
+
+impl Bvh {
+    // Builds a hierarchy over primitives 0..bounds.len(), where bounds[i] is the box of primitive i
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let mut primitives: Vec<BuildPrimitive> = bounds
+            .iter()
+            .enumerate()
+            .map(|(index, b)| BuildPrimitive {
+                index,
+                bounds: *b,
+                centroid: b.centroid(),
+            })
+            .collect();
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * bounds.len()),
+            indices: Vec::with_capacity(bounds.len()),
+        };
+        if !primitives.is_empty() {
+            bvh.build_recursive(&mut primitives, 0);
+        }
+        bvh
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::empty(), |root| root.bounds)
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // Returns the index of the node it created
+    fn build_recursive(&mut self, primitives: &mut [BuildPrimitive], depth: usize) -> usize {
+        let node_index = self.nodes.len();
+        let bounds = primitives
+            .iter()
+            .fold(Aabb::empty(), |b, p| b.union(&p.bounds));
+        self.nodes.push(BvhNode {
+            bounds,
+            offset: 0,
+            count: 0,
+            axis: 0,
+        });
+
+        if primitives.len() == 1 {
+            self.make_leaf(node_index, primitives);
+            return node_index;
+        }
+
+        let centroid_bounds = primitives
+            .iter()
+            .fold(Aabb::empty(), |b, p| b.grow(p.centroid));
+        let axis = centroid_bounds.longest_axis();
+        let axis_min = centroid_bounds.min[axis];
+        let axis_extent = centroid_bounds.max[axis] - axis_min;
+
+        // All centroids coincide: no split can separate them
+        if axis_extent <= 0.0 {
+            if primitives.len() <= MAX_LEAF_SIZE {
+                self.make_leaf(node_index, primitives);
+                return node_index;
+            }
+            let mid = primitives.len() / 2;
+            return self.build_children(node_index, axis, primitives, mid, depth);
+        }
+
+        let mid = if depth >= MAX_SAH_DEPTH {
+            None
+        } else {
+            Self::sah_split(primitives, &bounds, axis, axis_min, axis_extent)
+        };
+        let mid = match mid {
+            Some(mid) => mid,
+            None if primitives.len() <= MAX_LEAF_SIZE => {
+                self.make_leaf(node_index, primitives);
+                return node_index;
+            }
+            None => {
+                // Leaf would be too large: split at the median centroid instead
+                let mid = primitives.len() / 2;
+                primitives.select_nth_unstable_by(mid, |a, b| {
+                    a.centroid[axis].total_cmp(&b.centroid[axis])
+                });
+                mid
+            }
+        };
+        self.build_children(node_index, axis, primitives, mid, depth)
+    }
+
+    fn build_children(
+        &mut self,
+        node_index: usize,
+        axis: usize,
+        primitives: &mut [BuildPrimitive],
+        mid: usize,
+        depth: usize,
+    ) -> usize {
+        let (left, right) = primitives.split_at_mut(mid);
+        self.build_recursive(left, depth + 1);
+        let second_child = self.build_recursive(right, depth + 1);
+        let node = &mut self.nodes[node_index];
+        node.offset = second_child;
+        node.axis = axis;
+        node_index
+    }
+
+    fn make_leaf(&mut self, node_index: usize, primitives: &[BuildPrimitive]) {
+        let node = &mut self.nodes[node_index];
+        node.offset = self.indices.len();
+        node.count = primitives.len();
+        self.indices.extend(primitives.iter().map(|p| p.index));
+    }
+
+    // Buckets centroids into bins along the split axis and evaluates the SAH cost of splitting
+    // between each pair of neighbouring bins. Partitions the primitives around the cheapest
+    // split and returns the partition point, or None if a leaf is cheaper than any split.
+    fn sah_split(
+        primitives: &mut [BuildPrimitive],
+        bounds: &Aabb,
+        axis: usize,
+        axis_min: f64,
+        axis_extent: f64,
+    ) -> Option<usize> {
+        let bin_of = |p: &BuildPrimitive| -> usize {
+            let b = (BIN_COUNT as f64 * (p.centroid[axis] - axis_min) / axis_extent) as usize;
+            b.min(BIN_COUNT - 1)
+        };
+
+        let mut bins = [Bin {
+            bounds: Aabb::empty(),
+            count: 0,
+        }; BIN_COUNT];
+        for p in primitives.iter() {
+            let bin = &mut bins[bin_of(p)];
+            bin.bounds = bin.bounds.union(&p.bounds);
+            bin.count += 1;
+        }
+
+        // Sweep from the right to get the area and count of everything right of each split
+        let mut right_area = [0.0; BIN_COUNT - 1];
+        let mut right_count = [0; BIN_COUNT - 1];
+        let mut accumulated = Aabb::empty();
+        let mut count = 0;
+        for split in (0..BIN_COUNT - 1).rev() {
+            accumulated = accumulated.union(&bins[split + 1].bounds);
+            count += bins[split + 1].count;
+            right_area[split] = accumulated.surface_area();
+            right_count[split] = count;
+        }
+
+        // Then from the left, keeping the cheapest split seen
+        let mut best_split = 0;
+        let mut best_cost = f64::INFINITY;
+        let mut accumulated = Aabb::empty();
+        let mut count = 0;
+        for split in 0..BIN_COUNT - 1 {
+            accumulated = accumulated.union(&bins[split].bounds);
+            count += bins[split].count;
+            if count == 0 || right_count[split] == 0 {
+                continue;
+            }
+            let cost = count as f64 * accumulated.surface_area()
+                + right_count[split] as f64 * right_area[split];
+            if cost < best_cost {
+                best_cost = cost;
+                best_split = split;
+            }
+        }
+
+        let total_area = bounds.surface_area();
+        let split_cost = if total_area > 0.0 {
+            TRAVERSAL_COST + best_cost / total_area
+        } else {
+            best_cost
+        };
+        let leaf_cost = primitives.len() as f64;
+        if !best_cost.is_finite() || (split_cost >= leaf_cost && primitives.len() <= MAX_LEAF_SIZE)
+        {
+            return None;
+        }
+
+        // In-place partition: primitives in bins 0..=best_split go to the front
+        let mut mid = 0;
+        for i in 0..primitives.len() {
+            if bin_of(&primitives[i]) <= best_split {
+                primitives.swap(i, mid);
+                mid += 1;
+            }
+        }
+        Some(mid)
+    }
+
+    // Walks the hierarchy front to back. hit_primitive is called with a primitive index and
+    // the interval still worth searching, and returns the ray parameter of its hit, if any.
+    // Returns true if any primitive was hit. A caller that never reports a hit is shown every
+    // primitive whose leaf the ray passes through.
+    pub fn hit<F>(&self, ray: &Ray, time: &Interval, mut hit_primitive: F) -> bool
+    where
+        F: FnMut(usize, &Interval) -> Option<f64>,
+    {
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let inv_direction = Vec3::new(
+            1.0 / ray.direction.x,
+            1.0 / ray.direction.y,
+            1.0 / ray.direction.z,
+        );
+        let direction_is_negative = [
+            inv_direction.x < 0.0,
+            inv_direction.y < 0.0,
+            inv_direction.z < 0.0,
+        ];
+
+        let mut closest_t = time.max;
+        let mut hit_anything = false;
+        let mut stack = [0usize; STACK_SIZE];
+        let mut stack_len = 0;
+        let mut current = 0;
+        loop {
+            let node = &self.nodes[current];
+            let search = Interval::new(time.min, closest_t);
+            if node.bounds.hit(ray, inv_direction, &search) {
+                if node.count > 0 {
+                    for i in node.offset..node.offset + node.count {
+                        let search = Interval::new(time.min, closest_t);
+                        if let Some(t) = hit_primitive(self.indices[i], &search) {
+                            closest_t = t;
+                            hit_anything = true;
+                        }
+                    }
+                } else {
+                    // Descend into the child nearer the ray origin first
+                    let (near, far) = if direction_is_negative[node.axis] {
+                        (node.offset, current + 1)
+                    } else {
+                        (current + 1, node.offset)
+                    };
+                    stack[stack_len] = far;
+                    stack_len += 1;
+                    current = near;
+                    continue;
+                }
+            }
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            current = stack[stack_len];
+        }
+        hit_anything
+    }
+}
@@ -2,20 +2,17 @@
 // Controls camera data and position
 
 use crate::geometry::{Point3, Vec3, degrees_to_radians};
-use crate::material::BLACK;
-use crate::material::BLUE;
 use crate::material::Color;
-use crate::material::GREEN;
-use crate::material::RED;
-use crate::material::WHITE;
+use crate::material::{BLACK, RED};
 use crate::math::Interval;
 use crate::ray::Ray;
-use crate::shapes::{Hittable, World};
-use rand::Rng;
-use rand::rngs::ThreadRng;
+use crate::sampling::power_heuristic;
+use crate::shapes::{HitRecord, Hittable, World};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 use rayon::prelude::*;
-use std::fs::File;
-use std::io::{self, BufWriter, Result, Write};
+use crate::film::Film;
+use std::io::{self, Write};
 
 pub struct Camera {
     pub image_width: u32,
@@ -24,7 +21,20 @@ pub struct Camera {
     pub samples: u32,
     pub max_depth: i32,
     pub vfov: f64, // Vertical field of view in degrees
+    // Seed for the per-pixel random number generators. Renders with the same seed are
+    // identical regardless of thread count; None picks a fresh seed for every render.
+    pub seed: Option<u64>,
+    // Next-event estimation: at each diffuse bounce, also send a shadow ray towards a sampled
+    // light and weight the two strategies with multiple importance sampling. Turning this off
+    // leaves pure BSDF sampling, which is unbiased but much noisier for small lights.
+    pub light_sampling: bool,
+    // Times at which the shutter opens and closes. Each camera ray is cast at a uniformly
+    // random time in between, so shapes that move blur along their path. Motion is described
+    // over times 0 to 1, and both default to 0, a still frame.
+    pub shutter_open: f64,
+    pub shutter_close: f64,
     // Viewport fields:
+    pixel_spread: f64, // Angle one pixel subtends, for texture filtering footprints
     pixel00: Point3,
     delta_u: Vec3,
     delta_v: Vec3,
@@ -33,12 +43,13 @@ pub struct Camera {
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         aspect_ratio: f64,
         image_width: u32,
         location: Point3,
         view_target: Point3,
-        focal_length: f64,
+        _focal_length: f64, // Unused: the focus distance is the distance to view_target
         focal_angle: f64, /* Degrees */
         vfov: f64,
         samples: u32,
@@ -88,6 +99,11 @@ impl Camera {
             samples,
             max_depth,
             vfov,
+            seed: None,
+            light_sampling: true,
+            shutter_open: 0.0,
+            shutter_close: 0.0,
+            pixel_spread: 2.0 * h / image_height as f64,
             pixel00,
             delta_u: pixel_delta_u,
             delta_v: pixel_delta_v,
@@ -96,43 +112,44 @@ impl Camera {
         }
     }
 
-    pub fn render(&self, world: &World) -> io::Result<()> {
-        println!("Writing {} x {} image", self.image_width, self.image_height);
-        let file = File::create("output.ppm")?;
-        let mut buf_writer = BufWriter::new(file);
-        writeln!(buf_writer, "P3")?;
-        writeln!(buf_writer, "{} {}", self.image_width, self.image_height)?;
-        writeln!(buf_writer, "255")?;
+    // Traces every pixel and returns the accumulated radiance; nothing is written to disk
+    pub fn render(&self, world: &World) -> Film {
+        println!("Rendering {} x {} image", self.image_width, self.image_height);
+        let seed = self.seed.unwrap_or_else(rand::random);
+        let mut film = Film::new(self.image_width, self.image_height);
 
-        for row in 0..(self.image_height) {
-            io::stdout().flush()?;
+        for (row, line_buffer) in film.rows_mut().enumerate() {
+            let row = row as u32;
+            let _ = io::stdout().flush();
             print!("\rRendering line {}", row);
-            let mut line_buffer: Vec<Color> = vec![Color::default(); self.image_width as usize];
             line_buffer
                 .par_iter_mut()
                 .enumerate()
-                .for_each(|(col, elem)| {
-                    let mut rng: ThreadRng = rand::thread_rng();
-                    let mut color = Color::new(0.0, 0.0, 0.0);
-                    for i in 0..self.samples {
+                .for_each(|(col, pixel)| {
+                    let mut rng = StdRng::seed_from_u64(self.pixel_seed(seed, row, col as u32));
+                    for _ in 0..self.samples {
                         //println!("Casting Ray at ({}, {})", row, col);
                         let ray = self.get_ray(row, col as u32, &mut rng);
-                        color = color + Camera::ray_color(&ray, world, &mut rng, self.max_depth);
-                        //println!();
+                        let color = self.ray_color(&ray, world, &mut rng, self.max_depth, None, 0.0);
+                        pixel.add_sample(color, 1.0);
                     }
-                    let color_avg = color / self.samples as f64;
-                    let gamma_corrected_color = Self::color_gamma_transform(color_avg, 2.0);
-                    *elem = gamma_corrected_color;
-                    //Camera::write_pixel(&mut buf_writer, gamma_corrected_color)?;
                 });
-            Camera::write_line(&mut buf_writer, &line_buffer);
         }
-        println!("");
+        println!();
+        film
+    }
 
-        Ok(())
+    // Decorrelates the generators of neighbouring pixels by hashing the render seed with the
+    // pixel index (splitmix64 finalizer)
+    fn pixel_seed(&self, seed: u64, row: u32, col: u32) -> u64 {
+        let pixel = row as u64 * self.image_width as u64 + col as u64;
+        let mut z = seed ^ pixel.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
     }
 
-    fn get_ray(&self, pixel_row: u32, pixel_col: u32, rng: &mut ThreadRng) -> Ray {
+    fn get_ray(&self, pixel_row: u32, pixel_col: u32, rng: &mut StdRng) -> Ray {
         // Pixels are located at the center of the square they occupy
         // Thus, we sample an offset in [-0.5,0.5) x [-0.5,0.5) to get a ray in the sample pixel
         // u is the change of coordinate for the x direction, and v is the change of coordinate for the y direction
@@ -143,68 +160,154 @@ impl Camera {
             + ((pixel_col as f64 + offset_u) * self.delta_u);
 
         let ray_origin = self.sample_ray_origin(rng);
+        let open = self.shutter_open.clamp(0.0, 1.0);
+        let close = self.shutter_close.clamp(0.0, 1.0);
+        // Still frames draw no time, so they render exactly as before motion existed
+        let time = if close > open { open + (close - open) * rng.r#gen::<f64>() } else { open };
 
         Ray {
             origin: ray_origin,
             direction: viewport_location - ray_origin,
+            time,
         }
     }
 
-    fn sample_ray_origin(&self, rng: &mut ThreadRng) -> Point3 {
+    fn sample_ray_origin(&self, rng: &mut StdRng) -> Point3 {
         let offset = Vec3::sample_unit_disk(rng);
         //println!("{:?}", offset);
         self.location + offset.x * self.lens_u + offset.y * self.lens_v
     }
 
-    fn write_line<W: Write>(writer: &mut W, line_buffer: &Vec<Color>) {
-        for color in line_buffer {
-            let rbyte = (color.x * 255.999) as u8;
-            let gbyte = (color.y * 255.999) as u8;
-            let bbyte = (color.z * 255.999) as u8;
-            writeln!(writer, "{} {} {}", rbyte, gbyte, bbyte);
-        }
-    }
-
-    fn write_pixel<W: Write>(writer: &mut W, color: Color) -> Result<()> {
-        let rbyte = (color.x * 255.999) as u8;
-        let gbyte = (color.y * 255.999) as u8;
-        let bbyte = (color.z * 255.999) as u8;
-        writeln!(writer, "{} {} {}", rbyte, gbyte, bbyte)
-    }
-
-    fn color_gamma_transform(color: Color, gamma: f64) -> Color {
-        Color::new(
-            Self::linear_to_gamma(color.x, gamma),
-            Self::linear_to_gamma(color.y, gamma),
-            Self::linear_to_gamma(color.z, gamma),
-        )
-    }
-
-    // Transforms linear colour space to gamma
-    fn linear_to_gamma(linear: f64, gamma: f64) -> f64 {
-        linear.powf(1.0 / gamma)
-    }
-
-    fn color_rgb(r: u8, g: u8, b: u8) -> Color {
-        Color::new(r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0)
-    }
-
-    fn ray_color(ray: &Ray, world: &World, rng: &mut ThreadRng, depth: i32) -> Color {
+    // scatter_pdf is the density with which the previous bounce picked ray when lights were
+    // also sampled there, or None for camera rays, specular bounces and pure BSDF sampling.
+    // distance is the length of the path before ray, over which the pixel's cone has spread.
+    fn ray_color(
+        &self,
+        ray: &Ray,
+        world: &World,
+        rng: &mut StdRng,
+        depth: i32,
+        scatter_pdf: Option<f64>,
+        distance: f64,
+    ) -> Color {
         if depth <= 0 {
             return RED;
         }
         let mut hit_rec = world.new_hitrecord();
-        if world.hit(ray, &Interval::new(0.001, 100000000000.0), &mut hit_rec) {
-            match hit_rec.material.scatter(ray, &hit_rec, rng) {
-                Some((attenuation, new_ray)) => {
-                    attenuation * Self::ray_color(&new_ray, world, rng, depth - 1)
-                }
-                None => BLACK,
+        let search = Interval::new(0.001, 100000000000.0);
+        let surface = world.hit(ray, &search, &mut hit_rec);
+        // Fog or smoke on the way may scatter the ray before it reaches the surface
+        let reach = Interval::new(search.min, if surface { hit_rec.t } else { search.max });
+        let mut glow = BLACK; // Given off by media on the way
+        let in_medium = world.sample_medium(ray, &reach, &mut hit_rec, &mut glow, rng);
+        glow + if surface || in_medium {
+            let distance = distance + hit_rec.t * ray.direction.len();
+            hit_rec.footprint = self.pixel_spread * distance;
+            let mut emitted = hit_rec.material.emitted(&hit_rec);
+            if let Some(pdf) = scatter_pdf
+                && hit_rec.material.is_emissive()
+            {
+                // The light may also have been sampled directly from the last hit
+                let light_pdf = world.light_pdf(ray.origin, ray.direction, ray.time);
+                emitted = power_heuristic(pdf, light_pdf) * emitted;
+            }
+            let Some(scatter) = hit_rec.material.scatter(ray, &hit_rec, rng) else {
+                return glow + emitted;
+            };
+            let new_ray = Ray {
+                origin: hit_rec.p,
+                direction: scatter.direction,
+                time: ray.time,
+            };
+            let weight = scatter.weight(&hit_rec);
+            if self.light_sampling && !scatter.is_specular {
+                let wo = -ray.direction.normalize();
+                let direct = Self::sample_lights(world, &hit_rec, wo, ray.time, rng)
+                    + Self::sample_background(world, &hit_rec, wo, ray.time, rng);
+                let pdf = Some(scatter.pdf);
+                let indirect = self.ray_color(&new_ray, world, rng, depth - 1, pdf, distance);
+                emitted + direct + weight * indirect
+            } else {
+                let indirect = self.ray_color(&new_ray, world, rng, depth - 1, None, distance);
+                emitted + weight * indirect
             }
         } else {
-            let unit_direction = ray.direction.normalize();
-            let a = (unit_direction.y + 1.0) * 0.5;
-            (a) * BLUE + (1.0 - a) * WHITE
+            let radiance = world.background.radiance(ray);
+            match scatter_pdf {
+                // The background may also have been sampled directly from the last hit
+                Some(pdf) => power_heuristic(pdf, world.background.pdf(ray.direction)) * radiance,
+                None => radiance,
+            }
+        }
+    }
+
+    // Light arriving from emissive objects, found with a shadow ray towards a sampled point
+    // on one of them and weighted against the chance of the scattered ray finding it instead.
+    // time is that of the path, so moving lights are sampled where the shadow ray finds them.
+    fn sample_lights(
+        world: &World,
+        hit_rec: &HitRecord,
+        wo: Vec3,
+        time: f64,
+        rng: &mut StdRng,
+    ) -> Color {
+        let Some(direction) = world.sample_light(hit_rec.p, time, rng) else {
+            return BLACK;
+        };
+        let light_pdf = world.light_pdf(hit_rec.p, direction, time);
+        let wi = direction.normalize();
+        let scatter_pdf = hit_rec.material.pdf(hit_rec, wo, wi);
+        if light_pdf <= 0.0 || scatter_pdf <= 0.0 {
+            return BLACK;
+        }
+        // Whatever is hit first is what the shadow ray sees, even if it is another light
+        let shadow_ray = Ray {
+            origin: hit_rec.p,
+            direction,
+            time,
+        };
+        let mut light_rec = world.new_hitrecord();
+        if !world.hit(&shadow_ray, &Interval::new(0.001, 100000000000.0), &mut light_rec) {
+            return BLACK;
+        }
+        let bsdf = hit_rec.material.eval(hit_rec, wo, wi);
+        let cosine = hit_rec.material.cosine(hit_rec, wi);
+        let unblocked = Interval::new(0.001, light_rec.t);
+        let transmittance = world.transmittance(&shadow_ray, &unblocked, rng);
+        let weight = power_heuristic(light_pdf, scatter_pdf) * cosine * transmittance / light_pdf;
+        weight * bsdf * light_rec.material.emitted(&light_rec)
+    }
+
+    // Light arriving straight from the background, found with a shadow ray in a sampled
+    // direction and weighted against the chance of the scattered ray finding it instead
+    fn sample_background(
+        world: &World,
+        hit_rec: &HitRecord,
+        wo: Vec3,
+        time: f64,
+        rng: &mut StdRng,
+    ) -> Color {
+        let Some((direction, light_pdf)) = world.background.sample(rng) else {
+            return BLACK;
+        };
+        let scatter_pdf = hit_rec.material.pdf(hit_rec, wo, direction);
+        if scatter_pdf <= 0.0 {
+            return BLACK;
+        }
+        let shadow_ray = Ray {
+            origin: hit_rec.p,
+            direction,
+            time,
+        };
+        let mut blocker = world.new_hitrecord();
+        let search = Interval::new(0.001, 100000000000.0);
+        if world.hit(&shadow_ray, &search, &mut blocker) {
+            return BLACK;
         }
+        let bsdf = hit_rec.material.eval(hit_rec, wo, direction);
+        let cosine = hit_rec.material.cosine(hit_rec, direction);
+        let transmittance = world.transmittance(&shadow_ray, &search, rng);
+        let weight = power_heuristic(light_pdf, scatter_pdf) * cosine * transmittance / light_pdf;
+        weight * bsdf * world.background.radiance(&shadow_ray)
     }
 }
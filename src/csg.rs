@@ -0,0 +1,156 @@
+// csg.rs
+// Constructive solid geometry: solids made by combining two others, such as a lens from the
+// overlap of two spheres or a drilled part from a box with cylinders taken away
+use crate::aabb::Aabb;
+use crate::math::Interval;
+use crate::ray::Ray;
+use crate::shapes::{Crossing, HitRecord, Hittable, Shape, Span, surface_spans};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOperation {
+    Union,        // Inside either solid
+    Intersection, // Inside both
+    Difference,   // Inside the left solid but not the right
+}
+
+impl CsgOperation {
+    fn inside(self, left: bool, right: bool) -> bool {
+        match self {
+            CsgOperation::Union => left || right,
+            CsgOperation::Intersection => left && right,
+            CsgOperation::Difference => left && !right,
+        }
+    }
+}
+
+// Each operand is a closed solid, possibly in several parts such as a mesh split by material,
+// or another Csg. Only the surface of the result is hit, so where the operands overlap their
+// surfaces inside each other disappear.
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: Vec<Arc<Shape>>,
+    pub right: Vec<Arc<Shape>>,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, left: Vec<Arc<Shape>>, right: Vec<Arc<Shape>>) -> Self {
+        Csg {
+            operation,
+            left,
+            right,
+        }
+    }
+}
+
+// An operand's parts taken together as one solid
+struct Parts<'a>(&'a [Arc<Shape>]);
+
+impl Hittable for Parts<'_> {
+    fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        let mut closest = time.max;
+        let mut hit_anything = false;
+        for part in self.0 {
+            if part.hit(ray, &Interval::new(time.min, closest), hit_rec) {
+                closest = hit_rec.t;
+                hit_anything = true;
+            }
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.0
+            .iter()
+            .fold(Aabb::empty(), |b, part| b.union(&part.bounding_box()))
+    }
+
+    fn spans(&self, ray: &Ray, interval: &Interval, scratch: &mut HitRecord, spans: &mut Vec<Span>) {
+        match self.0 {
+            [part] => part.spans(ray, interval, scratch, spans),
+            _ => surface_spans(self, ray, interval, scratch, spans),
+        }
+    }
+}
+
+impl Hittable for Csg {
+    // The first boundary of the combined solid inside the interval. The operands' hits go to
+    // a scratch record, so a miss leaves hit_rec as it was.
+    fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        let mut spans = Vec::new();
+        let mut scratch = hit_rec.clone();
+        self.spans(ray, time, &mut scratch, &mut spans);
+        let first = spans
+            .iter()
+            .flat_map(|span| [span.enter, span.exit])
+            .find(|crossing| crossing.t.is_finite());
+        match first {
+            Some(crossing) => {
+                crossing.record(ray, hit_rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let left = Parts(&self.left).bounding_box();
+        let right = Parts(&self.right).bounding_box();
+        match self.operation {
+            CsgOperation::Union => left.union(&right),
+            CsgOperation::Intersection => left.intersect(&right),
+            CsgOperation::Difference => left,
+        }
+    }
+
+    // Walks both operands' spans in order, noting where being inside the result changes
+    fn spans(&self, ray: &Ray, interval: &Interval, scratch: &mut HitRecord, spans: &mut Vec<Span>) {
+        let mut left = Vec::new();
+        let mut right = Vec::new();
+        Parts(&self.left).spans(ray, interval, scratch, &mut left);
+        if left.is_empty() && self.operation != CsgOperation::Union {
+            return;
+        }
+        Parts(&self.right).spans(ray, interval, scratch, &mut right);
+
+        // Each event is a crossing, whether it is of the right operand, and whether it enters
+        let mut events: Vec<(Crossing, bool, bool)> = Vec::new();
+        for (operand, is_right) in [(&left, false), (&right, true)] {
+            for span in operand.iter() {
+                events.push((span.enter, is_right, true));
+                events.push((span.exit, is_right, false));
+            }
+        }
+        events.sort_by(|a, b| a.0.t.total_cmp(&b.0.t));
+
+        let (mut in_left, mut in_right) = (false, false);
+        let mut enter: Option<Crossing> = None;
+        for (crossing, is_right, entering) in events {
+            let was_inside = self.operation.inside(in_left, in_right);
+            if is_right {
+                in_right = entering;
+            } else {
+                in_left = entering;
+            }
+            let inside = self.operation.inside(in_left, in_right);
+            if inside == was_inside {
+                continue;
+            }
+            let crossing = if is_right && self.operation == CsgOperation::Difference {
+                crossing.flipped()
+            } else {
+                crossing
+            };
+            if inside {
+                enter = Some(crossing);
+            } else if let Some(entry) = enter.take() {
+                spans.push(Span {
+                    enter: entry,
+                    exit: crossing,
+                });
+            }
+        }
+    }
+}
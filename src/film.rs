@@ -0,0 +1,143 @@
+// film.rs
+// In-memory framebuffer that Camera::render fills with radiance samples. Post-processing
+// and image writing operate on the finished Film, so renders can be inspected or embedded
+// without going through a file.
+use crate::material::Color;
+use crate::output::{OutputFormat, write_image};
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FilmPixel {
+    pub radiance_sum: Color, // Sum of weighted radiance samples
+    pub weight_sum: f64,
+    pub sample_count: u32,
+}
+
+impl FilmPixel {
+    pub fn add_sample(&mut self, radiance: Color, weight: f64) {
+        self.radiance_sum = self.radiance_sum + weight * radiance;
+        self.weight_sum += weight;
+        self.sample_count += 1;
+    }
+
+    // Weighted average radiance, or black if the pixel has no samples
+    pub fn radiance(&self) -> Color {
+        if self.weight_sum > 0.0 {
+            self.radiance_sum / self.weight_sum
+        } else {
+            Color::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Film {
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<FilmPixel>, // Row by row from the top left
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Self {
+        Film {
+            width,
+            height,
+            pixels: vec![FilmPixel::default(); width as usize * height as usize],
+        }
+    }
+
+    pub fn pixel(&self, row: u32, col: u32) -> &FilmPixel {
+        &self.pixels[row as usize * self.width as usize + col as usize]
+    }
+
+    pub fn pixel_mut(&mut self, row: u32, col: u32) -> &mut FilmPixel {
+        &mut self.pixels[row as usize * self.width as usize + col as usize]
+    }
+
+    // Rows as mutable slices, so separate rows can be filled in parallel
+    pub fn rows_mut(&mut self) -> std::slice::ChunksMut<'_, FilmPixel> {
+        self.pixels.chunks_mut(self.width as usize)
+    }
+
+    // Average linear radiance of every pixel
+    pub fn linear(&self) -> Vec<Color> {
+        self.pixels.iter().map(FilmPixel::radiance).collect()
+    }
+
+    // Display-ready colours: linear radiance passed through a gamma curve
+    pub fn gamma_corrected(&self, gamma: f64) -> Vec<Color> {
+        self.pixels
+            .iter()
+            .map(|pixel| color_gamma_transform(pixel.radiance(), gamma))
+            .collect()
+    }
+
+    // High dynamic range formats store the linear averages; the rest are gamma corrected
+    pub fn write(&self, path: &Path, format: OutputFormat) -> io::Result<()> {
+        let pixels = if format.is_high_dynamic_range() {
+            self.linear()
+        } else {
+            self.gamma_corrected(2.0)
+        };
+        write_image(path, format, self.width, self.height, &pixels)
+    }
+}
+
+pub fn color_gamma_transform(color: Color, gamma: f64) -> Color {
+    Color::new(
+        linear_to_gamma(color.x, gamma),
+        linear_to_gamma(color.y, gamma),
+        linear_to_gamma(color.z, gamma),
+    )
+}
+
+// Transforms linear colour space to gamma
+fn linear_to_gamma(linear: f64, gamma: f64) -> f64 {
+    linear.powf(1.0 / gamma)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(found: Color, expected: Color) {
+        let d = found - expected;
+        assert!(d.dot(d) < 1e-24, "{:?} is not {:?}", found, expected);
+    }
+
+    #[test]
+    fn pixels_average_their_weighted_samples() {
+        let mut film = Film::new(3, 2);
+        let pixel = film.pixel_mut(1, 2);
+        pixel.add_sample(Color::new(1.0, 0.0, 0.5), 1.0);
+        pixel.add_sample(Color::new(0.0, 1.0, 0.5), 3.0);
+        assert_eq!(film.pixel(1, 2).sample_count, 2);
+        assert_close(film.pixel(1, 2).radiance(), Color::new(0.25, 0.75, 0.5));
+        // The pixel is the last of the second row, and untouched pixels stay black
+        let linear = film.linear();
+        assert_close(linear[5], Color::new(0.25, 0.75, 0.5));
+        assert!(linear[..5].iter().all(|color| color.dot(*color) == 0.0));
+    }
+
+    #[test]
+    fn rows_cover_the_film_in_order() {
+        let mut film = Film::new(2, 3);
+        for (row, pixels) in film.rows_mut().enumerate() {
+            for pixel in pixels {
+                pixel.add_sample(Color::new(row as f64, 0.0, 0.0), 1.0);
+            }
+        }
+        assert_close(film.pixel(2, 1).radiance(), Color::new(2.0, 0.0, 0.0));
+        assert_close(film.pixel(0, 0).radiance(), Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn gamma_correction_applies_to_the_average() {
+        let mut film = Film::new(1, 1);
+        film.pixel_mut(0, 0).add_sample(Color::new(0.0, 0.5, 1.0), 1.0);
+        film.pixel_mut(0, 0).add_sample(Color::new(0.5, 0.5, 1.0), 1.0);
+        let corrected = film.gamma_corrected(2.0);
+        assert_close(corrected[0], Color::new(0.5, 0.5f64.sqrt(), 1.0));
+    }
+}
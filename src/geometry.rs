@@ -1,15 +1,20 @@
 //geometry.rs
 use std::ops::Add;
 use std::ops::Div;
+use std::ops::Index;
+use std::ops::IndexMut;
 use std::ops::Mul;
 use std::ops::Neg;
 use std::ops::Sub;
 
 use rand::Rng;
-use rand::rngs::ThreadRng;
+use rand::rngs::StdRng;
+use serde::Deserialize;
 pub type Point3 = Vec3;
 
-#[derive(Debug, Clone, Copy)]
+// Scene files write vectors as [x, y, z]
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(from = "[f64; 3]")]
 pub struct Vec3{
     pub x: f64,
     pub y: f64,
@@ -27,6 +32,12 @@ impl Default for Vec3 {
     }
 }
 
+impl From<[f64; 3]> for Vec3 {
+    fn from([x, y, z]: [f64; 3]) -> Self {
+        Vec3 { x, y, z }
+    }
+}
+
 impl Add for Vec3 {
     type Output = Vec3;
     fn add(self, other: Vec3) -> Vec3 {
@@ -96,6 +107,30 @@ impl Sub for Vec3 {
     }
 }
 
+// Axis access: 0 = x, 1 = y, 2 = z
+impl Index<usize> for Vec3 {
+    type Output = f64;
+    fn index(&self, axis: usize) -> &f64 {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 axis {} out of range", axis),
+        }
+    }
+}
+
+impl IndexMut<usize> for Vec3 {
+    fn index_mut(&mut self, axis: usize) -> &mut f64 {
+        match axis {
+            0 => &mut self.x,
+            1 => &mut self.y,
+            2 => &mut self.z,
+            _ => panic!("Vec3 axis {} out of range", axis),
+        }
+    }
+}
+
 impl Vec3 {
     pub fn dot(self, other: Vec3) -> f64 {
         self.x * other.x + self.y * other.y + self.z * other.z
@@ -126,6 +161,15 @@ impl Vec3 {
         Vec3 { x, y, z }
     }
 
+    // Component-wise minimum and maximum
+    pub fn min(a: Vec3, b: Vec3) -> Vec3 {
+        Vec3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z))
+    }
+
+    pub fn max(a: Vec3, b: Vec3) -> Vec3 {
+        Vec3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z))
+    }
+
     // Reflects a vetor v about a UNIT LENGTH normal n
     pub fn reflect(v: Vec3, n: Vec3) -> Vec3 {
         v - 2.0 * v.dot(n) * n
@@ -142,8 +186,20 @@ impl Vec3 {
         perp_component + parallel_component
     }
 
+    // Two unit vectors completing the UNIT LENGTH vector n to an orthonormal basis, without
+    // branching on which axis n is closest to (Duff et al. 2017)
+    pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+        let sign = 1.0_f64.copysign(n.z);
+        let a = -1.0 / (sign + n.z);
+        let b = n.x * n.y * a;
+        (
+            Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+            Vec3::new(b, sign + n.y * n.y * a, -n.y),
+        )
+    }
+
     // Get a random vector in [-1,1] x [-1,1] x [-1,1]
-    pub fn random_vec(rng: &mut ThreadRng) -> Vec3 {
+    pub fn random_vec(rng: &mut StdRng) -> Vec3 {
         Vec3 {
             x: rng.gen_range(-1.0..1.0),
             y: rng.gen_range(-1.0..1.0),
@@ -152,10 +208,10 @@ impl Vec3 {
     }
 
     // Rejection sampling: TODO consider updating sampling method
-    pub fn sample_unit_vector(rng: &mut ThreadRng) -> Vec3 {
+    pub fn sample_unit_vector(rng: &mut StdRng) -> Vec3 {
         let mut vec = Self::random_vec(rng);
         let mut lensq = vec.dot(vec);
-        while lensq > 1.0 || lensq < 1e-100 {
+        while !(1e-100..=1.0).contains(&lensq) {
             vec = Self::random_vec(rng);
             lensq = vec.dot(vec)
         }
@@ -163,7 +219,7 @@ impl Vec3 {
     }
 
     // Gets a random vector in [-1,1] x [-1,1] 
-    pub fn sample_unit_disk(rng: &mut ThreadRng) -> Vec3 {
+    pub fn sample_unit_disk(rng: &mut StdRng) -> Vec3 {
         let mut vec = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
         while vec.dot(vec) > 1.0 {
             vec = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
@@ -0,0 +1,379 @@
+// image.rs
+// Images loaded from disk as linear floating point colours
+use crate::material::Color;
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>, // Row by row from the top left
+}
+
+impl Image {
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    // Decodes sRGB-encoded values, as stored by ordinary 8- and 16-bit images, to linear
+    pub fn srgb_to_linear(&mut self) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel = Color::new(
+                srgb_to_linear(pixel.x),
+                srgb_to_linear(pixel.y),
+                srgb_to_linear(pixel.z),
+            );
+        }
+    }
+}
+
+fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io { path: PathBuf, source: io::Error },
+    Format { path: PathBuf, message: String },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ImageError::Format { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl Error for ImageError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ImageError::Io { source, .. } => Some(source),
+            ImageError::Format { .. } => None,
+        }
+    }
+}
+
+// Loads a PNG, PPM or Radiance HDR file, chosen by extension. Values are returned as
+// stored; only HDR files hold linear radiance.
+pub fn load_image(path: &Path) -> Result<Image, ImageError> {
+    match extension(path).as_deref() {
+        Some("png") => load_png(path),
+        Some("ppm") => load_ppm(path),
+        Some("hdr") => load_hdr(path),
+        _ => Err(ImageError::Format {
+            path: path.to_path_buf(),
+            message: "unsupported image format; expected .png, .ppm or .hdr".to_string(),
+        }),
+    }
+}
+
+// Whether load_image reads path as linear radiance rather than display-encoded values
+pub fn is_high_dynamic_range(path: &Path) -> bool {
+    extension(path).as_deref() == Some("hdr")
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase)
+}
+
+// Reads a plain (P3) or binary (P6) PPM with any maximum value up to 65535
+pub fn load_ppm(path: &Path) -> Result<Image, ImageError> {
+    let data = std::fs::read(path).map_err(|source| ImageError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    read_ppm(&data).map_err(|message| ImageError::Format {
+        path: path.to_path_buf(),
+        message,
+    })
+}
+
+fn read_ppm(data: &[u8]) -> Result<Image, String> {
+    let mut position = 0;
+    let magic = ppm_token(data, &mut position).ok_or("empty file")?;
+    let binary = match magic {
+        b"P3" => false,
+        b"P6" => true,
+        _ => return Err("not a P3 or P6 PPM file".to_string()),
+    };
+    let mut header_number = |name: &str| -> Result<usize, String> {
+        let token = ppm_token(data, &mut position).ok_or(format!("missing {}", name))?;
+        std::str::from_utf8(token)
+            .ok()
+            .and_then(|t| t.parse().ok())
+            .ok_or(format!("invalid {} '{}'", name, String::from_utf8_lossy(token)))
+    };
+    let width = header_number("width")?;
+    let height = header_number("height")?;
+    let max_value = header_number("maximum value")?;
+    if !(1..=65535).contains(&max_value) {
+        return Err(format!("maximum value {} is out of range", max_value));
+    }
+
+    let count = width * height * 3;
+    let mut samples = Vec::with_capacity(count);
+    if binary {
+        // Exactly one whitespace byte separates the header from the raster
+        let start = position + 1;
+        let sample_size = if max_value < 256 { 1 } else { 2 };
+        let raster = data
+            .get(start..start + count * sample_size)
+            .ok_or("file ends before the last pixel")?;
+        samples.extend(raster.chunks_exact(sample_size).map(|bytes| match bytes {
+            [value] => *value as usize,
+            [high, low] => u16::from_be_bytes([*high, *low]) as usize,
+            _ => unreachable!(),
+        }));
+    } else {
+        for _ in 0..count {
+            samples.push(header_number("sample")?);
+        }
+    }
+    let pixels = samples
+        .chunks_exact(3)
+        .map(|rgb| {
+            let channel = |value: usize| value.min(max_value) as f64 / max_value as f64;
+            Color::new(channel(rgb[0]), channel(rgb[1]), channel(rgb[2]))
+        })
+        .collect();
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+// Next whitespace-separated token, skipping # comments
+fn ppm_token<'a>(data: &'a [u8], position: &mut usize) -> Option<&'a [u8]> {
+    loop {
+        while *position < data.len() && data[*position].is_ascii_whitespace() {
+            *position += 1;
+        }
+        if data.get(*position) != Some(&b'#') {
+            break;
+        }
+        while *position < data.len() && data[*position] != b'\n' {
+            *position += 1;
+        }
+    }
+    let start = *position;
+    while *position < data.len() && !data[*position].is_ascii_whitespace() {
+        *position += 1;
+    }
+    (*position > start).then(|| &data[start..*position])
+}
+
+// Reads an 8- or 16-bit PNG of any colour type. Values are scaled to [0, 1] as stored, and
+// any alpha channel is ignored.
+pub fn load_png(path: &Path) -> Result<Image, ImageError> {
+    let file = File::open(path).map_err(|source| ImageError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let format_error = |e: png::DecodingError| match e {
+        png::DecodingError::IoError(source) => ImageError::Io {
+            path: path.to_path_buf(),
+            source,
+        },
+        e => ImageError::Format {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        },
+    };
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    // Palettes become RGB and bit depths below 8 become 8
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(format_error)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut buffer).map_err(format_error)?;
+    let (color_type, bit_depth) = reader.output_color_type();
+
+    let channels = color_type.samples();
+    let sample_size = if bit_depth == png::BitDepth::Sixteen { 2 } else { 1 };
+    let max = if sample_size == 2 { 65535.0 } else { 255.0 };
+    let sample = |bytes: &[u8], channel: usize| -> f64 {
+        let i = channel * sample_size;
+        let value = if sample_size == 2 {
+            u16::from_be_bytes([bytes[i], bytes[i + 1]])
+        } else {
+            bytes[i] as u16
+        };
+        value as f64 / max
+    };
+    let pixels = buffer[..frame.buffer_size()]
+        .chunks_exact(channels * sample_size)
+        .map(|bytes| match color_type {
+            png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => {
+                let gray = sample(bytes, 0);
+                Color::new(gray, gray, gray)
+            }
+            _ => Color::new(sample(bytes, 0), sample(bytes, 1), sample(bytes, 2)),
+        })
+        .collect();
+    Ok(Image {
+        width: frame.width as usize,
+        height: frame.height as usize,
+        pixels,
+    })
+}
+
+// Reads a Radiance RGBE (.hdr) file, flat or run-length encoded
+pub fn load_hdr(path: &Path) -> Result<Image, ImageError> {
+    let file = File::open(path).map_err(|source| ImageError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    read_hdr(&mut BufReader::new(file)).map_err(|e| match e {
+        HdrError::Io(source) => ImageError::Io {
+            path: path.to_path_buf(),
+            source,
+        },
+        HdrError::Format(message) => ImageError::Format {
+            path: path.to_path_buf(),
+            message,
+        },
+    })
+}
+
+enum HdrError {
+    Io(io::Error),
+    Format(String),
+}
+
+impl From<io::Error> for HdrError {
+    fn from(e: io::Error) -> Self {
+        if e.kind() == io::ErrorKind::UnexpectedEof {
+            HdrError::Format("file ends before the last scanline".to_string())
+        } else {
+            HdrError::Io(e)
+        }
+    }
+}
+
+fn read_hdr<R: BufRead>(reader: &mut R) -> Result<Image, HdrError> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(HdrError::Format("not a Radiance HDR file".to_string()));
+    }
+    // Header variables end at the first blank line
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(HdrError::Format("header is not terminated".to_string()));
+        }
+        let variable = line.trim();
+        if variable.is_empty() {
+            break;
+        }
+        if let Some(format) = variable.strip_prefix("FORMAT=")
+            && format != "32-bit_rle_rgbe"
+        {
+            return Err(HdrError::Format(format!("unsupported pixel format '{}'", format)));
+        }
+    }
+
+    line.clear();
+    reader.read_line(&mut line)?;
+    let resolution: Vec<&str> = line.split_whitespace().collect();
+    let (height, width) = match resolution.as_slice() {
+        ["-Y", height, "+X", width] => match (height.parse(), width.parse()) {
+            (Ok(height), Ok(width)) => (height, width),
+            _ => return Err(HdrError::Format(format!("invalid resolution '{}'", line.trim()))),
+        },
+        _ => {
+            return Err(HdrError::Format(format!(
+                "unsupported orientation '{}', only '-Y <height> +X <width>' is read",
+                line.trim()
+            )));
+        }
+    };
+
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        read_scanline(reader, &mut scanline)?;
+        pixels.extend(scanline.iter().map(|rgbe| from_rgbe(*rgbe)));
+    }
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn read_scanline<R: Read>(reader: &mut R, scanline: &mut [[u8; 4]]) -> Result<(), HdrError> {
+    let width = scanline.len();
+    let mut first = [0u8; 4];
+    reader.read_exact(&mut first)?;
+    let run_length_encoded =
+        (8..=0x7fff).contains(&width) && first[0] == 2 && first[1] == 2 && first[2] & 0x80 == 0;
+    if !run_length_encoded {
+        scanline[0] = first;
+        for pixel in scanline[1..].iter_mut() {
+            reader.read_exact(pixel)?;
+        }
+        return Ok(());
+    }
+
+    if ((first[2] as usize) << 8 | first[3] as usize) != width {
+        return Err(HdrError::Format("scanline width mismatch".to_string()));
+    }
+    // Each channel is stored separately as a sequence of runs and literal spans
+    for channel in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut count = [0u8; 1];
+            reader.read_exact(&mut count)?;
+            let count = count[0] as usize;
+            let (length, is_run) = if count > 128 {
+                (count - 128, true)
+            } else {
+                (count, false)
+            };
+            if length == 0 || x + length > width {
+                return Err(HdrError::Format("corrupt run-length encoding".to_string()));
+            }
+            if is_run {
+                let mut value = [0u8; 1];
+                reader.read_exact(&mut value)?;
+                for pixel in scanline[x..x + length].iter_mut() {
+                    pixel[channel] = value[0];
+                }
+            } else {
+                let mut values = [0u8; 128];
+                reader.read_exact(&mut values[..length])?;
+                for (pixel, value) in scanline[x..x + length].iter_mut().zip(values) {
+                    pixel[channel] = value;
+                }
+            }
+            x += length;
+        }
+    }
+    Ok(())
+}
+
+// Mantissas are taken at the centre of their bucket, as Radiance itself does
+fn from_rgbe([r, g, b, e]: [u8; 4]) -> Color {
+    if e == 0 {
+        return Color::default();
+    }
+    let scale = 2f64.powi(e as i32 - (128 + 8));
+    Color::new(
+        (r as f64 + 0.5) * scale,
+        (g as f64 + 0.5) * scale,
+        (b as f64 + 0.5) * scale,
+    )
+}
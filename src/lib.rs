@@ -0,0 +1,27 @@
+// lib.rs
+// Renderer library: scene description, geometry and the camera that renders it
+pub mod aabb;
+pub mod background;
+pub mod bvh;
+pub mod camera;
+pub mod csg;
+pub mod film;
+pub mod geometry;
+pub mod image;
+pub mod material;
+pub mod math;
+pub mod medium;
+pub mod noise;
+pub mod obj;
+pub mod output;
+pub mod planar;
+pub mod quadric;
+pub mod ray;
+pub mod sampling;
+pub mod scene;
+pub mod sdf;
+pub mod shapes;
+pub mod texture;
+pub mod transform;
+pub mod triangle;
+pub mod volume;
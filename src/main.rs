@@ -1,21 +1,8 @@
-mod camera;
-mod geometry;
-mod material;
-mod math;
-mod ray;
-mod shapes;
-use crate::camera::Camera;
-use geometry::Point3;
-use material::Color;
-use material::Lambertian;
-use material::Metal;
-use material::Dielectric;
-use material::{GREEN, WHITE, PINK, RED, BLUE};
-use shapes::Shape;
-use shapes::Sphere;
-use shapes::World;
-use std::f64::consts::PI;
-use std::sync::Arc;
+use clap::Parser;
+use raytracer::output::OutputFormat;
+use raytracer::scene::load_scene;
+use std::path::PathBuf;
+use std::process::ExitCode;
 use std::time::Instant;
 
 /*
@@ -24,152 +11,115 @@ Rendering 'Shiny Metal' scene at 400x250, max_depth = 50 took 710s
 Without Rayon:      6.8s        710s
 Rayon:              1.1s        104.4s
 
+World::hit on 100k rays against N random spheres (cargo bench --bench bvh)
+# Spheres:          100         1000        10000
+Linear:             64ms        504ms       5.75s
+BVH:                20ms        70ms        219ms
+
+Cornell box at 48x48, RMSE against a 2048 spp reference (tests/light_sampling.rs checks this)
+# Samples:          4           16          64
+BSDF only:          0.437       0.232       0.122
+Light sampling:     0.237       0.108       0.059
 */
 
-fn main() {
-    // Image dimension calculations (width fixed)
-    let aspect_ratio: f64 = 8.0 / 5.0;
-    let image_width = 400;
-    let location = Point3::new(7.0,4.0,7.0);
-    let view_target = Point3::new(0.0,3.0,0.1);
-    let vfov = 46.0;
-    let focal_length = 10.0;
-    let focal_angle = 0.5; 
-    let samples = 40;
-    let max_depth = 50;
-    let camera = Camera::new(aspect_ratio, image_width, location, view_target, focal_length, focal_angle, vfov, samples, max_depth);
-
-    // Define world:
-    let mut world = World::new();
-
-    //initialize_materials(&mut world);
-    //add_objects(&mut world);
-    make_scene(&mut world);
+/// Renders a TOML scene file to an image.
+#[derive(Parser)]
+#[command(version)]
+struct Args {
+    /// Scene description file
+    #[arg(default_value = "scenes/default.toml")]
+    scene: PathBuf,
+
+    /// Image width in pixels; the height follows from the scene's aspect ratio
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    width: Option<u32>,
+
+    /// Samples per pixel
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    spp: Option<u32>,
+
+    /// Maximum number of bounces per path
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    max_depth: Option<i32>,
+
+    /// Output image path [default: output.<format>]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Number of render threads [default: one per core]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    threads: Option<u64>,
+
+    /// Seed for reproducible renders [default: random]
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Disable direct light sampling, leaving only BSDF-sampled paths
+    #[arg(long)]
+    no_light_sampling: bool,
+
+    /// Output format (ppm, png, png16, hdr, pfm, exr, exr32) [default: from the output extension, else ppm]
+    #[arg(long)]
+    format: Option<OutputFormat>,
+}
 
-    // Render with timer
-    let start = Instant::now();
-    match camera.render(&world) {
-        Ok(()) => {
-            println!("Finished rendering in {:?}", start.elapsed());
-        }
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
         Err(e) => {
-            println!("Failed to render scene: {e}");
+            eprintln!("Error: {e}");
+            ExitCode::FAILURE
         }
     }
 }
 
-fn initialize_materials(world: &mut World) {
-    let ground = Lambertian {
-        albedo: Color::new(0.8, 0.8, 0.0),
-    };
-    let center = Lambertian {
-        albedo: Color::new(0.1, 0.2, 0.5),
-    };
-    let left = Metal {
-        albedo: Color::new(0.8, 0.8, 0.8),
-        fuzz: 0.0
-    };
-    let right = Metal {
-        albedo: Color::new(0.8, 0.6, 0.2),
-        fuzz: 0.0
-    };
-    let glass = Dielectric {
-        refraction_index: 1.5
-    };
-    let air_in_water =  Dielectric {
-        refraction_index: 1.0/1.33
-    };
-    let air_in_glass = Dielectric {
-        refraction_index: 1.0 / 1.5
-    };
-    world.materials.push(Arc::new(ground));
-    world.materials.push(Arc::new(center));
-    world.materials.push(Arc::new(left));
-    world.materials.push(Arc::new(right));
-    world.materials.push(Arc::new(glass));
-    world.materials.push(Arc::new(air_in_water));
-    world.materials.push(Arc::new(air_in_glass));
+fn run(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let (output, format) = output_path_and_format(args)?;
 
-}
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads as usize)
+            .build_global()?;
+    }
+
+    let mut scene = load_scene(&args.scene)?;
+    if let Some(width) = args.width {
+        scene.camera.image_width = width;
+    }
+    if let Some(spp) = args.spp {
+        scene.camera.samples = spp;
+    }
+    if let Some(max_depth) = args.max_depth {
+        scene.camera.max_depth = max_depth;
+    }
+    let mut camera = scene.camera.build();
+    camera.seed = args.seed;
+    camera.light_sampling = !args.no_light_sampling;
 
-fn add_objects(world: &mut World) {
-    let sphere_center = Point3::new(0.0, 0.0, -1.2);
-    let radius = 0.5;
-    let sphere = Sphere {
-        label: String::from("Sph1"),
-        center: sphere_center,
-        radius,
-    };
-    world.objects.push((Shape::Sphere(sphere), 2));
-    world.objects.push((Shape::Sphere(Sphere {
-        label: String::from("ground"),
-        center: Point3::new(0.0, -100.5, 0.0),
-        radius: 100.0,
-    }), 1));
-    world.objects.push((Shape::Sphere(Sphere {
-        label: String::from("left"),
-        center: Point3::new(-1.0, 0.0, -1.0),
-        radius: 0.5,
-    }), 3));
-    world.objects.push((Shape::Sphere(Sphere {
-        label: String::from("right"),
-        center: Point3::new(1.0, 0.0, -1.0),
-        radius: 0.5,
-    }), 5));
-    
-    world.objects.push((Shape::Sphere(Sphere {
-        label: String::from("interior"),
-        center: Point3::new(1.0, 0.0, -1.0),
-        radius: 0.1,
-    }), 3));
-    
+    // Render with timer
+    let start = Instant::now();
+    let film = camera.render(&scene.world);
+    println!("Finished rendering in {:?}", start.elapsed());
+    film.write(&output, format)
+        .map_err(|e| format!("failed to write {}: {e}", output.display()))?;
+    println!("Wrote {}", output.display());
+    Ok(())
 }
 
-fn make_scene(world: &mut World) {
-    let rng = rand::thread_rng();
-    let ground = Lambertian {
-        albedo: Color::new(0.5, 0.5, 0.5),
-    };
-    let glass = Dielectric {
-        refraction_index: 1.5
-    };
-    let metal = Metal {
-        albedo: Color::new(0.9,0.9,0.9),
-        fuzz: 0.0
-    };
-    world.add_material(ground);
-    world.add_material(glass);
-    world.add_material(metal);
-
-    world.add_material(Lambertian {albedo: GREEN});
-    world.add_material(Lambertian {albedo: RED});
-    world.add_material(Lambertian {albedo: PINK});
-
-    /* 
-    let ground_sphere = Sphere::new(0.0, -1000.0, 0.0, 1000.0);
-    world.objects.push((Shape::Sphere(ground_sphere), 1));
-
-    let central_sphere = Sphere::new(0.0, 1.0, 0.0, 1.0);
-    world.objects.push((Shape::Sphere(central_sphere), 5));
-    
-    let s1 = Sphere::new(1.7, 0.7, 0.0, 0.7);
-    world.objects.push((Shape::Sphere(s1), 2));
-
-    let s2 = Sphere::new(2.8, 0.4, 0.0, 0.4);
-    world.objects.push((Shape::Sphere(s2), 4));
-
-    let s3 = Sphere::new(-5.0, 4.0, 0.0, 4.0);
-    world.objects.push((Shape::Sphere(s3), 3));
-
-    let big_sphere = Sphere::new(2.0, 10.0, -13.0, 10.0);
-    world.objects.push((Shape::Sphere(big_sphere), 3));
-
-    let big_sphere = Sphere::new(100.0, 100.0, 30.0, 100.0);
-    world.objects.push((Shape::Sphere(big_sphere), 6));
-    */
-    let ground_sphere = Sphere::new(0.0, -1000.0, 0.0, 1000.0);
-    world.objects.push((Shape::Sphere(ground_sphere), 1));
-
-    let central_sphere = Sphere::new(0.0, 1.0, 0.0, 1.0);
-    world.objects.push((Shape::Sphere(central_sphere), 5));
-}
\ No newline at end of file
+fn output_path_and_format(args: &Args) -> Result<(PathBuf, OutputFormat), String> {
+    match (&args.output, args.format) {
+        (Some(output), Some(format)) => Ok((output.clone(), format)),
+        (Some(output), None) => match OutputFormat::from_path(output) {
+            Some(format) => Ok((output.clone(), format)),
+            None => Err(format!(
+                "cannot tell the image format of {}; give --format or a known extension",
+                output.display()
+            )),
+        },
+        (None, format) => {
+            let format = format.unwrap_or(OutputFormat::Ppm);
+            Ok((PathBuf::from(format!("output.{}", format.extension())), format))
+        }
+    }
+}
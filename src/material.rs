@@ -1,11 +1,15 @@
-use rand::rngs::ThreadRng;
+use rand::rngs::StdRng;
 use rand::Rng;
 
 // material.rs
 use crate::geometry::Vec3;
 use crate::ray::Ray;
 use crate::shapes::HitRecord;
+use crate::texture::Texture;
+use serde::Deserialize;
+use std::f64::consts::PI;
 use std::fmt::Debug;
+use std::sync::Arc;
 pub type Color = Vec3;
 pub const BLUE: Color = Color {
     x: 0.5,
@@ -40,13 +44,76 @@ pub const PINK: Color = Color {
     z: 227.0 / 255.0,
 };
 
+// A direction sampled by Material::scatter. Directions here and in eval and pdf are unit
+// vectors pointing away from the surface: wo back along the incoming ray, wi towards where
+// light arrives from.
+#[derive(Debug, Clone, Copy)]
+pub struct ScatterRecord {
+    pub direction: Vec3, // wi
+    // BSDF value f(wo, wi), or for specular bounces the whole weight the path is multiplied by
+    pub value: Color,
+    pub pdf: f64, // Solid angle density of direction; meaningless for specular bounces
+    // Perfect mirrors and refraction: eval and pdf are zero for every direction, so lights
+    // cannot be sampled and value is used as is
+    pub is_specular: bool,
+}
+
+impl ScatterRecord {
+    pub fn specular(direction: Vec3, attenuation: Color) -> Self {
+        ScatterRecord {
+            direction,
+            value: attenuation,
+            pdf: 0.0,
+            is_specular: true,
+        }
+    }
+
+    // Path throughput of the sample: f cos(theta) / pdf, or value for specular bounces
+    pub fn weight(&self, hit_rec: &HitRecord) -> Color {
+        if self.is_specular {
+            self.value
+        } else if self.pdf > 0.0 {
+            (hit_rec.material.cosine(hit_rec, self.direction) / self.pdf) * self.value
+        } else {
+            BLACK
+        }
+    }
+}
+
 pub trait Material: Debug + Sync + Send {
+    // Samples the direction light arrives from, or None if the path is absorbed
     fn scatter(
         &self,
         ray_in: &Ray,
         hit_rec: &HitRecord,
-        rng: &mut ThreadRng,
-    ) -> Option<(Color, Ray)>;
+        rng: &mut StdRng,
+    ) -> Option<ScatterRecord>;
+
+    // BSDF value f(wo, wi), without the cosine factor
+    fn eval(&self, _hit_rec: &HitRecord, _wo: Vec3, _wi: Vec3) -> Color {
+        BLACK
+    }
+
+    // Density per unit solid angle with which scatter picks wi
+    fn pdf(&self, _hit_rec: &HitRecord, _wo: Vec3, _wi: Vec3) -> f64 {
+        0.0
+    }
+
+    // Radiance given off at the hit point, added to whatever is scattered
+    fn emitted(&self, _hit_rec: &HitRecord) -> Color {
+        BLACK
+    }
+
+    // Whether emitted can be non-zero; emissive objects are sampled directly as lights
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    // Foreshortening of light arriving from wi, |cos theta| at a surface. Media have no
+    // surface to tilt away, so for their phase functions it is 1.
+    fn cosine(&self, hit_rec: &HitRecord, wi: Vec3) -> f64 {
+        hit_rec.normal.dot(wi).abs()
+    }
 }
 
 #[derive(Debug)]
@@ -55,17 +122,17 @@ pub struct DefaultMaterial {}
 impl Material for DefaultMaterial {
     fn scatter(
         &self,
-        ray_in: &Ray,
+        _ray_in: &Ray,
         hit_rec: &HitRecord,
-        rng: &mut ThreadRng,
-    ) -> Option<(Color, Ray)> {
+        _rng: &mut StdRng,
+    ) -> Option<ScatterRecord> {
         panic!("No material assigned for {:?}", hit_rec)
     }
 }
 
 #[derive(Debug)]
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Arc<dyn Texture>,
 }
 
 impl Material for Lambertian {
@@ -73,47 +140,284 @@ impl Material for Lambertian {
         &self,
         ray_in: &Ray,
         hit_rec: &HitRecord,
-        rng: &mut ThreadRng,
-    ) -> Option<(Color, Ray)> {
+        rng: &mut StdRng,
+    ) -> Option<ScatterRecord> {
+        // Normal plus a random unit vector is cosine distributed about the normal
         let mut scatter_direction = hit_rec.normal + Vec3::sample_unit_vector(rng);
         if Vec3::too_small(scatter_direction) {
             scatter_direction = hit_rec.normal;
         }
-        let out_ray = Ray {
-            direction: scatter_direction,
-            origin: hit_rec.p,
+        let wo = -ray_in.direction.normalize();
+        let wi = scatter_direction.normalize();
+        Some(ScatterRecord {
+            direction: wi,
+            value: self.eval(hit_rec, wo, wi),
+            pdf: self.pdf(hit_rec, wo, wi),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, hit_rec: &HitRecord, _wo: Vec3, wi: Vec3) -> Color {
+        if hit_rec.normal.dot(wi) > 0.0 {
+            self.albedo.value(hit_rec.uv, hit_rec.p, hit_rec.uv_footprint()) / PI
+        } else {
+            BLACK
+        }
+    }
+
+    fn pdf(&self, hit_rec: &HitRecord, _wo: Vec3, wi: Vec3) -> f64 {
+        hit_rec.normal.dot(wi).max(0.0) / PI
+    }
+}
+
+// How much light a conductor reflects at each angle of incidence
+#[derive(Debug)]
+pub enum ConductorFresnel {
+    // The exact Fresnel equations for a complex index of refraction eta + i k per channel,
+    // as measured for real metals, with the outside taken to be air
+    Complex { eta: Color, k: Color },
+    // Schlick's approximation from the colour reflected at normal incidence, for metals
+    // described by their look rather than measured constants
+    Schlick(Arc<dyn Texture>),
+}
+
+// Complex indices of refraction of common metals, fitted to the red, green and blue
+// channels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConductorPreset {
+    Gold,
+    Copper,
+    Aluminium,
+}
+
+impl ConductorPreset {
+    pub fn fresnel(self) -> ConductorFresnel {
+        let (eta, k) = match self {
+            ConductorPreset::Gold => {
+                (Color::new(0.143, 0.374, 1.442), Color::new(3.983, 2.385, 1.603))
+            }
+            ConductorPreset::Copper => {
+                (Color::new(0.200, 0.924, 1.102), Color::new(3.912, 2.452, 2.142))
+            }
+            ConductorPreset::Aluminium => {
+                (Color::new(1.657, 0.880, 0.521), Color::new(9.224, 6.270, 4.837))
+            }
         };
-        Some((self.albedo, out_ray))
+        ConductorFresnel::Complex { eta, k }
     }
 }
 
+// Fresnel reflectance of a conductor for one channel, from the closed form of the complex
+// Fresnel equations averaged over both polarisations
+fn fresnel_conductor(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let t2 = 2.0 * cos2.sqrt() * a;
+    let r_s = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let r_p = r_s * (t3 - t4) / (t3 + t4);
+    0.5 * (r_s + r_p)
+}
+
+// Roughness below which a conductor is treated as a perfect mirror
+const MIRROR_ROUGHNESS: f64 = 1e-3;
+
+// Rough metal: a GGX (Trowbridge-Reitz) microfacet BRDF with Smith masking and shadowing
+// between facets. Directions are sampled from the distribution of facet normals visible from
+// wo, so the weight of a sample is close to the Fresnel term. Light scattered more than once
+// between facets is not followed, which darkens very rough metals slightly.
+//
+// Roughness is the square root of the GGX alpha. roughness_u applies along tangent, projected
+// onto the surface, and roughness_v across it; tangent is a direction in world space, such as
+// the axis a part was brushed along.
 #[derive(Debug)]
-pub struct Metal {
-    pub albedo: Color,
-    pub fuzz: f64,
+pub struct Conductor {
+    pub fresnel: ConductorFresnel,
+    pub roughness_u: Arc<dyn Texture>,
+    pub roughness_v: Arc<dyn Texture>,
+    pub tangent: Vec3,
+}
+
+impl Conductor {
+    // An isotropic conductor
+    pub fn new(fresnel: ConductorFresnel, roughness: Arc<dyn Texture>) -> Self {
+        Conductor {
+            fresnel,
+            roughness_u: Arc::clone(&roughness),
+            roughness_v: roughness,
+            tangent: Vec3::new(1.0, 0.0, 0.0),
+        }
+    }
+
+    // Unit vectors along tangent, across it and along the shading normal
+    fn frame(&self, hit_rec: &HitRecord) -> (Vec3, Vec3, Vec3) {
+        let n = hit_rec.normal;
+        let along = self.tangent - self.tangent.dot(n) * n;
+        let x = if along.dot(along) < 1e-16 {
+            Vec3::orthonormal_basis(n).0
+        } else {
+            along.normalize()
+        };
+        (x, n.cross(x), n)
+    }
+
+    // GGX alphas along and across the tangent, or None for a mirror
+    fn alphas(&self, hit_rec: &HitRecord) -> Option<(f64, f64)> {
+        let footprint = hit_rec.uv_footprint();
+        let u = self.roughness_u.scalar(hit_rec.uv, hit_rec.p, footprint).max(0.0);
+        let v = self.roughness_v.scalar(hit_rec.uv, hit_rec.p, footprint).max(0.0);
+        if u.max(v) < MIRROR_ROUGHNESS {
+            return None;
+        }
+        let u = u.max(MIRROR_ROUGHNESS);
+        let v = v.max(MIRROR_ROUGHNESS);
+        Some((u * u, v * v))
+    }
+
+    fn reflectance(&self, hit_rec: &HitRecord, cos_i: f64) -> Color {
+        match &self.fresnel {
+            ConductorFresnel::Complex { eta, k } => Color::new(
+                fresnel_conductor(cos_i, eta.x, k.x),
+                fresnel_conductor(cos_i, eta.y, k.y),
+                fresnel_conductor(cos_i, eta.z, k.z),
+            ),
+            ConductorFresnel::Schlick(color) => {
+                let f0 = color.value(hit_rec.uv, hit_rec.p, hit_rec.uv_footprint());
+                let weight = (1.0 - cos_i.clamp(0.0, 1.0)).powi(5);
+                f0 + weight * (WHITE - f0)
+            }
+        }
+    }
+}
+
+// Direction w in the frame (x, y, z), and back
+fn to_local(w: Vec3, (x, y, z): (Vec3, Vec3, Vec3)) -> Vec3 {
+    Vec3::new(w.dot(x), w.dot(y), w.dot(z))
+}
+
+fn to_world(w: Vec3, (x, y, z): (Vec3, Vec3, Vec3)) -> Vec3 {
+    w.x * x + w.y * y + w.z * z
+}
+
+// GGX density of facet normals h, in the local frame, per unit solid angle of h projected
+// onto the surface
+fn ggx_distribution(h: Vec3, (alpha_x, alpha_y): (f64, f64)) -> f64 {
+    let (x, y) = (h.x / alpha_x, h.y / alpha_y);
+    let denominator = x * x + y * y + h.z * h.z;
+    1.0 / (PI * alpha_x * alpha_y * denominator * denominator)
+}
+
+// Smith's auxiliary function: the masked area of facets seen from w, relative to the area
+// seen
+fn ggx_lambda(w: Vec3, (alpha_x, alpha_y): (f64, f64)) -> f64 {
+    let tan2 = ((alpha_x * w.x).powi(2) + (alpha_y * w.y).powi(2)) / (w.z * w.z);
+    0.5 * ((1.0 + tan2).sqrt() - 1.0)
+}
+
+// Fraction of facets facing w that w sees over the others
+fn ggx_masking(w: Vec3, alphas: (f64, f64)) -> f64 {
+    1.0 / (1.0 + ggx_lambda(w, alphas))
+}
+
+// Samples a facet normal visible from wo, in proportion to how much of it wo sees (Heitz,
+// "Sampling the GGX Distribution of Visible Normals", 2018)
+fn sample_visible_normal(wo: Vec3, (alpha_x, alpha_y): (f64, f64), rng: &mut StdRng) -> Vec3 {
+    // Stretched so that the facets form a hemisphere
+    let v = Vec3::new(alpha_x * wo.x, alpha_y * wo.y, wo.z).normalize();
+    let length2 = v.x * v.x + v.y * v.y;
+    let t1 = if length2 > 0.0 {
+        Vec3::new(-v.y, v.x, 0.0) / length2.sqrt()
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t2 = v.cross(t1);
+    // A point on the disk seen along v, squeezed to the part of the hemisphere facing v
+    let r = rng.r#gen::<f64>().sqrt();
+    let phi = 2.0 * PI * rng.r#gen::<f64>();
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + v.z);
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+    let h = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+    Vec3::new(alpha_x * h.x, alpha_y * h.y, h.z.max(0.0)).normalize()
 }
 
-impl Material for Metal {
+impl Material for Conductor {
     fn scatter(
         &self,
         ray_in: &Ray,
         hit_rec: &HitRecord,
-        rng: &mut ThreadRng,
-    ) -> Option<(Color, Ray)> {
-        let mut reflected = Vec3::reflect(ray_in.direction, hit_rec.normal);
-        reflected = Vec3::normalize(reflected) + self.fuzz * Vec3::sample_unit_vector(rng);
-        if reflected.dot(hit_rec.normal) > 0.0 {
-            Some((
-                self.albedo,
-                Ray {
-                    origin: hit_rec.p,
-                    direction: reflected,
-                },
-            ))
-        } else {
-            // Reflected ray is absorbed by metal, no ray out and color = black
-            None
+        rng: &mut StdRng,
+    ) -> Option<ScatterRecord> {
+        let wo = -ray_in.direction.normalize();
+        let Some(alphas) = self.alphas(hit_rec) else {
+            let wi = Vec3::reflect(-wo, hit_rec.normal).normalize();
+            let cos_i = hit_rec.normal.dot(wo);
+            return Some(ScatterRecord::specular(wi, self.reflectance(hit_rec, cos_i)));
+        };
+        let frame = self.frame(hit_rec);
+        let local_wo = to_local(wo, frame);
+        if local_wo.z <= 0.0 {
+            return None;
+        }
+        let h = sample_visible_normal(local_wo, alphas, rng);
+        let local_wi = 2.0 * local_wo.dot(h) * h - local_wo;
+        // Reflected off a facet to below the surface, a path that would need to bounce
+        // between facets to get out
+        if local_wi.z <= 0.0 {
+            return None;
+        }
+        let wi = to_world(local_wi, frame);
+        Some(ScatterRecord {
+            direction: wi,
+            value: self.eval(hit_rec, wo, wi),
+            pdf: self.pdf(hit_rec, wo, wi),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, hit_rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        let Some(alphas) = self.alphas(hit_rec) else {
+            return BLACK;
+        };
+        let frame = self.frame(hit_rec);
+        let (wo, wi) = (to_local(wo, frame), to_local(wi, frame));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return BLACK;
+        }
+        let h = wo + wi;
+        if h.dot(h) < 1e-16 {
+            return BLACK;
         }
+        let h = h.normalize();
+        // Masking and shadowing together, correlated by facet height
+        let shadowing = 1.0 / (1.0 + ggx_lambda(wo, alphas) + ggx_lambda(wi, alphas));
+        let d = ggx_distribution(h, alphas);
+        (d * shadowing / (4.0 * wo.z * wi.z)) * self.reflectance(hit_rec, wo.dot(h))
+    }
+
+    // The density of visible normals, D(h) G1(wo) (wo . h) / cos(wo), over the 4 (wo . h)
+    // by which reflection spreads facet normals out over directions
+    fn pdf(&self, hit_rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        let Some(alphas) = self.alphas(hit_rec) else {
+            return 0.0;
+        };
+        let frame = self.frame(hit_rec);
+        let (wo, wi) = (to_local(wo, frame), to_local(wi, frame));
+        if wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let h = wo + wi;
+        if h.dot(h) < 1e-16 {
+            return 0.0;
+        }
+        let h = h.normalize();
+        ggx_distribution(h, alphas) * ggx_masking(wo, alphas) / (4.0 * wo.z)
     }
 }
 
@@ -127,8 +431,8 @@ impl Material for Dielectric {
         &self,
         ray_in: &Ray,
         hit_rec: &HitRecord,
-        rng: &mut ThreadRng,
-    ) -> Option<(Color, Ray)> {
+        rng: &mut StdRng,
+    ) -> Option<ScatterRecord> {
         let refract = if hit_rec.front_face {
             1.0 / self.refraction_index
         } else {
@@ -146,12 +450,9 @@ impl Material for Dielectric {
             Vec3::refract(in_direction, hit_rec.normal, refract)
         };
 
-        Some((
+        Some(ScatterRecord::specular(
+            out_direction.normalize(),
             Color::new(1.0, 1.0, 1.0),
-            Ray {
-                origin: hit_rec.p,
-                direction: out_direction,
-            },
         ))
     }
 }
@@ -162,4 +463,134 @@ impl Dielectric {
         let r1 = r0 * r0;
         r1 + (1.0 - r1) * (1.0 - cosine).powi(5)
     }
-}
\ No newline at end of file
+}
+// Area light: any shape with this material glows uniformly from its front (outward) face
+#[derive(Debug)]
+pub struct DiffuseLight {
+    pub emit: Color,
+}
+
+impl Material for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _hit_rec: &HitRecord,
+        _rng: &mut StdRng,
+    ) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+
+    fn emitted(&self, hit_rec: &HitRecord) -> Color {
+        if hit_rec.front_face {
+            self.emit
+        } else {
+            BLACK
+        }
+    }
+}
+
+// Phase function of a medium that scatters equally in all directions. albedo is the fraction
+// of light scattered rather than absorbed at each interaction.
+#[derive(Debug)]
+pub struct Isotropic {
+    pub albedo: Arc<dyn Texture>,
+}
+
+impl Material for Isotropic {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        hit_rec: &HitRecord,
+        rng: &mut StdRng,
+    ) -> Option<ScatterRecord> {
+        let wi = Vec3::sample_unit_vector(rng);
+        Some(ScatterRecord {
+            direction: wi,
+            value: self.eval(hit_rec, wi, wi),
+            pdf: 1.0 / (4.0 * PI),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, hit_rec: &HitRecord, _wo: Vec3, _wi: Vec3) -> Color {
+        self.albedo.value(hit_rec.uv, hit_rec.p, 0.0) / (4.0 * PI)
+    }
+
+    fn pdf(&self, _hit_rec: &HitRecord, _wo: Vec3, _wi: Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn cosine(&self, _hit_rec: &HitRecord, _wi: Vec3) -> f64 {
+        1.0
+    }
+}
+
+// Henyey-Greenstein phase function: g in (-1, 1) is the mean cosine of the scattering angle,
+// positive for media such as fog and cloud that mostly scatter light onwards, negative for
+// ones that scatter it back, and zero for isotropic scattering. Sampling follows the phase
+// function exactly.
+#[derive(Debug)]
+pub struct HenyeyGreenstein {
+    pub albedo: Arc<dyn Texture>,
+    pub g: f64,
+}
+
+impl HenyeyGreenstein {
+    // Density of scattering by an angle with the given cosine from the direction of travel
+    fn phase(&self, cos_theta: f64) -> f64 {
+        let g = self.g.clamp(-0.999, 0.999);
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.max(1e-12).sqrt())
+    }
+}
+
+impl Material for HenyeyGreenstein {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        hit_rec: &HitRecord,
+        rng: &mut StdRng,
+    ) -> Option<ScatterRecord> {
+        let g = self.g.clamp(-0.999, 0.999);
+        let u = rng.r#gen::<f64>();
+        // Inverting the cumulative distribution of the cosine
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.r#gen::<f64>();
+        // Light scattered onwards towards the viewer arrives from beyond the point, so wi is
+        // taken about the direction the ray travels
+        let forward = ray_in.direction.normalize();
+        let (u_axis, v_axis) = Vec3::orthonormal_basis(forward);
+        let wi = sin_theta * phi.cos() * u_axis + sin_theta * phi.sin() * v_axis
+            + cos_theta * forward;
+        let wo = -forward;
+        Some(ScatterRecord {
+            direction: wi,
+            value: self.eval(hit_rec, wo, wi),
+            pdf: self.pdf(hit_rec, wo, wi),
+            is_specular: false,
+        })
+    }
+
+    fn eval(&self, hit_rec: &HitRecord, wo: Vec3, wi: Vec3) -> Color {
+        self.pdf(hit_rec, wo, wi) * self.albedo.value(hit_rec.uv, hit_rec.p, 0.0)
+    }
+
+    // Light travels along -wi before scattering towards wo
+    fn pdf(&self, _hit_rec: &HitRecord, wo: Vec3, wi: Vec3) -> f64 {
+        self.phase((-wi).dot(wo))
+    }
+
+    fn cosine(&self, _hit_rec: &HitRecord, _wi: Vec3) -> f64 {
+        1.0
+    }
+}
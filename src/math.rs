@@ -23,3 +23,104 @@ impl Interval {
         Interval {min, max}
     }
 }
+
+// Real roots of a t^2 + b t + c = 0 in increasing order; a linear equation (a = 0) gives its
+// single root twice. Computing one root from the other avoids cancellation when b^2 >> ac.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    if a == 0.0 {
+        if b == 0.0 {
+            return None;
+        }
+        return Some((-c / b, -c / b));
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let q = -0.5 * (b + discriminant.sqrt().copysign(b));
+    if q == 0.0 {
+        return Some((0.0, 0.0));
+    }
+    let (t0, t1) = (q / a, c / q);
+    Some((t0.min(t1), t0.max(t1)))
+}
+
+// Real roots of t^4 + b t^3 + c t^2 + d t + e = 0 in increasing order, by Ferrari's method.
+// The closed form loses precision when roots lie close together, so each root is polished
+// with a few Newton steps on the original polynomial.
+pub fn solve_quartic(b: f64, c: f64, d: f64, e: f64) -> Vec<f64> {
+    // Depressed quartic y^4 + p y^2 + q y + r = 0, where t = y - b / 4
+    let b2 = b * b;
+    let p = c - 3.0 * b2 / 8.0;
+    let q = d - b * c / 2.0 + b2 * b / 8.0;
+    let r = e - b * d / 4.0 + b2 * c / 16.0 - 3.0 * b2 * b2 / 256.0;
+
+    let mut roots = Vec::with_capacity(4);
+    let mut push_quadratic = |c1: f64, c0: f64| {
+        if let Some((y0, y1)) = solve_quadratic(1.0, c1, c0) {
+            roots.push(y0);
+            roots.push(y1);
+        }
+    };
+    if q.abs() < 1e-12 {
+        // Biquadratic: a quadratic in y^2
+        if let Some((z0, z1)) = solve_quadratic(1.0, p, r) {
+            for z in [z0, z1] {
+                if z >= 0.0 {
+                    push_quadratic(0.0, -z);
+                }
+            }
+        }
+    } else {
+        // (y^2 + p/2 + m)^2 = 2m y^2 - q y + m^2 + m p + p^2/4 - r, and for a root m > 0 of
+        // the resolvent cubic the right side is the square (s y - q / 2s)^2 with s^2 = 2m
+        let m = largest_cubic_root(p, p * p / 4.0 - r, -q * q / 8.0);
+        if m > 0.0 {
+            let s = (2.0 * m).sqrt();
+            push_quadratic(-s, p / 2.0 + m + q / (2.0 * s));
+            push_quadratic(s, p / 2.0 + m - q / (2.0 * s));
+        }
+    }
+
+    let polynomial = |t: f64| (((t + b) * t + c) * t + d) * t + e;
+    let derivative = |t: f64| ((4.0 * t + 3.0 * b) * t + 2.0 * c) * t + d;
+    let mut roots: Vec<f64> = roots
+        .into_iter()
+        .map(|y| {
+            let mut t = y - b / 4.0;
+            for _ in 0..3 {
+                let slope = derivative(t);
+                if slope == 0.0 {
+                    break;
+                }
+                t -= polynomial(t) / slope;
+            }
+            t
+        })
+        .collect();
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+// Largest real root of m^3 + a m^2 + b m + c = 0, by Cardano's formula or, when there are
+// three real roots, the trigonometric form
+fn largest_cubic_root(a: f64, b: f64, c: f64) -> f64 {
+    // Depressed cubic w^3 + p w + q = 0, where m = w - a / 3
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a * a * a / 27.0 - a * b / 3.0 + c;
+    let discriminant = q * q / 4.0 + p * p * p / 27.0;
+    let w = if discriminant >= 0.0 {
+        let root = discriminant.sqrt();
+        (-q / 2.0 + root).cbrt() + (-q / 2.0 - root).cbrt()
+    } else {
+        let radius = (-p / 3.0).sqrt();
+        let cosine = (-q / (2.0 * radius * radius * radius)).clamp(-1.0, 1.0);
+        2.0 * radius * (cosine.acos() / 3.0).cos()
+    };
+    let mut m = w - a / 3.0;
+    let slope = (3.0 * m + 2.0 * a) * m + b;
+    if slope != 0.0 {
+        m -= (((m + a) * m + b) * m + c) / slope;
+    }
+    m
+}
@@ -0,0 +1,156 @@
+// medium.rs
+// Participating media: fog, smoke and other volumes that scatter light throughout their
+// inside rather than at a surface
+use crate::material::Color;
+use crate::math::Interval;
+use crate::ray::Ray;
+use crate::shapes::{HitRecord, Hittable, Shape};
+use crate::volume::GridMedium;
+use rand::Rng;
+use rand::rngs::StdRng;
+use std::sync::Arc;
+
+// Distance moved past each boundary crossing before looking for the next one
+const CROSSING_GAP: f64 = 1e-4;
+// Crossings followed along one ray, against boundaries that are not properly closed
+const MAX_CROSSINGS: usize = 64;
+
+// What happens when light interacts with a medium is up to the medium's material, a phase
+// function such as Isotropic
+pub enum Medium {
+    Constant(ConstantMedium),
+    Grid(GridMedium),
+}
+
+impl Medium {
+    // Ray parameter at which light travelling along the ray through interval first interacts
+    // with the medium, or None if it gets through. Light the medium gives off on the way is
+    // pushed to glow with the ray parameter it was estimated at, since only what comes before
+    // the first interaction with any medium counts.
+    pub fn sample_distance(
+        &self,
+        ray: &Ray,
+        interval: &Interval,
+        scratch: &mut HitRecord,
+        glow: &mut Vec<(f64, Color)>,
+        rng: &mut StdRng,
+    ) -> Option<f64> {
+        match self {
+            Medium::Constant(medium) => medium.sample_distance(ray, interval, scratch, rng),
+            Medium::Grid(medium) => medium.sample_distance(ray, interval, glow, rng),
+        }
+    }
+
+    // Fraction of light that gets through the medium along the ray within interval, or an
+    // unbiased estimate of it
+    pub fn transmittance(
+        &self,
+        ray: &Ray,
+        interval: &Interval,
+        scratch: &mut HitRecord,
+        rng: &mut StdRng,
+    ) -> f64 {
+        match self {
+            Medium::Constant(medium) => medium.transmittance(ray, interval, scratch),
+            Medium::Grid(medium) => medium.transmittance(ray, interval, rng),
+        }
+    }
+}
+
+// A medium of even density filling the inside of boundary, which should be closed with its
+// normals facing out. It may be in several parts, such as an OBJ mesh split by material.
+// density is the chance per unit distance of the light interacting with the medium.
+pub struct ConstantMedium {
+    pub boundary: Vec<Arc<Shape>>,
+    pub density: f64,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Vec<Arc<Shape>>, density: f64) -> Self {
+        ConstantMedium { boundary, density }
+    }
+
+    // Ray parameter at which light travelling along the ray through interval first interacts
+    // with the medium, or None if it gets through. Distances are sampled in proportion to
+    // the transmittance up to them, so no weight is needed either way.
+    pub fn sample_distance(
+        &self,
+        ray: &Ray,
+        interval: &Interval,
+        scratch: &mut HitRecord,
+        rng: &mut StdRng,
+    ) -> Option<f64> {
+        if self.density <= 0.0 {
+            return None;
+        }
+        let speed = ray.direction.len();
+        let mut remaining = -(1.0 - rng.r#gen::<f64>()).ln() / self.density;
+        let mut result = None;
+        self.walk(ray, interval, scratch, |start, end| {
+            let length = (end - start) * speed;
+            if remaining < length {
+                result = Some(start + remaining / speed);
+                false
+            } else {
+                remaining -= length;
+                true
+            }
+        });
+        result
+    }
+
+    // Fraction of light that gets through the medium along the ray within interval
+    pub fn transmittance(&self, ray: &Ray, interval: &Interval, scratch: &mut HitRecord) -> f64 {
+        if self.density <= 0.0 {
+            return 1.0;
+        }
+        let speed = ray.direction.len();
+        let mut depth = 0.0;
+        self.walk(ray, interval, scratch, |start, end| {
+            depth += (end - start) * speed;
+            true
+        });
+        (-self.density * depth).exp()
+    }
+
+    // Calls visit with the ranges of ray parameters inside the boundary within interval, in
+    // order, until it returns false. Leaving through a boundary's back face shows the ray was
+    // inside since the last crossing, which also covers rays that start inside.
+    fn walk(
+        &self,
+        ray: &Ray,
+        interval: &Interval,
+        scratch: &mut HitRecord,
+        mut visit: impl FnMut(f64, f64) -> bool,
+    ) {
+        let gap = CROSSING_GAP / ray.direction.len();
+        let mut start = interval.min;
+        for _ in 0..MAX_CROSSINGS {
+            if start >= interval.max {
+                return;
+            }
+            let Some((t, entering)) = self.next_crossing(ray, start, scratch) else {
+                return;
+            };
+            if !entering && !visit(start, t.min(interval.max)) {
+                return;
+            }
+            if t >= interval.max {
+                return;
+            }
+            start = t + gap;
+        }
+    }
+
+    // Nearest boundary crossing after start, and whether the ray enters there
+    fn next_crossing(&self, ray: &Ray, start: f64, scratch: &mut HitRecord) -> Option<(f64, bool)> {
+        let mut closest: Option<(f64, bool)> = None;
+        for part in &self.boundary {
+            let max = closest.map_or(f64::INFINITY, |(t, _)| t);
+            if part.hit(ray, &Interval::new(start, max), scratch) {
+                closest = Some((scratch.t, scratch.front_face));
+            }
+        }
+        closest
+    }
+}
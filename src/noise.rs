@@ -0,0 +1,146 @@
+// noise.rs
+// Seeded 3D noise functions for procedural textures
+use crate::geometry::{Point3, Vec3};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+
+const POINT_COUNT: usize = 256;
+
+// Perlin gradient noise: random unit gradients at the lattice points, blended smoothly
+// across each cell. Values lie roughly in [-1, 1] and average zero.
+#[derive(Debug, Clone)]
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    permutations: [Vec<usize>; 3], // One shuffle of 0..POINT_COUNT per axis
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| Vec3::sample_unit_vector(&mut rng))
+            .collect();
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(&mut rng);
+            p
+        };
+        let permutations = [permutation(), permutation(), permutation()];
+        Perlin {
+            gradients,
+            permutations,
+        }
+    }
+
+    pub fn noise(&self, p: Point3) -> f64 {
+        let cell = [p.x.floor(), p.y.floor(), p.z.floor()];
+        let f = [p.x - cell[0], p.y - cell[1], p.z - cell[2]];
+        let mut sum = 0.0;
+        for corner in 0..8 {
+            let offset = [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1];
+            let hash = (0..3).fold(0, |hash, axis| {
+                let index = (cell[axis] as i64 + offset[axis] as i64).rem_euclid(POINT_COUNT as i64);
+                hash ^ self.permutations[axis][index as usize]
+            });
+            let to_point = Vec3::new(
+                f[0] - offset[0] as f64,
+                f[1] - offset[1] as f64,
+                f[2] - offset[2] as f64,
+            );
+            let weight: f64 = (0..3)
+                .map(|axis| {
+                    // Hermite smoothing hides the lattice
+                    let t = f[axis] * f[axis] * (3.0 - 2.0 * f[axis]);
+                    if offset[axis] == 1 { t } else { 1.0 - t }
+                })
+                .product();
+            sum += weight * self.gradients[hash].dot(to_point);
+        }
+        sum
+    }
+
+    // Fractal sum of octaves at doubling frequency and halving amplitude, normalized so the
+    // result stays in the range of a single octave
+    pub fn fbm(&self, p: Point3, octaves: u32) -> f64 {
+        octave_sum(p, octaves, |p| self.noise(p))
+    }
+
+    // Like fbm but with each octave folded to its absolute value, giving the billowy,
+    // creased look of turbulence. Values lie in [0, 1].
+    pub fn turbulence(&self, p: Point3, octaves: u32) -> f64 {
+        octave_sum(p, octaves, |p| self.noise(p).abs()).min(1.0)
+    }
+}
+
+// Worley (cellular) noise: one random feature point in every unit cell, and the value is
+// the distance to the nearest one. Values lie in [0, 1] for almost every point.
+#[derive(Debug, Clone)]
+pub struct Worley {
+    seed: u64,
+}
+
+impl Worley {
+    pub fn new(seed: u64) -> Self {
+        Worley { seed }
+    }
+
+    pub fn noise(&self, p: Point3) -> f64 {
+        let cell = [p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64];
+        let mut nearest = f64::INFINITY;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let neighbour = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                    let offset = p - self.feature_point(neighbour);
+                    nearest = nearest.min(offset.dot(offset));
+                }
+            }
+        }
+        nearest.sqrt().min(1.0)
+    }
+
+    pub fn fbm(&self, p: Point3, octaves: u32) -> f64 {
+        octave_sum(p, octaves, |p| self.noise(p))
+    }
+
+    // The feature point of a cell is a pure function of the seed and cell, so no table is
+    // needed and the pattern never repeats
+    fn feature_point(&self, cell: [i64; 3]) -> Point3 {
+        let mut hash = self.seed;
+        for coordinate in cell {
+            hash = mix(hash ^ coordinate as u64);
+        }
+        let mut unit = || {
+            hash = mix(hash);
+            (hash >> 11) as f64 / (1u64 << 53) as f64
+        };
+        Point3::new(
+            cell[0] as f64 + unit(),
+            cell[1] as f64 + unit(),
+            cell[2] as f64 + unit(),
+        )
+    }
+}
+
+fn octave_sum(p: Point3, octaves: u32, noise: impl Fn(Point3) -> f64) -> f64 {
+    let mut sum = 0.0;
+    let mut total_amplitude = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    for _ in 0..octaves.max(1) {
+        sum += amplitude * noise(frequency * p);
+        total_amplitude += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total_amplitude
+}
+
+// splitmix64 finalizer
+fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,531 @@
+// obj.rs
+// Wavefront OBJ mesh loader, with the companion MTL material library.
+// Each group (g/o) and material (usemtl) combination becomes one TriangleMesh in the World.
+use crate::geometry::{Point3, Vec3};
+use crate::image::{ImageError, is_high_dynamic_range, load_image};
+use crate::material::{Color, Conductor, ConductorFresnel, Dielectric, DiffuseLight, Lambertian};
+use crate::shapes::{Shape, World};
+use crate::texture::{ImageTexture, TextureFilter, WrapMode, solid};
+use crate::triangle::TriangleMesh;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io { path: PathBuf, source: io::Error },
+    Parse { path: PathBuf, line: usize, message: String },
+    Image(ImageError), // A texture map named by an MTL file
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+            ObjError::Image(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Parse { .. } => None,
+            ObjError::Image(e) => Some(e),
+        }
+    }
+}
+
+// Loads every face in the OBJ file at path into world, returning the number of triangles added.
+// Materials from referenced MTL files are registered with World::add_material; faces that
+// appear before any usemtl statement use default_material.
+pub fn load_obj(path: &Path, world: &mut World, default_material: usize) -> Result<usize, ObjError> {
+    let mut triangle_count = 0;
+    for (mesh, material) in read_obj(path, world, default_material)? {
+        triangle_count += mesh.triangle_count();
+        world.add_object(Shape::Mesh(mesh), material);
+    }
+    Ok(triangle_count)
+}
+
+// Like load_obj, but returns the meshes with their material indices instead of adding them
+// to world, so they can be shared between instances. Materials are still registered.
+pub fn read_obj(
+    path: &Path,
+    world: &mut World,
+    default_material: usize,
+) -> Result<Vec<(TriangleMesh, usize)>, ObjError> {
+    let source = read_file(path)?;
+    let mut positions: Vec<Point3> = vec![];
+    let mut normals: Vec<Vec3> = vec![];
+    let mut uvs: Vec<(f64, f64)> = vec![];
+    let mut materials: HashMap<String, usize> = HashMap::new();
+
+    let mut group = String::new();
+    let mut material = default_material;
+    // Insertion order is kept so that meshes are added to the World in file order
+    let mut builders: Vec<((String, usize), MeshBuilder)> = vec![];
+
+    for (line_index, raw_line) in source.lines().enumerate() {
+        let mut line = Line::new(path, line_index + 1, raw_line);
+        let Some(keyword) = line.next_token() else {
+            continue;
+        };
+        match keyword {
+            "v" => {
+                positions.push(line.vec3()?);
+            }
+            "vn" => {
+                normals.push(line.vec3()?);
+            }
+            "vt" => {
+                let u = line.f64()?;
+                let v = line.optional_f64()?.unwrap_or(0.0);
+                uvs.push((u, v));
+            }
+            "f" => {
+                let mut corners = vec![];
+                while let Some(token) = line.next_token() {
+                    corners.push(line.face_vertex(token, positions.len(), uvs.len(), normals.len())?);
+                }
+                if corners.len() < 3 {
+                    return Err(line.error(format!(
+                        "face has {} vertices, at least 3 are needed",
+                        corners.len()
+                    )));
+                }
+                let key = (group.clone(), material);
+                let builder = match builders.iter().position(|(k, _)| *k == key) {
+                    Some(i) => &mut builders[i].1,
+                    None => {
+                        builders.push((key, MeshBuilder::default()));
+                        &mut builders.last_mut().unwrap().1
+                    }
+                };
+                builder.add_polygon(&corners, &positions, &uvs, &normals);
+            }
+            "g" | "o" => {
+                group = line.rest().to_string();
+            }
+            "usemtl" => {
+                let name = line.rest();
+                material = *materials
+                    .get(name)
+                    .ok_or_else(|| line.error(format!("unknown material '{}'", name)))?;
+            }
+            "mtllib" => {
+                let directory = path.parent().unwrap_or(Path::new(""));
+                while let Some(file) = line.next_token() {
+                    load_mtl(&directory.join(file), world, &mut materials)?;
+                }
+            }
+            // Smoothing groups, free-form geometry, lines and points are not supported
+            _ => {}
+        }
+    }
+
+    Ok(builders
+        .into_iter()
+        .map(|((_, material), builder)| (builder.finish(), material))
+        .collect())
+}
+
+// Material parameters as written in the MTL file, before conversion
+struct MtlEntry {
+    name: String,
+    line: usize,
+    diffuse: Color,
+    specular: Color,
+    emission: Color,
+    shininess: Option<f64>,
+    refraction_index: f64,
+    dissolve: f64,
+    diffuse_map: Option<PathBuf>,
+}
+
+impl MtlEntry {
+    fn new(name: String, line: usize) -> Self {
+        MtlEntry {
+            name,
+            line,
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::default(),
+            emission: Color::default(),
+            shininess: None,
+            refraction_index: 1.5,
+            dissolve: 1.0,
+            diffuse_map: None,
+        }
+    }
+
+    // Maps onto the closest material we have:
+    // - emissive (Ke) materials become DiffuseLight
+    // - transparent (d < 1) materials become Dielectric with index Ni
+    // - materials with a specular colour and no diffuse colour become a Conductor of that
+    //   colour, with the Phong exponent Ns converted to roughness
+    // - everything else is Lambertian with albedo Kd, or the sRGB image map_Kd if given
+    fn register(self, world: &mut World) -> Result<usize, ObjError> {
+        Ok(if !is_black(self.emission) {
+            world.add_material(DiffuseLight {
+                emit: self.emission,
+            })
+        } else if self.dissolve < 1.0 {
+            world.add_material(Dielectric {
+                refraction_index: self.refraction_index,
+            })
+        } else if is_black(self.diffuse) && !is_black(self.specular) {
+            // The microfacet alpha matching a Phong lobe is sqrt(2 / (Ns + 2)), and roughness
+            // is the square root of alpha
+            let roughness = self
+                .shininess
+                .map_or(0.0, |ns| (2.0 / (ns + 2.0)).powf(0.25).clamp(0.0, 1.0));
+            world.add_material(Conductor::new(
+                ConductorFresnel::Schlick(solid(self.specular)),
+                solid(Color::new(roughness, roughness, roughness)),
+            ))
+        } else if let Some(map) = &self.diffuse_map {
+            let mut image = load_image(map).map_err(ObjError::Image)?;
+            if !is_high_dynamic_range(map) {
+                image.srgb_to_linear();
+            }
+            let texture = ImageTexture::new(image, WrapMode::Repeat, TextureFilter::Trilinear);
+            world.add_material(Lambertian {
+                albedo: Arc::new(texture),
+            })
+        } else {
+            world.add_material(Lambertian {
+                albedo: solid(self.diffuse),
+            })
+        })
+    }
+}
+
+fn is_black(color: Color) -> bool {
+    color.x <= 0.0 && color.y <= 0.0 && color.z <= 0.0
+}
+
+fn load_mtl(
+    path: &Path,
+    world: &mut World,
+    materials: &mut HashMap<String, usize>,
+) -> Result<(), ObjError> {
+    let source = read_file(path)?;
+    let mut entries: Vec<MtlEntry> = vec![];
+    for (line_index, raw_line) in source.lines().enumerate() {
+        let mut line = Line::new(path, line_index + 1, raw_line);
+        let Some(keyword) = line.next_token() else {
+            continue;
+        };
+        if keyword == "newmtl" {
+            let name = line.rest();
+            if name.is_empty() {
+                return Err(line.error("newmtl without a name".to_string()));
+            }
+            entries.push(MtlEntry::new(name.to_string(), line.number));
+            continue;
+        }
+        let Some(entry) = entries.last_mut() else {
+            // Statements before the first newmtl have nothing to apply to
+            if matches!(keyword, "Kd" | "Ks" | "Ke" | "Ns" | "Ni" | "d" | "Tr" | "map_Kd") {
+                return Err(line.error(format!("'{}' before any newmtl", keyword)));
+            }
+            continue;
+        };
+        match keyword {
+            "Kd" => entry.diffuse = line.vec3()?,
+            "Ks" => entry.specular = line.vec3()?,
+            "Ke" => entry.emission = line.vec3()?,
+            "Ns" => entry.shininess = Some(line.f64()?),
+            "Ni" => entry.refraction_index = line.f64()?,
+            "d" => entry.dissolve = line.f64()?,
+            "Tr" => entry.dissolve = 1.0 - line.f64()?,
+            // Options such as -s or -o come before the file name and are ignored
+            "map_Kd" => match line.rest().split_whitespace().last() {
+                Some(file) => {
+                    let directory = path.parent().unwrap_or(Path::new(""));
+                    entry.diffuse_map = Some(directory.join(file));
+                }
+                None => return Err(line.error("map_Kd without a file name".to_string())),
+            },
+            // Ambient, illumination models and other texture maps are not supported
+            _ => {}
+        }
+    }
+    for entry in entries {
+        if materials.contains_key(&entry.name) {
+            return Err(ObjError::Parse {
+                path: path.to_path_buf(),
+                line: entry.line,
+                message: format!("material '{}' is defined twice", entry.name),
+            });
+        }
+        let name = entry.name.clone();
+        let index = entry.register(world)?;
+        materials.insert(name, index);
+    }
+    Ok(())
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+// One line of an OBJ or MTL file, consumed token by token
+struct Line<'a> {
+    path: &'a Path,
+    number: usize,
+    text: &'a str,
+}
+
+impl<'a> Line<'a> {
+    fn new(path: &'a Path, number: usize, raw: &'a str) -> Self {
+        let text = match raw.find('#') {
+            Some(comment) => &raw[..comment],
+            None => raw,
+        };
+        Line {
+            path,
+            number,
+            text: text.trim(),
+        }
+    }
+
+    fn error(&self, message: String) -> ObjError {
+        ObjError::Parse {
+            path: self.path.to_path_buf(),
+            line: self.number,
+            message,
+        }
+    }
+
+    fn next_token(&mut self) -> Option<&'a str> {
+        let text = self.text.trim_start();
+        if text.is_empty() {
+            return None;
+        }
+        let end = text.find(char::is_whitespace).unwrap_or(text.len());
+        self.text = &text[end..];
+        Some(&text[..end])
+    }
+
+    // Everything left on the line, used for names which may contain spaces
+    fn rest(&mut self) -> &'a str {
+        let rest = self.text.trim();
+        self.text = "";
+        rest
+    }
+
+    fn optional_f64(&mut self) -> Result<Option<f64>, ObjError> {
+        match self.next_token() {
+            Some(token) => token
+                .parse()
+                .map(Some)
+                .map_err(|_| self.error(format!("expected a number, found '{}'", token))),
+            None => Ok(None),
+        }
+    }
+
+    fn f64(&mut self) -> Result<f64, ObjError> {
+        self.optional_f64()?
+            .ok_or_else(|| self.error("expected a number, found end of line".to_string()))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, ObjError> {
+        Ok(Vec3::new(self.f64()?, self.f64()?, self.f64()?))
+    }
+
+    // Parses one face corner: v, v/vt, v//vn or v/vt/vn, with 1-based or negative (relative)
+    // indices, and resolves it to 0-based indices into the data read so far
+    fn face_vertex(
+        &self,
+        token: &str,
+        position_count: usize,
+        uv_count: usize,
+        normal_count: usize,
+    ) -> Result<FaceVertex, ObjError> {
+        let mut parts = token.split('/');
+        let position = parts.next().unwrap_or("");
+        let uv = parts.next().filter(|s| !s.is_empty());
+        let normal = parts.next().filter(|s| !s.is_empty());
+        if parts.next().is_some() {
+            return Err(self.error(format!("malformed face vertex '{}'", token)));
+        }
+        Ok(FaceVertex {
+            position: self.resolve_index(position, position_count, "vertex")?,
+            uv: uv
+                .map(|s| self.resolve_index(s, uv_count, "texture coordinate"))
+                .transpose()?,
+            normal: normal
+                .map(|s| self.resolve_index(s, normal_count, "normal"))
+                .transpose()?,
+        })
+    }
+
+    fn resolve_index(&self, token: &str, count: usize, kind: &str) -> Result<usize, ObjError> {
+        let index: i64 = token
+            .parse()
+            .map_err(|_| self.error(format!("invalid {} index '{}'", kind, token)))?;
+        let resolved = if index > 0 {
+            index - 1
+        } else {
+            count as i64 + index
+        };
+        if index == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(self.error(format!(
+                "{} index {} out of range ({} defined so far)",
+                kind, index, count
+            )));
+        }
+        Ok(resolved as usize)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct FaceVertex {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+// Accumulates the faces of one mesh. OBJ indexes positions, normals and UVs separately,
+// so every distinct combination used by a face becomes one mesh vertex.
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    indices: Vec<[usize; 3]>,
+    vertex_map: HashMap<FaceVertex, usize>,
+    missing_normals: bool,
+    missing_uvs: bool,
+}
+
+impl MeshBuilder {
+    fn add_polygon(
+        &mut self,
+        corners: &[FaceVertex],
+        positions: &[Point3],
+        uvs: &[(f64, f64)],
+        normals: &[Vec3],
+    ) {
+        let vertices: Vec<usize> = corners
+            .iter()
+            .map(|corner| self.vertex(*corner, positions, uvs, normals))
+            .collect();
+        let points: Vec<Point3> = corners.iter().map(|c| positions[c.position]).collect();
+        for [a, b, c] in triangulate(&points) {
+            self.indices.push([vertices[a], vertices[b], vertices[c]]);
+        }
+    }
+
+    fn vertex(
+        &mut self,
+        corner: FaceVertex,
+        positions: &[Point3],
+        uvs: &[(f64, f64)],
+        normals: &[Vec3],
+    ) -> usize {
+        if let Some(&index) = self.vertex_map.get(&corner) {
+            return index;
+        }
+        let index = self.positions.len();
+        self.positions.push(positions[corner.position]);
+        match corner.normal {
+            Some(n) => self.normals.push(normals[n]),
+            None => self.missing_normals = true,
+        }
+        match corner.uv {
+            Some(uv) => self.uvs.push(uvs[uv]),
+            None => self.missing_uvs = true,
+        }
+        self.vertex_map.insert(corner, index);
+        index
+    }
+
+    // Normals and UVs are only kept if every vertex has one
+    fn finish(self) -> TriangleMesh {
+        let normals = if self.missing_normals { vec![] } else { self.normals };
+        let uvs = if self.missing_uvs { vec![] } else { self.uvs };
+        TriangleMesh::new(self.positions, normals, uvs, self.indices)
+    }
+}
+
+// Splits a planar polygon into triangles by ear clipping, so concave faces are handled.
+// Returns triples of indices into points. Falls back to a fan if the polygon is degenerate.
+fn triangulate(points: &[Point3]) -> Vec<[usize; 3]> {
+    let n = points.len();
+    if n == 3 {
+        return vec![[0, 1, 2]];
+    }
+    let fan = || (1..n - 1).map(|i| [0, i, i + 1]).collect();
+
+    // Newell's method gives a robust normal for the polygon's plane; project onto the two
+    // axes perpendicular to its largest component
+    let mut normal = Vec3::default();
+    for i in 0..n {
+        let (a, b) = (points[i], points[(i + 1) % n]);
+        normal = normal + Vec3::new(
+            (a.y - b.y) * (a.z + b.z),
+            (a.z - b.z) * (a.x + b.x),
+            (a.x - b.x) * (a.y + b.y),
+        );
+    }
+    let (ax, ay) = match (normal.x.abs(), normal.y.abs(), normal.z.abs()) {
+        (x, y, z) if x >= y && x >= z => (1, 2),
+        (_, y, z) if y >= z => (2, 0),
+        _ => (0, 1),
+    };
+    let flat: Vec<(f64, f64)> = points.iter().map(|p| (p[ax], p[ay])).collect();
+    let cross = |o: (f64, f64), a: (f64, f64), b: (f64, f64)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+    let signed_area: f64 = (0..n)
+        .map(|i| cross((0.0, 0.0), flat[i], flat[(i + 1) % n]))
+        .sum();
+    if signed_area == 0.0 {
+        return fan();
+    }
+    let orientation = signed_area.signum();
+
+    let mut remaining: Vec<usize> = (0..n).collect();
+    let mut triangles = Vec::with_capacity(n - 2);
+    while remaining.len() > 3 {
+        let m = remaining.len();
+        let ear = (0..m).find(|&i| {
+            let (prev, cur, next) = (remaining[(i + m - 1) % m], remaining[i], remaining[(i + 1) % m]);
+            // Convex corner with no other vertex inside the triangle it cuts off
+            if cross(flat[prev], flat[cur], flat[next]) * orientation <= 0.0 {
+                return false;
+            }
+            remaining.iter().all(|&other| {
+                other == prev
+                    || other == cur
+                    || other == next
+                    || cross(flat[prev], flat[cur], flat[other]) * orientation < 0.0
+                    || cross(flat[cur], flat[next], flat[other]) * orientation < 0.0
+                    || cross(flat[next], flat[prev], flat[other]) * orientation < 0.0
+            })
+        });
+        let Some(i) = ear else {
+            // Self-intersecting or otherwise malformed polygon
+            return fan();
+        };
+        triangles.push([remaining[(i + m - 1) % m], remaining[i], remaining[(i + 1) % m]]);
+        remaining.remove(i);
+    }
+    triangles.push([remaining[0], remaining[1], remaining[2]]);
+    triangles
+}
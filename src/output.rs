@@ -0,0 +1,339 @@
+// output.rs
+// Image file writers. Pixels are stored row by row from the top left. Low dynamic range formats
+// take display-ready colours in [0, 1]; high dynamic range formats take linear radiance, which
+// is written unclamped so it can be re-exposed and tone mapped later.
+use crate::material::Color;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufWriter, Result, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Ppm,      // ASCII P3 portable pixmap
+    Png,      // 8 bits per channel
+    Png16,    // 16 bits per channel, for smoother gradients
+    Hdr,      // Radiance RGBE: 8-bit mantissas with a shared exponent
+    Pfm,      // Portable float map, 32-bit floats
+    Exr,      // OpenEXR with 16-bit half floats
+    ExrFloat, // OpenEXR with 32-bit floats
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 7] = [
+        OutputFormat::Ppm,
+        OutputFormat::Png,
+        OutputFormat::Png16,
+        OutputFormat::Hdr,
+        OutputFormat::Pfm,
+        OutputFormat::Exr,
+        OutputFormat::ExrFloat,
+    ];
+
+    // Name accepted by --format
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Ppm => "ppm",
+            OutputFormat::Png => "png",
+            OutputFormat::Png16 => "png16",
+            OutputFormat::Hdr => "hdr",
+            OutputFormat::Pfm => "pfm",
+            OutputFormat::Exr => "exr",
+            OutputFormat::ExrFloat => "exr32",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Ppm => "ppm",
+            OutputFormat::Png | OutputFormat::Png16 => "png",
+            OutputFormat::Hdr => "hdr",
+            OutputFormat::Pfm => "pfm",
+            OutputFormat::Exr | OutputFormat::ExrFloat => "exr",
+        }
+    }
+
+    // Whether the format stores linear radiance rather than display-ready colours
+    pub fn is_high_dynamic_range(self) -> bool {
+        match self {
+            OutputFormat::Ppm | OutputFormat::Png | OutputFormat::Png16 => false,
+            OutputFormat::Hdr | OutputFormat::Pfm | OutputFormat::Exr | OutputFormat::ExrFloat => {
+                true
+            }
+        }
+    }
+
+    // Picks the format matching a file's extension, if any. Extensions shared by several
+    // formats map to the first of them.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> std::result::Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown output format '{}'", s))
+    }
+}
+
+impl fmt::Display for OutputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+pub fn write_image(
+    path: &Path,
+    format: OutputFormat,
+    width: u32,
+    height: u32,
+    pixels: &[Color],
+) -> Result<()> {
+    assert_eq!(pixels.len(), width as usize * height as usize);
+    let mut writer = BufWriter::new(File::create(path)?);
+    match format {
+        OutputFormat::Ppm => write_ppm(&mut writer, width, height, pixels)?,
+        OutputFormat::Png => write_png(&mut writer, width, height, pixels, png::BitDepth::Eight)?,
+        OutputFormat::Png16 => {
+            write_png(&mut writer, width, height, pixels, png::BitDepth::Sixteen)?
+        }
+        OutputFormat::Hdr => write_hdr(&mut writer, width, height, pixels)?,
+        OutputFormat::Pfm => write_pfm(&mut writer, width, height, pixels)?,
+        OutputFormat::Exr => write_exr(&mut writer, width, height, pixels, ExrPixelType::Half)?,
+        OutputFormat::ExrFloat => {
+            write_exr(&mut writer, width, height, pixels, ExrPixelType::Float)?
+        }
+    }
+    writer.flush()
+}
+
+fn write_ppm<W: Write>(writer: &mut W, width: u32, height: u32, pixels: &[Color]) -> Result<()> {
+    writeln!(writer, "P3")?;
+    writeln!(writer, "{} {}", width, height)?;
+    writeln!(writer, "255")?;
+    for color in pixels {
+        let [r, g, b] = to_bytes(*color);
+        writeln!(writer, "{} {} {}", r, g, b)?;
+    }
+    Ok(())
+}
+
+fn write_png<W: Write>(
+    writer: &mut W,
+    width: u32,
+    height: u32,
+    pixels: &[Color],
+    bit_depth: png::BitDepth,
+) -> Result<()> {
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(bit_depth);
+    // Pixels are gamma encoded with exponent 1/2 (see film::color_gamma_transform)
+    encoder.set_source_gamma(png::ScaledFloat::new(0.5));
+    let data: Vec<u8> = match bit_depth {
+        png::BitDepth::Sixteen => pixels
+            .iter()
+            .flat_map(|color| {
+                [color.x, color.y, color.z]
+                    .into_iter()
+                    .flat_map(|c| ((c.clamp(0.0, 1.0) * 65535.0).round() as u16).to_be_bytes())
+            })
+            .collect(),
+        _ => pixels.iter().flat_map(|color| to_bytes(*color)).collect(),
+    };
+    let mut png_writer = encoder.write_header().map_err(io::Error::other)?;
+    png_writer.write_image_data(&data).map_err(io::Error::other)?;
+    png_writer.finish().map_err(io::Error::other)
+}
+
+// Float casts saturate, so out of range values clamp to 0 and 255
+fn to_bytes(color: Color) -> [u8; 3] {
+    [
+        (color.x * 255.999) as u8,
+        (color.y * 255.999) as u8,
+        (color.z * 255.999) as u8,
+    ]
+}
+
+fn write_hdr<W: Write>(writer: &mut W, width: u32, height: u32, pixels: &[Color]) -> Result<()> {
+    writeln!(writer, "#?RADIANCE")?;
+    writeln!(writer, "FORMAT=32-bit_rle_rgbe")?;
+    writeln!(writer)?;
+    writeln!(writer, "-Y {} +X {}", height, width)?;
+    // Scanlines are written flat (uncompressed), which every reader accepts
+    for color in pixels {
+        writer.write_all(&to_rgbe(*color))?;
+    }
+    Ok(())
+}
+
+// Encodes a colour as three 8-bit mantissas sharing the exponent of the largest component.
+// Negative and NaN components are stored as zero.
+pub fn to_rgbe(color: Color) -> [u8; 4] {
+    let [r, g, b] = [color.x, color.y, color.z].map(|c| if c > 0.0 { c } else { 0.0 });
+    let largest = r.max(g).max(b);
+    if largest < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+    // largest = mantissa * 2^exponent with mantissa in [0.5, 1)
+    let mut exponent = largest.log2().floor() as i32 + 1;
+    if largest / 2f64.powi(exponent) >= 1.0 {
+        exponent += 1;
+    }
+    let exponent = exponent.clamp(-128, 127);
+    let scale = 256.0 / 2f64.powi(exponent);
+    [
+        (r * scale).min(255.0) as u8,
+        (g * scale).min(255.0) as u8,
+        (b * scale).min(255.0) as u8,
+        (exponent + 128) as u8,
+    ]
+}
+
+fn write_pfm<W: Write>(writer: &mut W, width: u32, height: u32, pixels: &[Color]) -> Result<()> {
+    // A negative scale marks little-endian data
+    write!(writer, "PF\n{} {}\n-1.0\n", width, height)?;
+    // Rows are stored bottom to top
+    for row in pixels.chunks(width as usize).rev() {
+        for color in row {
+            for c in [color.x, color.y, color.z] {
+                writer.write_all(&(c as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ExrPixelType {
+    Half,
+    Float,
+}
+
+// Minimal single-part scanline OpenEXR file: uncompressed, one scanline per block, with
+// B, G and R channels (channels must be listed in alphabetical order)
+fn write_exr<W: Write>(
+    writer: &mut W,
+    width: u32,
+    height: u32,
+    pixels: &[Color],
+    pixel_type: ExrPixelType,
+) -> Result<()> {
+    let (type_code, bytes_per_sample) = match pixel_type {
+        ExrPixelType::Half => (1i32, 2usize),
+        ExrPixelType::Float => (2i32, 4usize),
+    };
+
+    let mut header: Vec<u8> = vec![];
+    header.extend_from_slice(&20000630u32.to_le_bytes()); // Magic number
+    header.extend_from_slice(&2u32.to_le_bytes()); // Version 2, single-part scanline
+
+    let mut channels: Vec<u8> = vec![];
+    for name in ["B", "G", "R"] {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&type_code.to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0]); // pLinear and reserved bytes
+        channels.extend_from_slice(&1i32.to_le_bytes()); // x sampling
+        channels.extend_from_slice(&1i32.to_le_bytes()); // y sampling
+    }
+    channels.push(0);
+    let window: Vec<u8> = [0i32, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    exr_attribute(&mut header, "channels", "chlist", &channels);
+    exr_attribute(&mut header, "compression", "compression", &[0]);
+    exr_attribute(&mut header, "dataWindow", "box2i", &window);
+    exr_attribute(&mut header, "displayWindow", "box2i", &window);
+    exr_attribute(&mut header, "lineOrder", "lineOrder", &[0]); // Increasing y
+    exr_attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+    exr_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    exr_attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+    header.push(0);
+    writer.write_all(&header)?;
+
+    // Offset table: absolute file position of every scanline block
+    let line_data_size = width as usize * 3 * bytes_per_sample;
+    let block_size = 8 + line_data_size as u64;
+    let first_block = header.len() as u64 + 8 * height as u64;
+    for y in 0..height as u64 {
+        writer.write_all(&(first_block + y * block_size).to_le_bytes())?;
+    }
+
+    let mut line: Vec<u8> = Vec::with_capacity(line_data_size);
+    for (y, row) in pixels.chunks(width as usize).enumerate() {
+        writer.write_all(&(y as i32).to_le_bytes())?;
+        writer.write_all(&(line_data_size as i32).to_le_bytes())?;
+        line.clear();
+        for channel in [2, 1, 0] {
+            for color in row {
+                let value = color[channel] as f32;
+                match pixel_type {
+                    ExrPixelType::Half => line.extend_from_slice(&f32_to_half(value).to_le_bytes()),
+                    ExrPixelType::Float => line.extend_from_slice(&value.to_le_bytes()),
+                }
+            }
+        }
+        writer.write_all(&line)?;
+    }
+    Ok(())
+}
+
+fn exr_attribute(header: &mut Vec<u8>, name: &str, type_name: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(type_name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+// IEEE 754 binary32 to binary16 bits, rounding to nearest even. Values too large for a half
+// become infinity, and values too small become subnormals or zero.
+fn f32_to_half(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        // Infinity, or NaN with a mantissa bit kept set
+        let nan_bit = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan_bit;
+    }
+    let half_exponent = exponent - 127 + 15;
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if half_exponent <= 0 {
+        // Subnormal half: shift the mantissa (with its implicit leading bit) into place
+        if half_exponent < -10 {
+            return sign;
+        }
+        let full_mantissa = mantissa | 0x80_0000;
+        let shift = (14 - half_exponent) as u32;
+        let mut half_mantissa = full_mantissa >> shift;
+        let remainder = full_mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        if remainder > halfway || (remainder == halfway && half_mantissa & 1 == 1) {
+            half_mantissa += 1;
+        }
+        return sign | half_mantissa as u16;
+    }
+    let mut half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1fff;
+    if remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1) {
+        // A carry out of the mantissa correctly bumps the exponent, up to infinity
+        half += 1;
+    }
+    sign | half as u16
+}
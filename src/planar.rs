@@ -0,0 +1,351 @@
+// planar.rs
+// Flat shapes: parallelogram quads, infinite planes, disks, and boxes built from six quads
+use crate::aabb::Aabb;
+use crate::geometry::{Point3, Vec3};
+use crate::math::Interval;
+use crate::ray::Ray;
+use crate::sampling::area_to_solid_angle;
+use crate::shapes::{HitRecord, Hittable};
+use rand::Rng;
+use rand::rngs::StdRng;
+use std::f64::consts::PI;
+
+// Flat shapes have zero thickness along their normal; boxes are padded like triangles'
+const BOX_PADDING: f64 = 1e-4;
+
+// Ray parameter at which ray meets the plane through point with the given normal
+fn plane_intersect(ray: &Ray, point: Point3, normal: Vec3, time: &Interval) -> Option<f64> {
+    let denominator = normal.dot(ray.direction);
+    // Ray parallel to the plane
+    if denominator.abs() < 1e-12 {
+        return None;
+    }
+    let t = normal.dot(point - ray.origin) / denominator;
+    time.contains(t).then_some(t)
+}
+
+fn record_planar_hit(
+    ray: &Ray,
+    t: f64,
+    normal: Vec3,
+    uv: (f64, f64),
+    uv_scale: f64,
+    hit_rec: &mut HitRecord,
+) {
+    hit_rec.t = t;
+    hit_rec.p = ray.at(t);
+    hit_rec.barycentric = (0.0, 0.0);
+    hit_rec.uv = uv;
+    hit_rec.uv_scale = uv_scale;
+    hit_rec.set_face_normal(ray, normal);
+}
+
+// Parallelogram with one corner at corner and edges u and v leaving it. The front face is
+// the side u x v points to, and uv runs from (0, 0) at corner to (1, 1) at corner + u + v.
+#[derive(Debug, Clone)]
+pub struct Quad {
+    pub corner: Point3,
+    pub u: Vec3,
+    pub v: Vec3,
+    normal: Vec3,
+    w: Vec3, // (u x v) / |u x v|^2, which projects plane points onto the u and v edges
+    area: f64,
+}
+
+impl Quad {
+    // None if the edges are parallel or either is zero, which leaves no area to hit or sample
+    pub fn new(corner: Point3, u: Vec3, v: Vec3) -> Option<Self> {
+        let n = u.cross(v);
+        let length_squared = n.dot(n);
+        if !(length_squared > 0.0 && length_squared.is_finite()) {
+            return None;
+        }
+        Some(Quad {
+            corner,
+            u,
+            v,
+            normal: n.normalize(),
+            w: n / length_squared,
+            area: n.len(),
+        })
+    }
+
+    pub fn area(&self) -> f64 {
+        self.area
+    }
+
+    // Ray parameter and edge coordinates (alpha, beta) of the hit
+    fn intersect(&self, ray: &Ray, time: &Interval) -> Option<(f64, f64, f64)> {
+        let t = plane_intersect(ray, self.corner, self.normal, time)?;
+        let planar = ray.at(t) - self.corner;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        let inside = (0.0..=1.0).contains(&alpha) && (0.0..=1.0).contains(&beta);
+        inside.then_some((t, alpha, beta))
+    }
+
+    fn sample_point(&self, rng: &mut StdRng) -> Point3 {
+        self.corner + rng.r#gen::<f64>() * self.u + rng.r#gen::<f64>() * self.v
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        match self.intersect(ray, time) {
+            Some((t, alpha, beta)) => {
+                let uv_scale = 1.0 / self.area.sqrt();
+                record_planar_hit(ray, t, self.normal, (alpha, beta), uv_scale, hit_rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        Aabb::new(self.corner, self.corner + self.u + self.v)
+            .union(&Aabb::new(self.corner + self.u, self.corner + self.v))
+            .pad(BOX_PADDING)
+    }
+
+    fn sample_direction(&self, origin: Point3, _time: f64, rng: &mut StdRng) -> Option<Vec3> {
+        Some(self.sample_point(rng) - origin)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3, time: f64) -> f64 {
+        let ray = Ray { origin, direction, time };
+        match self.intersect(&ray, &Interval::new(0.001, f64::INFINITY)) {
+            Some((t, _, _)) => {
+                let cosine = self.normal.dot(direction.normalize()).abs();
+                area_to_solid_angle(1.0 / self.area, t * direction.len(), cosine)
+            }
+            None => 0.0,
+        }
+    }
+}
+
+// Infinite plane through point. uv are distances in world units along two directions in the
+// plane, so repeating textures tile with a period of one unit.
+#[derive(Debug, Clone)]
+pub struct Plane {
+    pub point: Point3,
+    pub normal: Vec3, // Unit length; the front face is the side it points to
+    tangent: Vec3,
+    bitangent: Vec3,
+}
+
+impl Plane {
+    // None if the normal is zero
+    pub fn new(point: Point3, normal: Vec3) -> Option<Self> {
+        let length_squared = normal.dot(normal);
+        if !(length_squared > 0.0 && length_squared.is_finite()) {
+            return None;
+        }
+        let normal = normal.normalize();
+        let (tangent, bitangent) = Vec3::orthonormal_basis(normal);
+        Some(Plane {
+            point,
+            normal,
+            tangent,
+            bitangent,
+        })
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        match plane_intersect(ray, self.point, self.normal, time) {
+            Some(t) => {
+                let planar = ray.at(t) - self.point;
+                let uv = (planar.dot(self.tangent), planar.dot(self.bitangent));
+                record_planar_hit(ray, t, self.normal, uv, 1.0, hit_rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Unbounded: the World keeps planes out of its BVH
+    fn bounding_box(&self) -> Aabb {
+        let infinity = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        Aabb::new(-infinity, infinity)
+    }
+}
+
+// Circular disk facing along normal. uv map the disk's bounding square to [0, 1] x [0, 1].
+#[derive(Debug, Clone)]
+pub struct Disk {
+    pub center: Point3,
+    pub normal: Vec3, // Unit length; the front face is the side it points to
+    pub radius: f64,
+    tangent: Vec3,
+    bitangent: Vec3,
+}
+
+impl Disk {
+    // None if the normal is zero or the radius is not positive
+    pub fn new(center: Point3, normal: Vec3, radius: f64) -> Option<Self> {
+        let length_squared = normal.dot(normal);
+        if !(length_squared > 0.0 && length_squared.is_finite() && radius > 0.0) {
+            return None;
+        }
+        let normal = normal.normalize();
+        let (tangent, bitangent) = Vec3::orthonormal_basis(normal);
+        Some(Disk {
+            center,
+            normal,
+            radius,
+            tangent,
+            bitangent,
+        })
+    }
+
+    fn intersect(&self, ray: &Ray, time: &Interval) -> Option<(f64, Vec3)> {
+        let t = plane_intersect(ray, self.center, self.normal, time)?;
+        let planar = ray.at(t) - self.center;
+        (planar.dot(planar) <= self.radius * self.radius).then_some((t, planar))
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        match self.intersect(ray, time) {
+            Some((t, planar)) => {
+                let diameter = 2.0 * self.radius;
+                let uv = (
+                    0.5 + planar.dot(self.tangent) / diameter,
+                    0.5 + planar.dot(self.bitangent) / diameter,
+                );
+                record_planar_hit(ray, t, self.normal, uv, 1.0 / diameter, hit_rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // The disk spans radius * sqrt(1 - n_i^2) along each axis i
+        let n = self.normal;
+        let extent = self.radius
+            * Vec3::new(
+                (1.0 - n.x * n.x).max(0.0).sqrt(),
+                (1.0 - n.y * n.y).max(0.0).sqrt(),
+                (1.0 - n.z * n.z).max(0.0).sqrt(),
+            );
+        Aabb::new(self.center - extent, self.center + extent).pad(BOX_PADDING)
+    }
+
+    fn sample_direction(&self, origin: Point3, _time: f64, rng: &mut StdRng) -> Option<Vec3> {
+        let r = self.radius * rng.r#gen::<f64>().sqrt();
+        let phi = 2.0 * PI * rng.r#gen::<f64>();
+        let point = self.center + r * phi.cos() * self.tangent + r * phi.sin() * self.bitangent;
+        Some(point - origin)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3, time: f64) -> f64 {
+        let ray = Ray { origin, direction, time };
+        match self.intersect(&ray, &Interval::new(0.001, f64::INFINITY)) {
+            Some((t, _)) => {
+                let area = PI * self.radius * self.radius;
+                let cosine = self.normal.dot(direction.normalize()).abs();
+                area_to_solid_angle(1.0 / area, t * direction.len(), cosine)
+            }
+            None => 0.0,
+        }
+    }
+}
+
+// Axis-aligned box between two opposite corners, made of six outward-facing quads
+#[derive(Debug, Clone)]
+pub struct Cuboid {
+    pub faces: Box<[Quad; 6]>, // Boxed to keep Shape small
+    area: f64,
+}
+
+impl Cuboid {
+    // None if the corners share a coordinate, which leaves the box flat
+    pub fn new(a: Point3, b: Point3) -> Option<Self> {
+        let min = Vec3::min(a, b);
+        let max = Vec3::max(a, b);
+        let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+        let faces = Box::new([
+            Quad::new(Point3::new(min.x, min.y, max.z), dx, dy)?, // Front (+z)
+            Quad::new(Point3::new(max.x, min.y, max.z), -dz, dy)?, // Right (+x)
+            Quad::new(Point3::new(max.x, min.y, min.z), -dx, dy)?, // Back (-z)
+            Quad::new(Point3::new(min.x, min.y, min.z), dz, dy)?, // Left (-x)
+            Quad::new(Point3::new(min.x, max.y, max.z), dx, -dz)?, // Top (+y)
+            Quad::new(Point3::new(min.x, min.y, min.z), dx, dz)?, // Bottom (-y)
+        ]);
+        let area = faces.iter().map(Quad::area).sum();
+        Some(Cuboid { faces, area })
+    }
+
+    // Closest face hit, with the ray parameter and edge coordinates of the hit
+    fn closest_face(&self, ray: &Ray, time: &Interval) -> Option<(&Quad, f64, f64, f64)> {
+        let mut closest = None;
+        let mut closest_t = time.max;
+        for face in self.faces.iter() {
+            let search = Interval::new(time.min, closest_t);
+            if let Some((t, alpha, beta)) = face.intersect(ray, &search) {
+                closest = Some((face, t, alpha, beta));
+                closest_t = t;
+            }
+        }
+        closest
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        match self.closest_face(ray, time) {
+            Some((face, t, alpha, beta)) => {
+                let uv_scale = 1.0 / face.area.sqrt();
+                record_planar_hit(ray, t, face.normal, (alpha, beta), uv_scale, hit_rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.faces
+            .iter()
+            .fold(Aabb::empty(), |b, face| b.union(&face.bounding_box()))
+    }
+
+    // Uniform over the surface: faces are picked in proportion to their area
+    fn sample_direction(&self, origin: Point3, time: f64, rng: &mut StdRng) -> Option<Vec3> {
+        if self.area <= 0.0 {
+            return None;
+        }
+        let mut pick = rng.r#gen::<f64>() * self.area;
+        let face = self
+            .faces
+            .iter()
+            .find(|face| {
+                pick -= face.area();
+                pick < 0.0
+            })
+            .unwrap_or(&self.faces[5]);
+        face.sample_direction(origin, time, rng)
+    }
+
+    // Faces are sampled whether or not they are hidden behind others, so the density is that
+    // of every face the line crosses, not only the closest
+    fn pdf_value(&self, origin: Point3, direction: Vec3, time: f64) -> f64 {
+        if self.area <= 0.0 {
+            return 0.0;
+        }
+        let ray = Ray { origin, direction, time };
+        let search = Interval::new(0.001, f64::INFINITY);
+        self.faces
+            .iter()
+            .filter_map(|face| {
+                let (t, _, _) = face.intersect(&ray, &search)?;
+                let cosine = face.normal.dot(direction.normalize()).abs();
+                Some(area_to_solid_angle(1.0 / self.area, t * direction.len(), cosine))
+            })
+            .sum()
+    }
+}
@@ -0,0 +1,443 @@
+// quadric.rs
+// Curved analytic surfaces: cylinders, cones, tori and general quadrics
+use crate::aabb::Aabb;
+use crate::geometry::{Point3, Vec3};
+use crate::math::{Interval, solve_quadratic, solve_quartic};
+use crate::ray::Ray;
+use crate::shapes::{HitRecord, Hittable, Sphere};
+use std::f64::consts::{PI, SQRT_2};
+
+// Orthonormal frame whose y axis is a shape's axis. Cylinders, cones and tori are intersected
+// in these local coordinates, where the axis is the y axis and the base or centre is the
+// origin. Lengths are unchanged, so ray parameters are the same in both spaces.
+#[derive(Debug, Clone)]
+struct Frame {
+    origin: Point3,
+    x: Vec3,
+    y: Vec3,
+    z: Vec3,
+}
+
+impl Frame {
+    // None if the axis is zero, which leaves no direction for it
+    fn new(origin: Point3, axis: Vec3) -> Option<Self> {
+        let length_squared = axis.dot(axis);
+        if !(length_squared > 0.0 && length_squared.is_finite()) {
+            return None;
+        }
+        let y = axis.normalize();
+        let (z, x) = Vec3::orthonormal_basis(y);
+        Some(Frame { origin, x, y, z })
+    }
+
+    fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(v.dot(self.x), v.dot(self.y), v.dot(self.z))
+    }
+
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        v.x * self.x + v.y * self.y + v.z * self.z
+    }
+
+    fn local_ray(&self, ray: &Ray) -> Ray {
+        Ray {
+            origin: self.to_local(ray.origin - self.origin),
+            direction: self.to_local(ray.direction),
+            time: ray.time,
+        }
+    }
+
+    // World bounds of the local box between min and max
+    fn bounds(&self, min: Point3, max: Point3) -> Aabb {
+        (0..8).fold(Aabb::empty(), |b, corner| {
+            let local = Vec3::new(
+                if corner & 1 == 0 { min.x } else { max.x },
+                if corner & 2 == 0 { min.y } else { max.y },
+                if corner & 4 == 0 { min.z } else { max.z },
+            );
+            b.grow(self.origin + self.to_world(local))
+        })
+    }
+}
+
+// Local hit found by one of the shapes below, before it is written to a HitRecord
+struct LocalHit {
+    t: f64,
+    normal: Vec3, // Outward, in local coordinates, unit length
+    uv: (f64, f64),
+    uv_scale: f64,
+}
+
+fn record_local_hit(ray: &Ray, frame: &Frame, hit: LocalHit, hit_rec: &mut HitRecord) {
+    hit_rec.t = hit.t;
+    hit_rec.p = ray.at(hit.t);
+    hit_rec.barycentric = (0.0, 0.0);
+    hit_rec.uv = hit.uv;
+    hit_rec.uv_scale = hit.uv_scale;
+    hit_rec.set_face_normal(ray, frame.to_world(hit.normal));
+}
+
+// Keeps whichever of two candidate hits is closer
+fn closer(a: Option<LocalHit>, b: Option<LocalHit>) -> Option<LocalHit> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if b.t < a.t { b } else { a }),
+        (a, b) => a.or(b),
+    }
+}
+
+// Fraction of a turn around the local y axis, starting from +x
+fn azimuth(p: Point3) -> f64 {
+    let phi = p.z.atan2(p.x);
+    if phi < 0.0 { phi / (2.0 * PI) + 1.0 } else { phi / (2.0 * PI) }
+}
+
+// Hit on the flat end of a cylinder or cone: the disk of radius at height y, facing along
+// +y if facing_up and -y otherwise. uv map the disk's bounding square to [0, 1] x [0, 1].
+fn cap_hit(ray: &Ray, time: &Interval, y: f64, radius: f64, facing_up: bool) -> Option<LocalHit> {
+    if ray.direction.y == 0.0 {
+        return None;
+    }
+    let t = (y - ray.origin.y) / ray.direction.y;
+    if !time.contains(t) {
+        return None;
+    }
+    let p = ray.at(t);
+    if p.x * p.x + p.z * p.z > radius * radius {
+        return None;
+    }
+    let diameter = 2.0 * radius;
+    Some(LocalHit {
+        t,
+        normal: Vec3::new(0.0, if facing_up { 1.0 } else { -1.0 }, 0.0),
+        uv: (0.5 + p.x / diameter, 0.5 + p.z / diameter),
+        uv_scale: 1.0 / diameter,
+    })
+}
+
+// Circular cylinder from base to top. Without caps it is an open tube, seen from inside
+// through its ends. On the side u runs once around the axis and v from base (0) to top (1).
+#[derive(Debug, Clone)]
+pub struct Cylinder {
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    frame: Frame,
+}
+
+impl Cylinder {
+    // None if base and top coincide or the radius is not positive
+    pub fn new(base: Point3, top: Point3, radius: f64, capped: bool) -> Option<Self> {
+        if !(radius > 0.0 && radius.is_finite()) {
+            return None;
+        }
+        Some(Cylinder {
+            radius,
+            height: (top - base).len(),
+            capped,
+            frame: Frame::new(base, top - base)?,
+        })
+    }
+
+    fn side_hit(&self, ray: &Ray, time: &Interval) -> Option<LocalHit> {
+        let (o, d) = (ray.origin, ray.direction);
+        let a = d.x * d.x + d.z * d.z;
+        let b = 2.0 * (o.x * d.x + o.z * d.z);
+        let c = o.x * o.x + o.z * o.z - self.radius * self.radius;
+        let (t0, t1) = solve_quadratic(a, b, c)?;
+        [t0, t1].into_iter().find_map(|t| {
+            let p = ray.at(t);
+            if !time.contains(t) || p.y < 0.0 || p.y > self.height {
+                return None;
+            }
+            Some(LocalHit {
+                t,
+                normal: Vec3::new(p.x, 0.0, p.z) / self.radius,
+                uv: (azimuth(p), p.y / self.height),
+                // u spans the circumference and v the height; take their geometric mean
+                uv_scale: 1.0 / (2.0 * PI * self.radius * self.height).sqrt(),
+            })
+        })
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        let local = self.frame.local_ray(ray);
+        let mut hit = self.side_hit(&local, time);
+        if self.capped {
+            hit = closer(hit, cap_hit(&local, time, 0.0, self.radius, false));
+            hit = closer(hit, cap_hit(&local, time, self.height, self.radius, true));
+        }
+        match hit {
+            Some(hit) => {
+                record_local_hit(ray, &self.frame, hit, hit_rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = self.radius;
+        self.frame
+            .bounds(Vec3::new(-r, 0.0, -r), Vec3::new(r, self.height, r))
+    }
+}
+
+// Right circular cone with a base of radius around base, narrowing to a point at apex. The
+// cap closes the base. On the side u runs once around the axis and v from base (0) to apex (1).
+#[derive(Debug, Clone)]
+pub struct Cone {
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    frame: Frame,
+}
+
+impl Cone {
+    // None if base and apex coincide or the radius is not positive
+    pub fn new(base: Point3, apex: Point3, radius: f64, capped: bool) -> Option<Self> {
+        if !(radius > 0.0 && radius.is_finite()) {
+            return None;
+        }
+        Some(Cone {
+            radius,
+            height: (apex - base).len(),
+            capped,
+            frame: Frame::new(base, apex - base)?,
+        })
+    }
+
+    // The side is x^2 + z^2 = k^2 (h - y)^2 for 0 <= y <= h, where k = radius / height
+    fn side_hit(&self, ray: &Ray, time: &Interval) -> Option<LocalHit> {
+        let (o, d) = (ray.origin, ray.direction);
+        let h = self.height;
+        let k2 = (self.radius / h) * (self.radius / h);
+        let a = d.x * d.x + d.z * d.z - k2 * d.y * d.y;
+        let b = 2.0 * (o.x * d.x + o.z * d.z + k2 * (h - o.y) * d.y);
+        let c = o.x * o.x + o.z * o.z - k2 * (h - o.y) * (h - o.y);
+        let (t0, t1) = solve_quadratic(a, b, c)?;
+        let slant = (self.radius * self.radius + h * h).sqrt();
+        [t0, t1].into_iter().find_map(|t| {
+            let p = ray.at(t);
+            // The equation also describes a mirrored cone above the apex
+            if !time.contains(t) || p.y < 0.0 || p.y > h {
+                return None;
+            }
+            let gradient = Vec3::new(p.x, k2 * (h - p.y), p.z);
+            let normal = if gradient.dot(gradient) < 1e-16 {
+                Vec3::new(0.0, 1.0, 0.0) // The apex
+            } else {
+                gradient.normalize()
+            };
+            Some(LocalHit {
+                t,
+                normal,
+                uv: (azimuth(p), p.y / h),
+                uv_scale: 1.0 / (2.0 * PI * self.radius * slant).sqrt(),
+            })
+        })
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        let local = self.frame.local_ray(ray);
+        let mut hit = self.side_hit(&local, time);
+        if self.capped {
+            hit = closer(hit, cap_hit(&local, time, 0.0, self.radius, false));
+        }
+        match hit {
+            Some(hit) => {
+                record_local_hit(ray, &self.frame, hit, hit_rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let r = self.radius;
+        self.frame
+            .bounds(Vec3::new(-r, 0.0, -r), Vec3::new(r, self.height, r))
+    }
+}
+
+// Ring swept by a circle of minor_radius whose centre runs around a circle of major_radius
+// about axis. u runs once around the axis and v once around the tube, starting outermost.
+#[derive(Debug, Clone)]
+pub struct Torus {
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    frame: Frame,
+}
+
+impl Torus {
+    // None if the axis is zero or the radii are not positive. A tube wider than the ring it
+    // runs around would pass through itself, so minor_radius may not exceed major_radius.
+    pub fn new(center: Point3, axis: Vec3, major_radius: f64, minor_radius: f64) -> Option<Self> {
+        if !(minor_radius > 0.0 && minor_radius <= major_radius && major_radius.is_finite()) {
+            return None;
+        }
+        Some(Torus {
+            major_radius,
+            minor_radius,
+            frame: Frame::new(center, axis)?,
+        })
+    }
+
+    // Solves (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2) along the ray
+    fn local_hit(&self, ray: &Ray, time: &Interval) -> Option<LocalHit> {
+        let (major, minor) = (self.major_radius, self.minor_radius);
+        let length = ray.direction.len();
+        let d = ray.direction / length;
+        // Quartic roots lose precision far from the origin, so the ray is restarted where
+        // it enters the bounding sphere and its parameter offset by start afterwards
+        let outer = major + minor;
+        let half_b = ray.origin.dot(d);
+        let c = ray.origin.dot(ray.origin) - outer * outer;
+        let discriminant = half_b * half_b - c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let start = -half_b - discriminant.sqrt();
+        let o = ray.origin + start * d;
+
+        let g = o.dot(d);
+        let s = o.dot(o) + major * major - minor * minor;
+        let four_major2 = 4.0 * major * major;
+        let roots = solve_quartic(
+            4.0 * g,
+            4.0 * g * g + 2.0 * s - four_major2 * (d.x * d.x + d.z * d.z),
+            4.0 * g * s - 2.0 * four_major2 * (o.x * d.x + o.z * d.z),
+            s * s - four_major2 * (o.x * o.x + o.z * o.z),
+        );
+        let t = roots
+            .into_iter()
+            .map(|root| (start + root) / length)
+            .find(|&t| time.contains(t))?;
+
+        let p = ray.at(t);
+        let radial = (p.x * p.x + p.z * p.z).sqrt();
+        // The nearest point on the tube's centre circle
+        let ring = if radial > 0.0 {
+            Vec3::new(p.x, 0.0, p.z) * (major / radial)
+        } else {
+            Vec3::new(major, 0.0, 0.0)
+        };
+        let tube = p.y.atan2(radial - major);
+        Some(LocalHit {
+            t,
+            normal: (p - ring).normalize(),
+            uv: (
+                azimuth(p),
+                if tube < 0.0 { tube / (2.0 * PI) + 1.0 } else { tube / (2.0 * PI) },
+            ),
+            uv_scale: 1.0 / (2.0 * PI * (major * minor).sqrt()),
+        })
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        let local = self.frame.local_ray(ray);
+        match self.local_hit(&local, time) {
+            Some(hit) => {
+                record_local_hit(ray, &self.frame, hit, hit_rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let outer = self.major_radius + self.minor_radius;
+        let minor = self.minor_radius;
+        self.frame
+            .bounds(Vec3::new(-outer, -minor, -outer), Vec3::new(outer, minor, outer))
+    }
+}
+
+// The surface a x^2 + b y^2 + c z^2 + d xy + e xz + f yz + g x + h y + i z + j = 0 given by
+// coefficients [a, ..., j], clipped to the box between min and max since most quadrics are
+// unbounded. The outside is where the left side is positive. uv are a spherical mapping
+// about the centre of the box.
+#[derive(Debug, Clone)]
+pub struct Quadric {
+    pub coefficients: [f64; 10],
+    pub bounds: Aabb,
+}
+
+impl Quadric {
+    pub fn new(coefficients: [f64; 10], min: Point3, max: Point3) -> Self {
+        Quadric {
+            coefficients,
+            bounds: Aabb::new(min, max),
+        }
+    }
+
+    pub fn value(&self, p: Point3) -> f64 {
+        let [a, b, c, d, e, f, g, h, i, j] = self.coefficients;
+        a * p.x * p.x + b * p.y * p.y + c * p.z * p.z
+            + d * p.x * p.y + e * p.x * p.z + f * p.y * p.z
+            + g * p.x + h * p.y + i * p.z
+            + j
+    }
+
+    pub fn gradient(&self, p: Point3) -> Vec3 {
+        let [a, b, c, d, e, f, g, h, i, _] = self.coefficients;
+        Vec3::new(
+            2.0 * a * p.x + d * p.y + e * p.z + g,
+            2.0 * b * p.y + d * p.x + f * p.z + h,
+            2.0 * c * p.z + e * p.x + f * p.y + i,
+        )
+    }
+
+    fn in_bounds(&self, p: Point3) -> bool {
+        (0..3).all(|axis| self.bounds.min[axis] <= p[axis] && p[axis] <= self.bounds.max[axis])
+    }
+}
+
+impl Hittable for Quadric {
+    fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        let [a, b, c, d, e, f, ..] = self.coefficients;
+        let (o, v) = (ray.origin, ray.direction);
+        // The quadratic part alone, evaluated at the direction
+        let quadratic = a * v.x * v.x + b * v.y * v.y + c * v.z * v.z
+            + d * v.x * v.y + e * v.x * v.z + f * v.y * v.z;
+        // The gradient at the origin projected on the direction is the linear coefficient
+        let linear = self.gradient(o).dot(v);
+        let Some((t0, t1)) = solve_quadratic(quadratic, linear, self.value(o)) else {
+            return false;
+        };
+        let Some(t) = [t0, t1]
+            .into_iter()
+            .find(|&t| time.contains(t) && self.in_bounds(ray.at(t)))
+        else {
+            return false;
+        };
+        let gradient = self.gradient(ray.at(t));
+        if gradient.dot(gradient) < 1e-16 {
+            // Singular point, such as the tip of a cone
+            return false;
+        }
+        let center = self.bounds.centroid();
+        let half_diagonal = 0.5 * (self.bounds.max - self.bounds.min).len();
+        hit_rec.t = t;
+        hit_rec.p = ray.at(t);
+        hit_rec.barycentric = (0.0, 0.0);
+        // The centre itself has no direction from the centre; any will do there
+        let offset = hit_rec.p - center;
+        hit_rec.uv = if offset.dot(offset) > 0.0 {
+            Sphere::uv(offset.normalize())
+        } else {
+            (0.0, 0.0)
+        };
+        hit_rec.uv_scale = 1.0 / (SQRT_2 * PI * half_diagonal);
+        hit_rec.set_face_normal(ray, gradient.normalize());
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bounds
+    }
+}
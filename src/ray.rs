@@ -5,10 +5,13 @@ use crate::geometry::Point3;
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vec3,
+    // When the ray was cast, between the camera's shutter times; moving shapes are hit where
+    // they are at this time
+    pub time: f64,
 }
 
 impl Ray {
     pub fn at(&self, t : f64) -> Point3 {
         self.origin + t * self.direction
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,123 @@
+// sampling.rs
+// Piecewise-constant distributions for importance sampling tabulated functions such as
+// environment maps
+
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>, // func.len() + 1 entries, from 0 to 1
+    integral: f64, // Integral of func over [0, 1]
+}
+
+impl Distribution1D {
+    // A function that is zero everywhere is sampled uniformly
+    pub fn new(func: Vec<f64>) -> Self {
+        assert!(!func.is_empty(), "distribution needs at least one value");
+        let n = func.len() as f64;
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        cdf.push(0.0);
+        for value in &func {
+            cdf.push(cdf.last().unwrap() + value.max(0.0) / n);
+        }
+        let integral = *cdf.last().unwrap();
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 { *c / integral } else { i as f64 / n };
+        }
+        *cdf.last_mut().unwrap() = 1.0;
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.func.is_empty()
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    // Maps u in [0, 1) to a point in [0, 1), returning the point, its density and the
+    // index of the segment it falls in
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let n = self.func.len();
+        let segment = (self.cdf.partition_point(|&c| c <= u) - 1).min(n - 1);
+        let width = self.cdf[segment + 1] - self.cdf[segment];
+        let offset = if width > 0.0 {
+            (u - self.cdf[segment]) / width
+        } else {
+            0.0
+        };
+        let x = ((segment as f64 + offset) / n as f64).min(1.0 - f64::EPSILON);
+        (x, self.segment_pdf(segment), segment)
+    }
+
+    // Density of sample() at x in [0, 1)
+    pub fn pdf(&self, x: f64) -> f64 {
+        let segment = ((x * self.func.len() as f64) as usize).min(self.func.len() - 1);
+        self.segment_pdf(segment)
+    }
+
+    fn segment_pdf(&self, segment: usize) -> f64 {
+        if self.integral > 0.0 {
+            self.func[segment].max(0.0) / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+// Samples (u, v) in [0, 1)^2 from a grid of values stored row by row: v picks the row from
+// the marginal distribution, then u is sampled within that row
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(values: &[f64], width: usize, height: usize) -> Self {
+        assert_eq!(values.len(), width * height, "grid size does not match values");
+        let rows: Vec<Distribution1D> = values
+            .chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(Distribution1D::integral).collect());
+        Distribution2D { rows, marginal }
+    }
+
+    // Returns (u, v) and the density with respect to area in the unit square
+    pub fn sample(&self, u1: f64, u2: f64) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample(u2);
+        let (u, pdf_u, _) = self.rows[row].sample(u1);
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = ((v * self.rows.len() as f64) as usize).min(self.rows.len() - 1);
+        self.marginal.pdf(v) * self.rows[row].pdf(u)
+    }
+}
+
+// Weight for a sample drawn with density pdf when other_pdf is the density the competing
+// strategy would have given the same sample
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
+}
+
+// Converts a density per unit area into one per unit solid angle, for a point at distance
+// whose surface normal makes an angle with the given cosine to the line of sight
+pub fn area_to_solid_angle(pdf_area: f64, distance: f64, cosine: f64) -> f64 {
+    if cosine <= 0.0 {
+        return 0.0;
+    }
+    pdf_area * distance * distance / cosine
+}
@@ -0,0 +1,1109 @@
+// scene.rs
+// Loads a camera and World from a TOML scene description. The format is described in
+// docs/scene_format.md, and scenes/ holds examples.
+use crate::background::{
+    EnvironmentBackground, GradientBackground, SolidBackground, VoidBackground,
+};
+use crate::camera::Camera;
+use crate::csg::{Csg, CsgOperation};
+use crate::geometry::{Point3, Vec3};
+use crate::image::{ImageError, is_high_dynamic_range, load_hdr, load_image};
+use crate::material::{
+    BLACK, Color, Conductor, ConductorFresnel, ConductorPreset, Dielectric, DiffuseLight,
+    HenyeyGreenstein, Isotropic, Lambertian, WHITE,
+};
+use crate::medium::{ConstantMedium, Medium};
+use crate::obj::{ObjError, read_obj};
+use crate::planar::{Cuboid, Disk, Plane, Quad};
+use crate::quadric::{Cone, Cylinder, Quadric, Torus};
+use crate::sdf::{Sdf, SdfNode};
+use crate::shapes::{Shape, Sphere, World};
+use crate::texture::{
+    CheckerTexture, ImageTexture, NoisePattern, NoiseTexture, Texture, TextureFilter, WrapMode,
+    solid,
+};
+use crate::transform::{AnimatedTransform, Instance, Matrix4, Transform};
+use crate::triangle::Triangle;
+use crate::volume::{GridMedium, VolumeError, load_grid};
+use serde::de::value::{MapAccessDeserializer, SeqAccessDeserializer};
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+pub struct Scene {
+    pub camera: CameraSettings,
+    pub world: World,
+}
+
+// The arguments of Camera::new, kept separately so they can be adjusted before the camera is built
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraSettings {
+    pub aspect_ratio: f64,
+    pub image_width: u32,
+    pub location: Point3,
+    pub view_target: Point3,
+    #[serde(default)]
+    pub focal_length: f64,
+    #[serde(default)]
+    pub focal_angle: f64, // Degrees; 0 gives a pinhole camera with everything in focus
+    pub vfov: f64,
+    #[serde(default = "default_samples")]
+    pub samples: u32,
+    #[serde(default = "default_max_depth")]
+    pub max_depth: i32,
+    #[serde(default)]
+    pub shutter_open: f64,
+    #[serde(default)]
+    pub shutter_close: f64,
+}
+
+fn default_samples() -> u32 {
+    40
+}
+
+fn default_max_depth() -> i32 {
+    50
+}
+
+impl CameraSettings {
+    pub fn build(&self) -> Camera {
+        let mut camera = Camera::new(
+            self.aspect_ratio,
+            self.image_width,
+            self.location,
+            self.view_target,
+            self.focal_length,
+            self.focal_angle,
+            self.vfov,
+            self.samples,
+            self.max_depth,
+        );
+        camera.shutter_open = self.shutter_open;
+        camera.shutter_close = self.shutter_close;
+        camera
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneFile {
+    #[serde(default)]
+    background: Option<BackgroundDesc>,
+    camera: CameraSettings,
+    #[serde(default)]
+    materials: BTreeMap<String, MaterialDesc>,
+    // Geometry placed by instance objects rather than directly
+    #[serde(default)]
+    shapes: BTreeMap<String, ObjectDesc>,
+    #[serde(default)]
+    objects: Vec<ObjectDesc>,
+    #[serde(default)]
+    media: Vec<MediumDesc>,
+}
+
+// Fog, smoke or cloud. material should be a phase function, isotropic or henyey_greenstein.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MediumDesc {
+    // Fills the inside of a closed shape from the shapes table
+    Constant {
+        boundary: String,
+        density: f64, // Chance of scattering or absorption per unit distance
+        material: String,
+    },
+    // A voxel grid file stretched over the box from min to max
+    Grid {
+        path: PathBuf,
+        min: Point3,
+        max: Point3,
+        #[serde(default = "default_scale")]
+        density: f64, // Multiplies the grid's densities
+        #[serde(default = "default_scale")]
+        emission: f64, // Multiplies the glow of a temperature channel
+        material: String,
+    },
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundDesc {
+    Solid {
+        color: Color,
+    },
+    Gradient {
+        #[serde(default = "default_horizon")]
+        horizon: Color,
+        #[serde(default = "default_zenith")]
+        zenith: Color,
+    },
+    Void,
+    Environment {
+        path: PathBuf,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+}
+
+fn default_intensity() -> f64 {
+    1.0
+}
+
+fn default_horizon() -> Color {
+    GradientBackground::default().horizon
+}
+
+fn default_zenith() -> Color {
+    GradientBackground::default().zenith
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: TextureSpec,
+    },
+    Metal {
+        albedo: TextureSpec,
+        #[serde(default)]
+        fuzz: TextureSpec,
+        #[serde(default)]
+        fuzz_v: Option<TextureSpec>,
+        #[serde(default = "default_tangent")]
+        tangent: Vec3,
+    },
+    Conductor {
+        metal: ConductorDesc,
+        #[serde(default)]
+        roughness: TextureSpec,
+        #[serde(default)]
+        roughness_v: Option<TextureSpec>,
+        #[serde(default = "default_tangent")]
+        tangent: Vec3,
+    },
+    Dielectric {
+        refraction_index: f64,
+    },
+    DiffuseLight {
+        emit: Color,
+    },
+    Isotropic {
+        albedo: TextureSpec,
+    },
+    HenyeyGreenstein {
+        albedo: TextureSpec,
+        g: f64,
+    },
+}
+
+// A preset name such as "gold", or a table giving eta and k
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConductorDesc {
+    Preset(ConductorPreset),
+    Measured { eta: Color, k: Color },
+}
+
+fn default_tangent() -> Vec3 {
+    Vec3::new(1.0, 0.0, 0.0)
+}
+
+// A colour that may vary over a surface: a plain [r, g, b], a number for grey, or an inline
+// texture table
+enum TextureSpec {
+    Color(Color),
+    Texture(Box<TextureDesc>),
+}
+
+impl Default for TextureSpec {
+    fn default() -> Self {
+        TextureSpec::Color(Color::default())
+    }
+}
+
+impl<'de> Deserialize<'de> for TextureSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SpecVisitor;
+
+        impl<'de> Visitor<'de> for SpecVisitor {
+            type Value = TextureSpec;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a number, a colour [r, g, b] or a texture table")
+            }
+
+            fn visit_f64<E: de::Error>(self, value: f64) -> Result<TextureSpec, E> {
+                Ok(TextureSpec::Color(Color::new(value, value, value)))
+            }
+
+            fn visit_i64<E: de::Error>(self, value: i64) -> Result<TextureSpec, E> {
+                self.visit_f64(value as f64)
+            }
+
+            fn visit_u64<E: de::Error>(self, value: u64) -> Result<TextureSpec, E> {
+                self.visit_f64(value as f64)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<TextureSpec, A::Error> {
+                Color::deserialize(SeqAccessDeserializer::new(seq)).map(TextureSpec::Color)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<TextureSpec, A::Error> {
+                TextureDesc::deserialize(MapAccessDeserializer::new(map))
+                    .map(|desc| TextureSpec::Texture(Box::new(desc)))
+            }
+        }
+
+        deserializer.deserialize_any(SpecVisitor)
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid {
+        color: Color,
+    },
+    Checker {
+        #[serde(default = "default_checker_scale")]
+        scale: f64,
+        even: TextureSpec,
+        odd: TextureSpec,
+    },
+    // PNG, PPM or Radiance HDR image over the surface's texture coordinates. PNG and PPM
+    // colours are decoded from sRGB unless srgb = false, e.g. for maps that hold data.
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        wrap: WrapMode,
+        #[serde(default)]
+        filter: TextureFilter,
+        #[serde(default)]
+        srgb: Option<bool>,
+    },
+    // Procedural pattern blending from low (default black) to high (default white)
+    Noise {
+        pattern: NoisePattern,
+        #[serde(default = "default_noise_scale")]
+        scale: f64,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+        #[serde(default)]
+        seed: u64,
+        #[serde(default)]
+        low: Option<TextureSpec>,
+        #[serde(default)]
+        high: Option<TextureSpec>,
+    },
+}
+
+fn default_checker_scale() -> f64 {
+    1.0
+}
+
+fn default_noise_scale() -> f64 {
+    1.0
+}
+
+fn default_octaves() -> u32 {
+    1
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ObjectDesc {
+    Sphere {
+        center: Point3,
+        radius: f64,
+        material: String,
+        #[serde(default)]
+        label: String,
+        #[serde(default)]
+        velocity: Vec3, // Distance moved between times 0 and 1
+    },
+    Triangle {
+        vertices: [Point3; 3],
+        #[serde(default)]
+        normals: Option<[Vec3; 3]>,
+        #[serde(default)]
+        uvs: Option<[(f64, f64); 3]>,
+        material: String,
+    },
+    // Parallelogram with edges u and v leaving corner; it faces along u x v
+    Quad {
+        corner: Point3,
+        u: Vec3,
+        v: Vec3,
+        material: String,
+    },
+    Plane {
+        point: Point3,
+        normal: Vec3,
+        material: String,
+    },
+    Disk {
+        center: Point3,
+        normal: Vec3,
+        radius: f64,
+        material: String,
+    },
+    // Axis-aligned, between two opposite corners
+    Box {
+        min: Point3,
+        max: Point3,
+        material: String,
+    },
+    Cylinder {
+        base: Point3,
+        top: Point3,
+        radius: f64,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        material: String,
+    },
+    Cone {
+        base: Point3,
+        apex: Point3,
+        radius: f64,
+        #[serde(default = "default_capped")]
+        capped: bool,
+        material: String,
+    },
+    Torus {
+        center: Point3,
+        #[serde(default = "default_axis")]
+        axis: Vec3,
+        major_radius: f64,
+        minor_radius: f64,
+        material: String,
+    },
+    // Coefficients of x^2, y^2, z^2, xy, xz, yz, x, y, z and 1, clipped to the box
+    Quadric {
+        coefficients: [f64; 10],
+        min: Point3,
+        max: Point3,
+        material: String,
+    },
+    // Faces without a usemtl statement use material, or a grey Lambertian if none is given
+    Mesh {
+        path: PathBuf,
+        #[serde(default)]
+        material: Option<String>,
+    },
+    // A copy of the named entry of the shapes table, placed by transform and then moved by
+    // keyframes. material replaces the shape's own materials if given.
+    Instance {
+        shape: String,
+        #[serde(default)]
+        transform: Vec<TransformStep>,
+        #[serde(default)]
+        keyframes: Vec<KeyframeDesc>,
+        #[serde(default)]
+        material: Option<String>,
+    },
+    // Two named shapes from the shapes table combined into one solid
+    Csg {
+        operation: CsgOperation,
+        left: String,
+        right: String,
+        material: String,
+    },
+    Sdf {
+        field: SdfNode,
+        material: String,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyframeDesc {
+    time: f64,
+    transform: Vec<TransformStep>,
+}
+
+// One step of an instance's transform; steps apply in the order listed
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformStep {
+    Translate(Vec3),
+    Scale(ScaleDesc),
+    Rotate { axis: Vec3, angle: f64 }, // Degrees, counterclockwise looking down the axis
+    Matrix([[f64; 4]; 4]),             // Rows of a matrix acting on column vectors
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleDesc {
+    Uniform(f64),
+    Axes(Vec3),
+}
+
+// None if a step cannot be inverted, such as a scale by zero
+fn build_transform(steps: &[TransformStep]) -> Option<Transform> {
+    steps.iter().try_fold(Transform::identity(), |transform, step| {
+        let step = match step {
+            TransformStep::Translate(offset) => Transform::translate(*offset),
+            TransformStep::Scale(scale) => {
+                let factors = match scale {
+                    ScaleDesc::Uniform(factor) => Vec3::new(*factor, *factor, *factor),
+                    ScaleDesc::Axes(factors) => *factors,
+                };
+                if (0..3).any(|axis| factors[axis] == 0.0) {
+                    return None;
+                }
+                Transform::scale(factors)
+            }
+            TransformStep::Rotate { axis, angle } => {
+                if axis.dot(*axis) == 0.0 {
+                    return None;
+                }
+                Transform::rotate(*axis, *angle)
+            }
+            TransformStep::Matrix(rows) => Transform::new(Matrix4 { m: *rows })?,
+        };
+        Some(transform.then(&step))
+    })
+}
+
+fn default_capped() -> bool {
+    true
+}
+
+fn default_axis() -> Vec3 {
+    Vec3::new(0.0, 1.0, 0.0)
+}
+
+impl ObjectDesc {
+    fn kind(&self) -> &'static str {
+        match self {
+            ObjectDesc::Sphere { .. } => "sphere",
+            ObjectDesc::Triangle { .. } => "triangle",
+            ObjectDesc::Quad { .. } => "quad",
+            ObjectDesc::Plane { .. } => "plane",
+            ObjectDesc::Disk { .. } => "disk",
+            ObjectDesc::Box { .. } => "box",
+            ObjectDesc::Cylinder { .. } => "cylinder",
+            ObjectDesc::Cone { .. } => "cone",
+            ObjectDesc::Torus { .. } => "torus",
+            ObjectDesc::Quadric { .. } => "quadric",
+            ObjectDesc::Mesh { .. } => "mesh",
+            ObjectDesc::Instance { .. } => "instance",
+            ObjectDesc::Csg { .. } => "csg",
+            ObjectDesc::Sdf { .. } => "sdf",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    // Syntax errors, missing fields and unknown fields or types, with their location in the file
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    UnknownMaterial {
+        path: PathBuf,
+        object: String, // Which object, e.g. "object 3 (sphere)" or "shape 'bolt' (mesh)"
+        name: String,
+        known: Vec<String>,
+    },
+    UnknownShape {
+        path: PathBuf,
+        object: String,
+        name: String,
+        known: Vec<String>,
+    },
+    // A shape that is, through instances or CSG, part of itself
+    ShapeCycle {
+        path: PathBuf,
+        name: String,
+    },
+    // A transform that squashes space flat, so rays cannot be carried into object space
+    SingularTransform {
+        path: PathBuf,
+        object: String,
+    },
+    // A shape whose sizes or directions leave nothing to render, such as a quad with parallel
+    // edges, a flat box, a cylinder of zero length or a torus whose tube is wider than its ring
+    DegenerateShape {
+        path: PathBuf,
+        object: String,
+    },
+    Obj(ObjError),
+    Image(ImageError),
+    Volume(VolumeError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Parse { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::UnknownMaterial {
+                path,
+                object,
+                name,
+                known,
+            } => {
+                write!(
+                    f,
+                    "{}: {} uses unknown material '{}'",
+                    path.display(),
+                    object,
+                    name
+                )?;
+                if known.is_empty() {
+                    write!(f, "; no materials are defined")
+                } else {
+                    write!(f, "; defined materials are: {}", known.join(", "))
+                }
+            }
+            SceneError::UnknownShape {
+                path,
+                object,
+                name,
+                known,
+            } => {
+                write!(f, "{}: {} uses unknown shape '{}'", path.display(), object, name)?;
+                if known.is_empty() {
+                    write!(f, "; no shapes are defined")
+                } else {
+                    write!(f, "; defined shapes are: {}", known.join(", "))
+                }
+            }
+            SceneError::ShapeCycle { path, name } => {
+                write!(f, "{}: shape '{}' is part of itself", path.display(), name)
+            }
+            SceneError::SingularTransform { path, object } => {
+                write!(f, "{}: {} has a transform that cannot be inverted", path.display(), object)
+            }
+            SceneError::DegenerateShape { path, object } => {
+                write!(f, "{}: {} has a zero or invalid size or direction", path.display(), object)
+            }
+            SceneError::Obj(e) => write!(f, "{}", e),
+            SceneError::Image(e) => write!(f, "{}", e),
+            SceneError::Volume(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            SceneError::Parse { source, .. } => Some(source),
+            SceneError::UnknownMaterial { .. }
+            | SceneError::UnknownShape { .. }
+            | SceneError::ShapeCycle { .. }
+            | SceneError::SingularTransform { .. }
+            | SceneError::DegenerateShape { .. } => None,
+            SceneError::Obj(e) => Some(e),
+            SceneError::Image(e) => Some(e),
+            SceneError::Volume(e) => Some(e),
+        }
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(e: ObjError) -> Self {
+        SceneError::Obj(e)
+    }
+}
+
+impl From<ImageError> for SceneError {
+    fn from(e: ImageError) -> Self {
+        SceneError::Image(e)
+    }
+}
+
+impl From<VolumeError> for SceneError {
+    fn from(e: VolumeError) -> Self {
+        SceneError::Volume(e)
+    }
+}
+
+// roughness_v defaults to roughness, for an isotropic surface
+fn build_conductor(
+    fresnel: ConductorFresnel,
+    roughness: &TextureSpec,
+    roughness_v: Option<&TextureSpec>,
+    tangent: Vec3,
+    directory: &Path,
+) -> Result<Conductor, SceneError> {
+    let roughness_u = build_texture(roughness, directory)?;
+    let roughness_v = match roughness_v {
+        Some(spec) => build_texture(spec, directory)?,
+        None => Arc::clone(&roughness_u),
+    };
+    Ok(Conductor {
+        fresnel,
+        roughness_u,
+        roughness_v,
+        tangent,
+    })
+}
+
+fn build_texture(spec: &TextureSpec, directory: &Path) -> Result<Arc<dyn Texture>, SceneError> {
+    let desc = match spec {
+        TextureSpec::Color(color) => return Ok(solid(*color)),
+        TextureSpec::Texture(desc) => desc,
+    };
+    Ok(match desc.as_ref() {
+        TextureDesc::Solid { color } => solid(*color),
+        TextureDesc::Checker { scale, even, odd } => Arc::new(CheckerTexture {
+            scale: *scale,
+            even: build_texture(even, directory)?,
+            odd: build_texture(odd, directory)?,
+        }),
+        TextureDesc::Image {
+            path,
+            wrap,
+            filter,
+            srgb,
+        } => {
+            let path = directory.join(path);
+            let mut image = load_image(&path)?;
+            if srgb.unwrap_or(!is_high_dynamic_range(&path)) {
+                image.srgb_to_linear();
+            }
+            Arc::new(ImageTexture::new(image, *wrap, *filter))
+        }
+        TextureDesc::Noise {
+            pattern,
+            scale,
+            octaves,
+            seed,
+            low,
+            high,
+        } => {
+            let low = match low {
+                Some(low) => build_texture(low, directory)?,
+                None => solid(BLACK),
+            };
+            let high = match high {
+                Some(high) => build_texture(high, directory)?,
+                None => solid(WHITE),
+            };
+            Arc::new(NoiseTexture::new(*pattern, *scale, *octaves, *seed, low, high))
+        }
+    })
+}
+
+// Turns object descriptions into shapes. Named shapes are built the first time an instance
+// refers to them and shared by every later instance.
+struct ObjectBuilder<'a> {
+    path: &'a Path,
+    directory: &'a Path,
+    materials: HashMap<String, usize>,
+    shape_names: Vec<String>, // Every name in the shapes table, in order
+    shape_descs: BTreeMap<String, ObjectDesc>, // Shapes not built yet
+    shapes: HashMap<String, Vec<(Arc<Shape>, usize)>>,
+}
+
+impl ObjectBuilder<'_> {
+    // location names the object being built in error messages
+    fn material(&self, name: &str, location: &str) -> Result<usize, SceneError> {
+        self.materials
+            .get(name)
+            .copied()
+            .ok_or_else(|| SceneError::UnknownMaterial {
+                path: self.path.to_path_buf(),
+                object: location.to_string(),
+                name: name.to_string(),
+                known: {
+                    let mut known: Vec<String> = self.materials.keys().cloned().collect();
+                    known.sort();
+                    known
+                },
+            })
+    }
+
+    fn degenerate(&self, location: &str) -> SceneError {
+        SceneError::DegenerateShape {
+            path: self.path.to_path_buf(),
+            object: location.to_string(),
+        }
+    }
+
+    // The parts of a named shape, each with its material
+    fn shape(
+        &mut self,
+        name: &str,
+        location: &str,
+        world: &mut World,
+    ) -> Result<Vec<(Arc<Shape>, usize)>, SceneError> {
+        if let Some(parts) = self.shapes.get(name) {
+            return Ok(parts.clone());
+        }
+        let Some(desc) = self.shape_descs.remove(name) else {
+            // A known shape that is neither built nor waiting is still being built
+            return Err(if self.shape_names.iter().any(|known| known == name) {
+                SceneError::ShapeCycle {
+                    path: self.path.to_path_buf(),
+                    name: name.to_string(),
+                }
+            } else {
+                SceneError::UnknownShape {
+                    path: self.path.to_path_buf(),
+                    object: location.to_string(),
+                    name: name.to_string(),
+                    known: self.shape_names.clone(),
+                }
+            });
+        };
+        let shape_location = format!("shape '{}' ({})", name, desc.kind());
+        let parts: Vec<(Arc<Shape>, usize)> = self
+            .build(&desc, &shape_location, world)?
+            .into_iter()
+            .map(|(shape, material)| (Arc::new(shape), material))
+            .collect();
+        self.shapes.insert(name.to_string(), parts.clone());
+        Ok(parts)
+    }
+
+    // The shapes desc describes, each with its material. Meshes with several materials and
+    // instances of them give more than one.
+    fn build(
+        &mut self,
+        desc: &ObjectDesc,
+        location: &str,
+        world: &mut World,
+    ) -> Result<Vec<(Shape, usize)>, SceneError> {
+        Ok(match desc {
+            ObjectDesc::Sphere {
+                center,
+                radius,
+                material,
+                label,
+                velocity,
+            } => {
+                let material = self.material(material, location)?;
+                let sphere = Sphere {
+                    label: label.clone(),
+                    center: *center,
+                    radius: *radius,
+                    velocity: *velocity,
+                };
+                vec![(Shape::Sphere(sphere), material)]
+            }
+            ObjectDesc::Triangle {
+                vertices,
+                normals,
+                uvs,
+                material,
+            } => {
+                let material = self.material(material, location)?;
+                let [a, b, c] = *vertices;
+                let mut triangle = Triangle::new(a, b, c);
+                triangle.normals = *normals;
+                triangle.uvs = *uvs;
+                vec![(Shape::Triangle(triangle), material)]
+            }
+            ObjectDesc::Quad {
+                corner,
+                u,
+                v,
+                material,
+            } => {
+                let material = self.material(material, location)?;
+                let quad = Quad::new(*corner, *u, *v).ok_or_else(|| self.degenerate(location))?;
+                vec![(Shape::Quad(quad), material)]
+            }
+            ObjectDesc::Plane {
+                point,
+                normal,
+                material,
+            } => {
+                let material = self.material(material, location)?;
+                let plane = Plane::new(*point, *normal).ok_or_else(|| self.degenerate(location))?;
+                vec![(Shape::Plane(plane), material)]
+            }
+            ObjectDesc::Disk {
+                center,
+                normal,
+                radius,
+                material,
+            } => {
+                let material = self.material(material, location)?;
+                let disk =
+                    Disk::new(*center, *normal, *radius).ok_or_else(|| self.degenerate(location))?;
+                vec![(Shape::Disk(disk), material)]
+            }
+            ObjectDesc::Box { min, max, material } => {
+                let material = self.material(material, location)?;
+                let cuboid = Cuboid::new(*min, *max).ok_or_else(|| self.degenerate(location))?;
+                vec![(Shape::Box(cuboid), material)]
+            }
+            ObjectDesc::Cylinder {
+                base,
+                top,
+                radius,
+                capped,
+                material,
+            } => {
+                let material = self.material(material, location)?;
+                let cylinder = Cylinder::new(*base, *top, *radius, *capped)
+                    .ok_or_else(|| self.degenerate(location))?;
+                vec![(Shape::Cylinder(cylinder), material)]
+            }
+            ObjectDesc::Cone {
+                base,
+                apex,
+                radius,
+                capped,
+                material,
+            } => {
+                let material = self.material(material, location)?;
+                let cone = Cone::new(*base, *apex, *radius, *capped)
+                    .ok_or_else(|| self.degenerate(location))?;
+                vec![(Shape::Cone(cone), material)]
+            }
+            ObjectDesc::Torus {
+                center,
+                axis,
+                major_radius,
+                minor_radius,
+                material,
+            } => {
+                let material = self.material(material, location)?;
+                let torus = Torus::new(*center, *axis, *major_radius, *minor_radius)
+                    .ok_or_else(|| self.degenerate(location))?;
+                vec![(Shape::Torus(torus), material)]
+            }
+            ObjectDesc::Quadric {
+                coefficients,
+                min,
+                max,
+                material,
+            } => {
+                let material = self.material(material, location)?;
+                let quadric = Quadric::new(*coefficients, *min, *max);
+                vec![(Shape::Quadric(quadric), material)]
+            }
+            ObjectDesc::Mesh {
+                path: mesh_path,
+                material,
+            } => {
+                let material = match material {
+                    Some(name) => self.material(name, location)?,
+                    None => world.add_material(Lambertian {
+                        albedo: solid(Color::new(0.8, 0.8, 0.8)),
+                    }),
+                };
+                read_obj(&self.directory.join(mesh_path), world, material)?
+                    .into_iter()
+                    .map(|(mesh, material)| (Shape::Mesh(mesh), material))
+                    .collect()
+            }
+            ObjectDesc::Instance {
+                shape,
+                transform,
+                keyframes,
+                material,
+            } => {
+                let parts = self.shape(shape, location, world)?;
+                let singular = || SceneError::SingularTransform {
+                    path: self.path.to_path_buf(),
+                    object: location.to_string(),
+                };
+                let placement = build_transform(transform).ok_or_else(singular)?;
+                let transform = if keyframes.is_empty() {
+                    AnimatedTransform::fixed(placement)
+                } else {
+                    let keyframes = keyframes
+                        .iter()
+                        .map(|keyframe| {
+                            let motion = build_transform(&keyframe.transform)?;
+                            Some((keyframe.time, placement.then(&motion)))
+                        })
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(singular)?;
+                    AnimatedTransform::new(keyframes)
+                };
+                let material = match material {
+                    Some(name) => Some(self.material(name, location)?),
+                    None => None,
+                };
+                parts
+                    .into_iter()
+                    .map(|(part, part_material)| {
+                        let instance = Instance::animated(part, transform.clone());
+                        (Shape::Instance(instance), material.unwrap_or(part_material))
+                    })
+                    .collect()
+            }
+            ObjectDesc::Csg {
+                operation,
+                left,
+                right,
+                material,
+            } => {
+                let material = self.material(material, location)?;
+                let mut operand = |name: &str| -> Result<Vec<Arc<Shape>>, SceneError> {
+                    let parts = self.shape(name, location, world)?;
+                    Ok(parts.into_iter().map(|(part, _)| part).collect())
+                };
+                let csg = Csg::new(*operation, operand(left)?, operand(right)?);
+                vec![(Shape::Csg(csg), material)]
+            }
+            ObjectDesc::Sdf { field, material } => {
+                let material = self.material(material, location)?;
+                vec![(Shape::Sdf(Sdf::new(field.clone())), material)]
+            }
+        })
+    }
+}
+
+// Reads and parses the scene file at path, returning a World with its BVH built
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let source = fs::read_to_string(path).map_err(|source| SceneError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_scene(&source, path)
+}
+
+// Parses scene source text. path is used for error messages and to resolve relative paths.
+pub fn parse_scene(source: &str, path: &Path) -> Result<Scene, SceneError> {
+    let file: SceneFile = toml::from_str(source).map_err(|source| SceneError::Parse {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    let mut world = World::new();
+    match file.background {
+        Some(BackgroundDesc::Solid { color }) => {
+            world.background = Box::new(SolidBackground { color })
+        }
+        Some(BackgroundDesc::Gradient { horizon, zenith }) => {
+            world.background = Box::new(GradientBackground { horizon, zenith })
+        }
+        Some(BackgroundDesc::Void) => world.background = Box::new(VoidBackground),
+        Some(BackgroundDesc::Environment {
+            path: map_path,
+            rotation,
+            intensity,
+        }) => {
+            let image = load_hdr(&directory.join(map_path))?;
+            world.background = Box::new(EnvironmentBackground::new(image, rotation, intensity));
+        }
+        None => {}
+    }
+    let mut materials: HashMap<String, usize> = HashMap::new();
+    for (name, desc) in file.materials {
+        let index = match desc {
+            MaterialDesc::Lambertian { albedo } => world.add_material(Lambertian {
+                albedo: build_texture(&albedo, directory)?,
+            }),
+            MaterialDesc::Metal {
+                albedo,
+                fuzz,
+                fuzz_v,
+                tangent,
+            } => {
+                let fresnel = ConductorFresnel::Schlick(build_texture(&albedo, directory)?);
+                let conductor =
+                    build_conductor(fresnel, &fuzz, fuzz_v.as_ref(), tangent, directory)?;
+                world.add_material(conductor)
+            }
+            MaterialDesc::Conductor {
+                metal,
+                roughness,
+                roughness_v,
+                tangent,
+            } => {
+                let fresnel = match metal {
+                    ConductorDesc::Preset(preset) => preset.fresnel(),
+                    ConductorDesc::Measured { eta, k } => ConductorFresnel::Complex { eta, k },
+                };
+                let conductor =
+                    build_conductor(fresnel, &roughness, roughness_v.as_ref(), tangent, directory)?;
+                world.add_material(conductor)
+            }
+            MaterialDesc::Dielectric { refraction_index } => {
+                world.add_material(Dielectric { refraction_index })
+            }
+            MaterialDesc::DiffuseLight { emit } => world.add_material(DiffuseLight { emit }),
+            MaterialDesc::Isotropic { albedo } => world.add_material(Isotropic {
+                albedo: build_texture(&albedo, directory)?,
+            }),
+            MaterialDesc::HenyeyGreenstein { albedo, g } => {
+                world.add_material(HenyeyGreenstein {
+                    albedo: build_texture(&albedo, directory)?,
+                    g,
+                })
+            }
+        };
+        materials.insert(name, index);
+    }
+
+    let mut builder = ObjectBuilder {
+        path,
+        directory,
+        materials,
+        shape_names: file.shapes.keys().cloned().collect(),
+        shape_descs: file.shapes,
+        shapes: HashMap::new(),
+    };
+    for (i, desc) in file.objects.iter().enumerate() {
+        let location = format!("object {} ({})", i + 1, desc.kind());
+        for (shape, material) in builder.build(desc, &location, &mut world)? {
+            world.add_object(shape, material);
+        }
+    }
+    for (i, desc) in file.media.iter().enumerate() {
+        let location = format!("medium {}", i + 1);
+        match desc {
+            MediumDesc::Constant {
+                boundary,
+                density,
+                material,
+            } => {
+                let material = builder.material(material, &location)?;
+                let boundary = builder
+                    .shape(boundary, &location, &mut world)?
+                    .into_iter()
+                    .map(|(part, _)| part)
+                    .collect();
+                let medium = ConstantMedium::new(boundary, *density);
+                world.add_medium(Medium::Constant(medium), material);
+            }
+            MediumDesc::Grid {
+                path: grid_path,
+                min,
+                max,
+                density,
+                emission,
+                material,
+            } => {
+                let material = builder.material(material, &location)?;
+                let grid = load_grid(&directory.join(grid_path))?;
+                let medium = GridMedium::new(grid, *min, *max, *density, *emission);
+                world.add_medium(Medium::Grid(medium), material);
+            }
+        }
+    }
+
+    world.build_bvh();
+    Ok(Scene {
+        camera: file.camera,
+        world,
+    })
+}
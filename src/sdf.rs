@@ -0,0 +1,306 @@
+// sdf.rs
+// Shapes given by signed distance functions: primitives blended smoothly together and
+// repeated through space, found along a ray by sphere tracing. Suits organic and fractal
+// forms that have no closed-form intersection.
+use crate::aabb::Aabb;
+use crate::geometry::{Point3, Vec3};
+use crate::math::Interval;
+use crate::ray::Ray;
+use crate::shapes::{HitRecord, Hittable};
+use serde::Deserialize;
+
+// Steps taken along one ray before giving up, as on rays that skim along the surface
+const MAX_STEPS: usize = 1024;
+// Shortest step. Once the surface is nearer than this the march steps across it, and the
+// crossing is then narrowed down by bisection.
+const MIN_STEP: f64 = 1e-4;
+// Length the crossing is narrowed down to, within a number of halvings
+const BISECTION_TOLERANCE: f64 = 1e-10;
+const MAX_BISECTIONS: usize = 60;
+// Offset for the central differences that give normals
+const NORMAL_STEP: f64 = 1e-6;
+
+// A distance function: negative inside, and never more than the distance to the surface, so
+// that a sphere of that radius is sure to be clear of it. Smooth blends and repetition keep
+// to this only roughly, which sphere tracing tolerates.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SdfNode {
+    Sphere {
+        center: Point3,
+        radius: f64,
+    },
+    Box {
+        center: Point3,
+        half_size: Vec3,
+    },
+    // Lies in the xz plane, around the y axis through center
+    Torus {
+        center: Point3,
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    // The points within radius of the segment from a to b
+    Capsule {
+        a: Point3,
+        b: Point3,
+        radius: f64,
+    },
+    // smoothness is the distance over which the surfaces of the parts blend; zero gives
+    // sharp creases
+    SmoothUnion {
+        smoothness: f64,
+        parts: Vec<SdfNode>,
+    },
+    SmoothIntersect {
+        smoothness: f64,
+        parts: Vec<SdfNode>,
+    },
+    // The first part with the rest cut away
+    SmoothSubtract {
+        smoothness: f64,
+        parts: Vec<SdfNode>,
+    },
+    // Copies of part spaced along each axis with a non-zero spacing, count more on each side
+    // of the original, or endlessly if count is not given. part should fit in the cell of
+    // space around the origin that each copy is given.
+    Repeat {
+        spacing: Vec3,
+        #[serde(default)]
+        count: Option<[u32; 3]>,
+        part: Box<SdfNode>,
+    },
+}
+
+// Polynomial smooth minimum, which pulls the surface out by at most k / 4 where parts meet
+fn smooth_min(a: f64, b: f64, k: f64) -> f64 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (k - (a - b).abs()).max(0.0) / k;
+    a.min(b) - 0.25 * h * h * k
+}
+
+fn smooth_max(a: f64, b: f64, k: f64) -> f64 {
+    -smooth_min(-a, -b, k)
+}
+
+impl SdfNode {
+    pub fn distance(&self, p: Point3) -> f64 {
+        match self {
+            SdfNode::Sphere { center, radius } => (p - *center).len() - radius,
+            SdfNode::Box { center, half_size } => {
+                let offset = p - *center;
+                let q = Vec3::new(
+                    offset.x.abs() - half_size.x,
+                    offset.y.abs() - half_size.y,
+                    offset.z.abs() - half_size.z,
+                );
+                let outside = Vec3::max(q, Vec3::default()).len();
+                outside + q.x.max(q.y).max(q.z).min(0.0)
+            }
+            SdfNode::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let offset = p - *center;
+                let rho = (offset.x * offset.x + offset.z * offset.z).sqrt() - major_radius;
+                (rho * rho + offset.y * offset.y).sqrt() - minor_radius
+            }
+            SdfNode::Capsule { a, b, radius } => {
+                let (pa, ba) = (p - *a, *b - *a);
+                let length_squared = ba.dot(ba);
+                let h = if length_squared > 0.0 {
+                    (pa.dot(ba) / length_squared).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                };
+                (pa - h * ba).len() - radius
+            }
+            SdfNode::SmoothUnion { smoothness, parts } => parts
+                .iter()
+                .map(|part| part.distance(p))
+                .reduce(|a, b| smooth_min(a, b, *smoothness))
+                .unwrap_or(f64::INFINITY),
+            SdfNode::SmoothIntersect { smoothness, parts } => parts
+                .iter()
+                .map(|part| part.distance(p))
+                .reduce(|a, b| smooth_max(a, b, *smoothness))
+                .unwrap_or(f64::INFINITY),
+            SdfNode::SmoothSubtract { smoothness, parts } => parts
+                .iter()
+                .map(|part| part.distance(p))
+                .reduce(|a, b| smooth_max(a, -b, *smoothness))
+                .unwrap_or(f64::INFINITY),
+            SdfNode::Repeat {
+                spacing,
+                count,
+                part,
+            } => {
+                let mut q = p;
+                for axis in 0..3 {
+                    if spacing[axis] > 0.0 {
+                        let mut cell = (p[axis] / spacing[axis]).round();
+                        if let Some(count) = count {
+                            let limit = count[axis] as f64;
+                            cell = cell.clamp(-limit, limit);
+                        }
+                        q[axis] = p[axis] - spacing[axis] * cell;
+                    }
+                }
+                part.distance(q)
+            }
+        }
+    }
+
+    // A box around everywhere the distance is negative
+    pub fn bounds(&self) -> Aabb {
+        match self {
+            SdfNode::Sphere { center, radius } => {
+                let extent = Vec3::new(*radius, *radius, *radius);
+                Aabb::new(*center - extent, *center + extent)
+            }
+            SdfNode::Box { center, half_size } => {
+                Aabb::new(*center - *half_size, *center + *half_size)
+            }
+            SdfNode::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let across = major_radius + minor_radius;
+                let extent = Vec3::new(across, *minor_radius, across);
+                Aabb::new(*center - extent, *center + extent)
+            }
+            SdfNode::Capsule { a, b, radius } => {
+                let extent = Vec3::new(*radius, *radius, *radius);
+                Aabb::new(Vec3::min(*a, *b) - extent, Vec3::max(*a, *b) + extent)
+            }
+            SdfNode::SmoothUnion { smoothness, parts } => {
+                let bounds = parts
+                    .iter()
+                    .fold(Aabb::empty(), |b, part| b.union(&part.bounds()));
+                if bounds.is_empty() {
+                    return bounds;
+                }
+                let bulge = 0.25 * smoothness.max(0.0);
+                let extent = Vec3::new(bulge, bulge, bulge);
+                Aabb::new(bounds.min - extent, bounds.max + extent)
+            }
+            SdfNode::SmoothIntersect { parts, .. } => parts
+                .iter()
+                .map(|part| part.bounds())
+                .reduce(|a, b| a.intersect(&b))
+                .unwrap_or_else(Aabb::empty),
+            SdfNode::SmoothSubtract { parts, .. } => {
+                parts.first().map_or_else(Aabb::empty, |part| part.bounds())
+            }
+            SdfNode::Repeat {
+                spacing,
+                count,
+                part,
+            } => {
+                let mut bounds = part.bounds();
+                for axis in 0..3 {
+                    if spacing[axis] > 0.0 {
+                        let reach = match count {
+                            Some(count) => spacing[axis] * count[axis] as f64,
+                            None => f64::INFINITY,
+                        };
+                        bounds.min[axis] -= reach;
+                        bounds.max[axis] += reach;
+                    }
+                }
+                bounds
+            }
+        }
+    }
+}
+
+pub struct Sdf {
+    pub root: SdfNode,
+    bounds: Aabb,
+}
+
+impl Sdf {
+    pub fn new(root: SdfNode) -> Self {
+        // Padded so that the march never starts right on the surface
+        let bounds = root.bounds();
+        let pad = Vec3::new(MIN_STEP, MIN_STEP, MIN_STEP);
+        let bounds = if bounds.is_empty() {
+            bounds
+        } else {
+            Aabb::new(bounds.min - pad, bounds.max + pad)
+        };
+        Sdf { root, bounds }
+    }
+
+    pub fn distance(&self, p: Point3) -> f64 {
+        self.root.distance(p)
+    }
+
+    // Outward unit normal, the gradient of the distance by central differences
+    pub fn normal(&self, p: Point3) -> Vec3 {
+        let partial = |axis: Vec3| {
+            self.distance(p + NORMAL_STEP * axis) - self.distance(p - NORMAL_STEP * axis)
+        };
+        let gradient = Vec3::new(
+            partial(Vec3::new(1.0, 0.0, 0.0)),
+            partial(Vec3::new(0.0, 1.0, 0.0)),
+            partial(Vec3::new(0.0, 0.0, 1.0)),
+        );
+        if gradient.dot(gradient) < 1e-300 {
+            return Vec3::new(0.0, 1.0, 0.0);
+        }
+        gradient.normalize()
+    }
+}
+
+impl Hittable for Sdf {
+    // Steps along the ray by the distance to the surface, which cannot overshoot it, until
+    // the distance changes sign. Working from the sign rather than waiting for the distance
+    // to reach zero also finds the way out of rays that start inside.
+    fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        let Some((t_start, t_end)) = self.bounds.clip(ray, time) else {
+            return false;
+        };
+        let speed = ray.direction.len();
+        let mut t = t_start;
+        let mut d = self.distance(ray.at(t));
+        for _ in 0..MAX_STEPS {
+            let next = (t + d.abs().max(MIN_STEP) / speed).min(t_end);
+            let d_next = self.distance(ray.at(next));
+            if (d_next < 0.0) != (d < 0.0) {
+                let (mut low, mut high) = (t, next);
+                for _ in 0..MAX_BISECTIONS {
+                    if (high - low) * speed <= BISECTION_TOLERANCE {
+                        break;
+                    }
+                    let mid = 0.5 * (low + high);
+                    if (self.distance(ray.at(mid)) < 0.0) == (d < 0.0) {
+                        low = mid;
+                    } else {
+                        high = mid;
+                    }
+                }
+                hit_rec.t = 0.5 * (low + high);
+                hit_rec.p = ray.at(hit_rec.t);
+                hit_rec.set_face_normal(ray, self.normal(hit_rec.p));
+                hit_rec.uv = (0.0, 0.0);
+                hit_rec.uv_scale = 0.0;
+                hit_rec.barycentric = (0.0, 0.0);
+                return true;
+            }
+            if next >= t_end {
+                return false;
+            }
+            (t, d) = (next, d_next);
+        }
+        false
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bounds
+    }
+}
@@ -2,22 +2,50 @@ use std::sync::Arc;
 
 // shapes.rs
 // Defines primitive shapes and their geometry
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::csg::Csg;
 use crate::geometry::{Point3, Vec3};
 use crate::ray::Ray;
 use crate::math::Interval;
-use crate::material::Material;
+use crate::background::{Background, GradientBackground};
+use crate::material::{Color, Material};
 use crate::material::DefaultMaterial;
+use crate::medium::Medium;
+use crate::planar::{Cuboid, Disk, Plane, Quad};
+use crate::quadric::{Cone, Cylinder, Quadric, Torus};
+use crate::sdf::Sdf;
+use crate::transform::Instance;
+use crate::triangle::{Triangle, TriangleMesh};
+use rand::Rng;
+use rand::rngs::StdRng;
+use std::f64::consts::{PI, SQRT_2};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HitRecord {
     pub p: Point3,
-    pub normal: Vec3,
+    pub normal: Vec3, // Shading normal, facing against the ray
+    pub geometric_normal: Vec3, // True surface normal, facing against the ray
     pub t: f64,
     pub front_face: bool,
+    // Weights of the second and third triangle vertices (the first is 1 - b1 - b2); zero for non-triangles
+    pub barycentric: (f64, f64),
+    pub uv: (f64, f64), // Surface texture coordinates
+    // Texture-space distance per unit of world-space distance across the surface; zero if
+    // the shape does not provide it
+    pub uv_scale: f64,
+    // World-space width of the area the path sample covers at the hit, set by the camera
+    pub footprint: f64,
     pub material: Arc<dyn Material>
 }
 
 impl HitRecord {
+    // Width of the sampled area in texture space, for choosing how much texture detail to
+    // filter away
+    pub fn uv_footprint(&self) -> f64 {
+        self.footprint * self.uv_scale
+    }
+
     // Outward normal must have unit length
     pub fn set_face_normal(&mut self, ray: &Ray, outward_normal: Vec3) {
         self.front_face = ray.direction.dot(outward_normal).is_sign_negative();
@@ -34,22 +62,154 @@ impl HitRecord {
             */
             self.normal = -outward_normal
         };
+        self.geometric_normal = self.normal;
     }
 }
 
 pub trait Hittable {
     fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool;
+    fn bounding_box(&self) -> Aabb;
+
+    // Light sampling: a direction from origin towards a random point of the shape, or None
+    // if the shape cannot be sampled from there
+    fn sample_direction(&self, _origin: Point3, _time: f64, _rng: &mut StdRng) -> Option<Vec3> {
+        None
+    }
+
+    // Density per unit solid angle with which sample_direction picks direction
+    fn pdf_value(&self, _origin: Point3, _direction: Vec3, _time: f64) -> f64 {
+        0.0
+    }
+
+    // Treating the shape as a solid, appends the ranges of ray parameters within interval over
+    // which the ray is inside it, in order. scratch is overwritten. The default finds them by
+    // repeated hits; see surface_spans.
+    fn spans(&self, ray: &Ray, interval: &Interval, scratch: &mut HitRecord, spans: &mut Vec<Span>) {
+        surface_spans(self, ray, interval, scratch, spans);
+    }
+}
+
+// Where a ray crosses the surface of a solid, with the surface details a hit needs
+#[derive(Debug, Clone, Copy)]
+pub struct Crossing {
+    pub t: f64,
+    pub normal: Vec3,           // Outward shading normal
+    pub geometric_normal: Vec3, // Outward
+    pub uv: (f64, f64),
+    pub uv_scale: f64,
+    pub barycentric: (f64, f64),
+}
+
+impl Crossing {
+    pub fn from_hit(hit_rec: &HitRecord) -> Self {
+        let sign = if hit_rec.front_face { 1.0 } else { -1.0 };
+        Crossing {
+            t: hit_rec.t,
+            normal: sign * hit_rec.normal,
+            geometric_normal: sign * hit_rec.geometric_normal,
+            uv: hit_rec.uv,
+            uv_scale: hit_rec.uv_scale,
+            barycentric: hit_rec.barycentric,
+        }
+    }
+
+    // Stands for an end of a span beyond the interval searched, at t = +-infinity
+    pub fn unbounded(t: f64) -> Self {
+        Crossing {
+            t,
+            normal: Vec3::default(),
+            geometric_normal: Vec3::default(),
+            uv: (0.0, 0.0),
+            uv_scale: 0.0,
+            barycentric: (0.0, 0.0),
+        }
+    }
+
+    // The same crossing with its normals turned inside out, as for a solid being subtracted
+    pub fn flipped(&self) -> Self {
+        Crossing {
+            normal: -self.normal,
+            geometric_normal: -self.geometric_normal,
+            ..*self
+        }
+    }
+
+    // Fills in hit_rec for the ray hitting the surface here
+    pub fn record(&self, ray: &Ray, hit_rec: &mut HitRecord) {
+        hit_rec.t = self.t;
+        hit_rec.p = ray.at(self.t);
+        hit_rec.uv = self.uv;
+        hit_rec.uv_scale = self.uv_scale;
+        hit_rec.barycentric = self.barycentric;
+        hit_rec.set_face_normal(ray, self.geometric_normal);
+        hit_rec.normal = if hit_rec.front_face { self.normal } else { -self.normal };
+    }
+}
+
+// A range of ray parameters over which the ray is inside a solid. An end beyond the interval
+// searched is unbounded, since where the ray crosses there is not known.
+#[derive(Debug, Clone, Copy)]
+pub struct Span {
+    pub enter: Crossing,
+    pub exit: Crossing,
+}
+
+// Crossings followed along one ray, against surfaces that turn out to be very complex or
+// not closed
+const MAX_CROSSINGS: usize = 256;
+// Distance moved past each crossing before looking for the next one
+const CROSSING_GAP: f64 = 1e-6;
+
+// Finds the spans of a solid by following the ray from hit to hit: it enters through front
+// faces and leaves through back faces, and if the first crossing is a way out the ray started
+// inside. This needs a closed shape with its normals facing out; a ray starting inside an
+// open one and never crossing it is not seen to be inside.
+pub fn surface_spans<H: Hittable + ?Sized>(
+    shape: &H,
+    ray: &Ray,
+    interval: &Interval,
+    scratch: &mut HitRecord,
+    spans: &mut Vec<Span>,
+) {
+    let gap = CROSSING_GAP / ray.direction.len();
+    let mut start = interval.min;
+    let mut enter: Option<Crossing> = None;
+    let mut first = true;
+    for _ in 0..MAX_CROSSINGS {
+        if !shape.hit(ray, &Interval::new(start, interval.max), scratch) {
+            break;
+        }
+        let crossing = Crossing::from_hit(scratch);
+        if scratch.front_face {
+            enter = Some(crossing);
+        } else if let Some(entry) = enter.take() {
+            spans.push(Span { enter: entry, exit: crossing });
+        } else if first {
+            let entry = Crossing::unbounded(f64::NEG_INFINITY);
+            spans.push(Span { enter: entry, exit: crossing });
+        }
+        // A second way out in a row can only be a graze, and is skipped
+        first = false;
+        start = crossing.t + gap;
+    }
+    if let Some(entry) = enter {
+        spans.push(Span { enter: entry, exit: Crossing::unbounded(f64::INFINITY) });
+    }
 }
 
 pub struct Sphere {
     pub label : String,
-    pub center: Point3,
+    pub center: Point3, // At time 0
     pub radius: f64,
+    // How far the center moves per unit of ray time, along a straight line; zero for a sphere
+    // that stays put
+    pub velocity: Vec3,
 }
 
 impl Hittable for Sphere {
     fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
-        let c_min_p: Vec3 = self.center - ray.origin;
+        let center = self.center_at(ray.time);
+        let c_min_p: Vec3 = center - ray.origin;
         let a = ray.direction.dot(ray.direction);
         let h = ray.direction.dot(c_min_p);
         let c = c_min_p.dot(c_min_p) - self.radius * self.radius;
@@ -70,29 +230,181 @@ impl Hittable for Sphere {
             }
             hit_rec.p = ray.at(intersect);
             hit_rec.t = intersect;
+            hit_rec.barycentric = (0.0, 0.0);
             //hit_rec.normal = (hit_rec.p - self.center) / self.radius; // Remove
-            let outward_normal = (hit_rec.p - self.center) / self.radius;
+            let outward_normal = (hit_rec.p - center) / self.radius;
+            hit_rec.uv = Sphere::uv(outward_normal);
+            // u covers the equator (2 pi r) and v a meridian (pi r); take their geometric mean
+            hit_rec.uv_scale = 1.0 / (SQRT_2 * PI * self.radius);
             hit_rec.set_face_normal(ray, outward_normal); // clunky
             //println!("Hit {}, {:?}", self.label, hit_rec);
             true
         }
     }
+
+    // Covers the path of the sphere over ray times from 0 to 1
+    fn bounding_box(&self) -> Aabb {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        let end = self.center_at(1.0);
+        Aabb::new(self.center - r, self.center + r).union(&Aabb::new(end - r, end + r))
+    }
+
+    // Uniform over the cone of directions the sphere subtends from origin
+    fn sample_direction(&self, origin: Point3, time: f64, rng: &mut StdRng) -> Option<Vec3> {
+        let (axis, one_minus_cos_max) = self.subtended_cone(origin, time)?;
+        let cos_theta = 1.0 - rng.r#gen::<f64>() * one_minus_cos_max;
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.r#gen::<f64>();
+        let (u, v) = Vec3::orthonormal_basis(axis);
+        Some(sin_theta * phi.cos() * u + sin_theta * phi.sin() * v + cos_theta * axis)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3, time: f64) -> f64 {
+        match self.subtended_cone(origin, time) {
+            Some((axis, one_minus_cos_max))
+                if direction.normalize().dot(axis) >= 1.0 - one_minus_cos_max =>
+            {
+                1.0 / (2.0 * PI * one_minus_cos_max)
+            }
+            _ => 0.0,
+        }
+    }
 }
 
 impl Sphere {
     pub fn new(x: f64, y: f64, z: f64, radius: f64) -> Self {
-        Sphere { label: "".to_string(), center: Point3::new(x, y, z), radius: radius }
+        Sphere {
+            label: "".to_string(),
+            center: Point3::new(x, y, z),
+            radius,
+            velocity: Vec3::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    pub fn center_at(&self, time: f64) -> Point3 {
+        self.center + time * self.velocity
+    }
+
+    // Spherical mapping of a point on the unit sphere: u runs once around the y axis starting
+    // from -x, v from the bottom pole (0) to the top pole (1)
+    pub fn uv(p: Point3) -> (f64, f64) {
+        let theta = (-p.y).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // Axis and 1 - cos(half angle) of the cone the sphere fills as seen from outside it.
+    // The second form avoids cancellation for small, distant spheres.
+    fn subtended_cone(&self, origin: Point3, time: f64) -> Option<(Vec3, f64)> {
+        let to_center = self.center_at(time) - origin;
+        let distance_squared = to_center.dot(to_center);
+        let sin_squared = self.radius * self.radius / distance_squared;
+        if sin_squared >= 1.0 {
+            return None;
+        }
+        let cos_max = (1.0 - sin_squared).sqrt();
+        Some((to_center / distance_squared.sqrt(), sin_squared / (1.0 + cos_max)))
     }
 }
 
 pub enum Shape {
-    Sphere(Sphere)
+    Sphere(Sphere),
+    Triangle(Triangle),
+    Mesh(TriangleMesh),
+    Quad(Quad),
+    Plane(Plane),
+    Disk(Disk),
+    Box(Cuboid),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Torus(Torus),
+    Quadric(Quadric),
+    Instance(Instance),
+    Csg(Csg),
+    Sdf(Sdf),
 }
 
 impl Hittable for Shape {
     fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
         match self {
             Shape::Sphere(s) => s.hit(ray, time, hit_rec),
+            Shape::Triangle(tri) => tri.hit(ray, time, hit_rec),
+            Shape::Mesh(mesh) => mesh.hit(ray, time, hit_rec),
+            Shape::Quad(quad) => quad.hit(ray, time, hit_rec),
+            Shape::Plane(plane) => plane.hit(ray, time, hit_rec),
+            Shape::Disk(disk) => disk.hit(ray, time, hit_rec),
+            Shape::Box(cuboid) => cuboid.hit(ray, time, hit_rec),
+            Shape::Cylinder(cylinder) => cylinder.hit(ray, time, hit_rec),
+            Shape::Cone(cone) => cone.hit(ray, time, hit_rec),
+            Shape::Torus(torus) => torus.hit(ray, time, hit_rec),
+            Shape::Quadric(quadric) => quadric.hit(ray, time, hit_rec),
+            Shape::Instance(instance) => instance.hit(ray, time, hit_rec),
+            Shape::Csg(csg) => csg.hit(ray, time, hit_rec),
+            Shape::Sdf(sdf) => sdf.hit(ray, time, hit_rec),
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match self {
+            Shape::Sphere(s) => s.bounding_box(),
+            Shape::Triangle(tri) => tri.bounding_box(),
+            Shape::Mesh(mesh) => mesh.bounding_box(),
+            Shape::Quad(quad) => quad.bounding_box(),
+            Shape::Plane(plane) => plane.bounding_box(),
+            Shape::Disk(disk) => disk.bounding_box(),
+            Shape::Box(cuboid) => cuboid.bounding_box(),
+            Shape::Cylinder(cylinder) => cylinder.bounding_box(),
+            Shape::Cone(cone) => cone.bounding_box(),
+            Shape::Torus(torus) => torus.bounding_box(),
+            Shape::Quadric(quadric) => quadric.bounding_box(),
+            Shape::Instance(instance) => instance.bounding_box(),
+            Shape::Csg(csg) => csg.bounding_box(),
+            Shape::Sdf(sdf) => sdf.bounding_box(),
+        }
+    }
+
+    fn sample_direction(&self, origin: Point3, time: f64, rng: &mut StdRng) -> Option<Vec3> {
+        match self {
+            Shape::Sphere(s) => s.sample_direction(origin, time, rng),
+            Shape::Triangle(tri) => tri.sample_direction(origin, time, rng),
+            Shape::Mesh(mesh) => mesh.sample_direction(origin, time, rng),
+            Shape::Quad(quad) => quad.sample_direction(origin, time, rng),
+            Shape::Plane(plane) => plane.sample_direction(origin, time, rng),
+            Shape::Disk(disk) => disk.sample_direction(origin, time, rng),
+            Shape::Box(cuboid) => cuboid.sample_direction(origin, time, rng),
+            Shape::Cylinder(cylinder) => cylinder.sample_direction(origin, time, rng),
+            Shape::Cone(cone) => cone.sample_direction(origin, time, rng),
+            Shape::Torus(torus) => torus.sample_direction(origin, time, rng),
+            Shape::Quadric(quadric) => quadric.sample_direction(origin, time, rng),
+            Shape::Instance(instance) => instance.sample_direction(origin, time, rng),
+            Shape::Csg(csg) => csg.sample_direction(origin, time, rng),
+            Shape::Sdf(sdf) => sdf.sample_direction(origin, time, rng),
+        }
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3, time: f64) -> f64 {
+        match self {
+            Shape::Sphere(s) => s.pdf_value(origin, direction, time),
+            Shape::Triangle(tri) => tri.pdf_value(origin, direction, time),
+            Shape::Mesh(mesh) => mesh.pdf_value(origin, direction, time),
+            Shape::Quad(quad) => quad.pdf_value(origin, direction, time),
+            Shape::Plane(plane) => plane.pdf_value(origin, direction, time),
+            Shape::Disk(disk) => disk.pdf_value(origin, direction, time),
+            Shape::Box(cuboid) => cuboid.pdf_value(origin, direction, time),
+            Shape::Cylinder(cylinder) => cylinder.pdf_value(origin, direction, time),
+            Shape::Cone(cone) => cone.pdf_value(origin, direction, time),
+            Shape::Torus(torus) => torus.pdf_value(origin, direction, time),
+            Shape::Quadric(quadric) => quadric.pdf_value(origin, direction, time),
+            Shape::Instance(instance) => instance.pdf_value(origin, direction, time),
+            Shape::Csg(csg) => csg.pdf_value(origin, direction, time),
+            Shape::Sdf(sdf) => sdf.pdf_value(origin, direction, time),
+        }
+    }
+
+    fn spans(&self, ray: &Ray, interval: &Interval, scratch: &mut HitRecord, spans: &mut Vec<Span>) {
+        match self {
+            Shape::Csg(csg) => csg.spans(ray, interval, scratch, spans),
+            _ => surface_spans(self, ray, interval, scratch, spans),
         }
     }
 }
@@ -101,11 +413,80 @@ pub struct World {
     // Note: monomorphization was used for shape determination, but dynamic dispatch
     // was used for material allocation. Consider tradeoffs of each.
     pub objects: Vec<(Shape, usize)>, // Each object is represented by its shape and index of material in materials
-    pub materials: Vec<Arc<dyn Material>>
+    pub materials: Vec<Arc<dyn Material>>,
+    pub background: Box<dyn Background>, // Light from rays that escape the scene
+    lights: Vec<usize>, // Indices of objects with emissive materials, for light sampling
+    // Acceleration structure over the objects with finite bounds, whose indices in objects
+    // are bvh_objects. Built by build_bvh once the scene is complete; until then (or after
+    // objects change) hits fall back to a linear scan.
+    bvh: Option<Bvh>,
+    bvh_objects: Vec<usize>,
+    unbounded: Vec<usize>, // Objects such as planes that no box can hold, tested one by one
+    // Volumes such as fog, each with the index of its phase function in materials. They are
+    // not surfaces, so hit passes through them; see sample_medium.
+    media: Vec<(Medium, usize)>,
 }
 
 impl Hittable for World {
     fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        match &self.bvh {
+            Some(bvh) => {
+                // Unbounded objects go first so their hits shorten the BVH traversal
+                let mut closest_t = time.max;
+                let mut hit_anything = false;
+                for &index in &self.unbounded {
+                    let (object, material_index) = &self.objects[index];
+                    if object.hit(ray, &Interval::new(time.min, closest_t), hit_rec) {
+                        hit_anything = true;
+                        closest_t = hit_rec.t;
+                        hit_rec.material = Arc::clone(&self.materials[*material_index]);
+                    }
+                }
+                let search = Interval::new(time.min, closest_t);
+                let bvh_hit = bvh.hit(ray, &search, |index, interval| {
+                    let (object, material_index) = &self.objects[self.bvh_objects[index]];
+                    if object.hit(ray, interval, hit_rec) {
+                        hit_rec.material = Arc::clone(&self.materials[*material_index]);
+                        Some(hit_rec.t)
+                    } else {
+                        None
+                    }
+                });
+                hit_anything || bvh_hit
+            }
+            None => self.hit_linear(ray, time, hit_rec),
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.objects
+            .iter()
+            .fold(Aabb::empty(), |b, (object, _)| b.union(&object.bounding_box()))
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl World {
+    pub fn new() -> Self {
+        World {
+            objects: vec![],
+            materials: vec![Arc::new(DefaultMaterial{})],
+            background: Box::new(GradientBackground::default()),
+            lights: vec![],
+            bvh: None,
+            bvh_objects: vec![],
+            unbounded: vec![],
+            media: vec![],
+        }
+    }
+
+    // Tests every object in turn; used when no BVH has been built
+    pub fn hit_linear(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
         let mut closest_t = time.max;
         let mut hit_anything = false;
         for (object, material_index) in self.objects.iter() {
@@ -117,24 +498,152 @@ impl Hittable for World {
         }
         hit_anything
     }
-}
 
-impl World {
-    pub fn new() -> Self {
-        World { objects: vec![], materials: vec![Arc::new(DefaultMaterial{})]}
+    // Builds the BVH over the current objects. Call again after adding objects.
+    pub fn build_bvh(&mut self) {
+        self.bvh_objects.clear();
+        self.unbounded.clear();
+        let mut bounds = vec![];
+        for (index, (object, _)) in self.objects.iter().enumerate() {
+            let object_bounds = object.bounding_box();
+            if object_bounds.is_finite() {
+                self.bvh_objects.push(index);
+                bounds.push(object_bounds);
+            } else {
+                self.unbounded.push(index);
+            }
+        }
+        self.bvh = Some(Bvh::build(&bounds));
     }
+
+    pub fn bvh(&self) -> Option<&Bvh> {
+        self.bvh.as_ref()
+    }
+
     pub fn new_hitrecord(&self) -> HitRecord {
         HitRecord {
             p: Point3::default(),
             normal: Vec3::default(),
+            geometric_normal: Vec3::default(),
             t: 0.0,
             front_face: false,
+            barycentric: (0.0, 0.0),
+            uv: (0.0, 0.0),
+            uv_scale: 0.0,
+            footprint: 0.0,
             material: Arc::clone(&self.materials[0])
         }
     }
-    pub fn add_material<T: Material + 'static>(&mut self, material: T) {
+    // Returns the index objects use to refer to the material
+    pub fn add_material<T: Material + 'static>(&mut self, material: T) -> usize {
         self.materials.push(Arc::new(material));
+        self.materials.len() - 1
+    }
+
+    // Adds a shape using the material at material_index. Invalidates any built BVH.
+    pub fn add_object(&mut self, shape: Shape, material_index: usize) {
+        if self.materials[material_index].is_emissive() {
+            self.lights.push(self.objects.len());
+        }
+        self.objects.push((shape, material_index));
+        self.bvh = None;
+    }
+
+    pub fn add_medium(&mut self, medium: Medium, material_index: usize) {
+        self.media.push((medium, material_index));
+    }
+
+    pub fn media(&self) -> &[(Medium, usize)] {
+        &self.media
+    }
+
+    // Whether light travelling along the ray within interval, which should end at the first
+    // surface hit, is scattered by a medium first. If so hit_rec is set to the point where
+    // that happens, with the medium's phase function as its material. Light that glowing
+    // media give off along the way, up to that point, is added to glow.
+    pub fn sample_medium(
+        &self,
+        ray: &Ray,
+        interval: &Interval,
+        hit_rec: &mut HitRecord,
+        glow: &mut Color,
+        rng: &mut StdRng,
+    ) -> bool {
+        if self.media.is_empty() {
+            return false;
+        }
+        let mut scratch = self.new_hitrecord();
+        let mut emitted = vec![];
+        let mut closest: Option<(f64, usize)> = None;
+        for (medium, material_index) in &self.media {
+            let max = closest.map_or(interval.max, |(t, _)| t);
+            let search = Interval::new(interval.min, max);
+            // Interactions with independent media race: the earliest one happens
+            if let Some(t) = medium.sample_distance(ray, &search, &mut scratch, &mut emitted, rng)
+            {
+                closest = Some((t, *material_index));
+            }
+        }
+        let end = closest.map_or(interval.max, |(t, _)| t);
+        // Estimates at the interaction itself count: the light got that far
+        for (t, radiance) in emitted {
+            if t <= end {
+                *glow = *glow + radiance;
+            }
+        }
+        let Some((t, material_index)) = closest else {
+            return false;
+        };
+        let wo = -ray.direction.normalize();
+        hit_rec.p = ray.at(t);
+        hit_rec.t = t;
+        hit_rec.normal = wo;
+        hit_rec.geometric_normal = wo;
+        hit_rec.front_face = true;
+        hit_rec.barycentric = (0.0, 0.0);
+        hit_rec.uv = (0.0, 0.0);
+        hit_rec.uv_scale = 0.0;
+        hit_rec.material = Arc::clone(&self.materials[material_index]);
+        true
+    }
+
+    // Fraction of light that gets through every medium along the ray within interval, or an
+    // unbiased estimate of it where the density varies
+    pub fn transmittance(&self, ray: &Ray, interval: &Interval, rng: &mut StdRng) -> f64 {
+        if self.media.is_empty() {
+            return 1.0;
+        }
+        let mut scratch = self.new_hitrecord();
+        self.media
+            .iter()
+            .map(|(medium, _)| medium.transmittance(ray, interval, &mut scratch, rng))
+            .product()
+    }
+
+    pub fn lights(&self) -> &[usize] {
+        &self.lights
+    }
+
+    // A direction from origin towards a point on a light, picked uniformly among lights
+    pub fn sample_light(&self, origin: Point3, time: f64, rng: &mut StdRng) -> Option<Vec3> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let light = self.lights[rng.gen_range(0..self.lights.len())];
+        self.objects[light].0.sample_direction(origin, time, rng)
+    }
+
+    // Density with which sample_light picks direction. Every light the direction points at
+    // contributes, since any of them could have been the one sampled.
+    pub fn light_pdf(&self, origin: Point3, direction: Vec3, time: f64) -> f64 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+        let pdf_sum: f64 = self
+            .lights
+            .iter()
+            .map(|&light| self.objects[light].0.pdf_value(origin, direction, time))
+            .sum();
+        pdf_sum / self.lights.len() as f64
     }
-    
-    //pub fn add_object()
 }
\ No newline at end of file
@@ -0,0 +1,267 @@
+// texture.rs
+// Colours that vary over a surface, looked up at the texture coordinates and position of a hit
+use crate::geometry::Point3;
+use crate::image::Image;
+use crate::material::Color;
+use crate::noise::{Perlin, Worley};
+use serde::Deserialize;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+pub trait Texture: Debug + Sync + Send {
+    // footprint is the width of the area being shaded in texture space, so detail finer than
+    // that can be filtered away; zero asks for a point sample
+    fn value(&self, uv: (f64, f64), p: Point3, footprint: f64) -> Color;
+
+    // Single-channel parameters such as roughness read the average of the three channels
+    fn scalar(&self, uv: (f64, f64), p: Point3, footprint: f64) -> f64 {
+        let color = self.value(uv, p, footprint);
+        (color.x + color.y + color.z) / 3.0
+    }
+}
+
+#[derive(Debug)]
+pub struct SolidColor {
+    pub color: Color,
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _uv: (f64, f64), _p: Point3, _footprint: f64) -> Color {
+        self.color
+    }
+}
+
+// Shorthand for the common case of a material parameter that does not vary
+pub fn solid(color: Color) -> Arc<dyn Texture> {
+    Arc::new(SolidColor { color })
+}
+
+// Alternates between two textures in cubes of side scale filling space, so the pattern is
+// carved out of the object rather than wrapped around it
+#[derive(Debug)]
+pub struct CheckerTexture {
+    pub scale: f64,
+    pub even: Arc<dyn Texture>,
+    pub odd: Arc<dyn Texture>,
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, uv: (f64, f64), p: Point3, footprint: f64) -> Color {
+        let cell = (p.x / self.scale).floor() + (p.y / self.scale).floor() + (p.z / self.scale).floor();
+        if cell.rem_euclid(2.0) == 0.0 {
+            self.even.value(uv, p, footprint)
+        } else {
+            self.odd.value(uv, p, footprint)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoisePattern {
+    Perlin,     // Smooth fractal gradient noise
+    Turbulence, // Creased, cloud-like fractal noise
+    Marble,     // Parallel veins along z, distorted by turbulence
+    Wood,       // Rings around the y axis, distorted by turbulence
+    Worley,     // Cells: distance to the nearest of a scattering of random points
+}
+
+// Procedural pattern in space, blending from low where the pattern is 0 to high where it is
+// 1. scale is the size of one feature, and each further octave adds detail at half the size
+// and half the strength. The same seed always gives the same pattern.
+#[derive(Debug)]
+pub struct NoiseTexture {
+    pub pattern: NoisePattern,
+    pub scale: f64,
+    pub octaves: u32,
+    pub low: Arc<dyn Texture>,
+    pub high: Arc<dyn Texture>,
+    perlin: Perlin,
+    worley: Worley,
+}
+
+impl NoiseTexture {
+    pub fn new(
+        pattern: NoisePattern,
+        scale: f64,
+        octaves: u32,
+        seed: u64,
+        low: Arc<dyn Texture>,
+        high: Arc<dyn Texture>,
+    ) -> Self {
+        NoiseTexture {
+            pattern,
+            scale,
+            octaves,
+            low,
+            high,
+            perlin: Perlin::new(seed),
+            worley: Worley::new(seed),
+        }
+    }
+
+    // The pattern at p, in [0, 1]
+    pub fn amount(&self, p: Point3) -> f64 {
+        let p = p / self.scale;
+        let amount = match self.pattern {
+            NoisePattern::Perlin => 0.5 * (1.0 + self.perlin.fbm(p, self.octaves)),
+            NoisePattern::Turbulence => self.perlin.turbulence(p, self.octaves),
+            NoisePattern::Marble => {
+                let phase = p.z + 10.0 * self.perlin.turbulence(p, self.octaves);
+                0.5 * (1.0 + phase.sin())
+            }
+            NoisePattern::Wood => {
+                let radius = (p.x * p.x + p.z * p.z).sqrt();
+                let rings = radius + 2.0 * self.perlin.turbulence(p, self.octaves);
+                rings - rings.floor()
+            }
+            NoisePattern::Worley => self.worley.fbm(p, self.octaves),
+        };
+        amount.clamp(0.0, 1.0)
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, uv: (f64, f64), p: Point3, footprint: f64) -> Color {
+        let t = self.amount(p);
+        (1.0 - t) * self.low.value(uv, p, footprint) + t * self.high.value(uv, p, footprint)
+    }
+}
+
+// What happens to texture coordinates outside [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WrapMode {
+    #[default]
+    Repeat, // Tile the image
+    Clamp,  // Stretch the edge texels
+    Mirror, // Tile, flipping every other copy so edges meet seamlessly
+}
+
+impl WrapMode {
+    // Maps a texel index that may fall outside 0..size onto one inside it
+    fn wrap(self, index: i64, size: usize) -> usize {
+        let size = size as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => index.rem_euclid(size),
+            WrapMode::Clamp => index.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let period = index.rem_euclid(2 * size);
+                if period < size { period } else { 2 * size - 1 - period }
+            }
+        };
+        wrapped as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureFilter {
+    Nearest,  // The texel the point falls in
+    Bilinear, // Blend of the four nearest texels of the full-resolution image
+    #[default]
+    Trilinear, // Bilinear in the two MIP levels closest to the footprint, blended
+}
+
+// An image stretched over the texture coordinates, with v = 0 at the bottom row. A MIP
+// pyramid of successively halved copies is built up front, so distant or minified surfaces
+// read a pre-averaged level instead of aliasing.
+#[derive(Debug)]
+pub struct ImageTexture {
+    pub wrap: WrapMode,
+    pub filter: TextureFilter,
+    levels: Vec<Image>, // Full resolution first, down to 1 x 1
+}
+
+impl ImageTexture {
+    pub fn new(image: Image, wrap: WrapMode, filter: TextureFilter) -> Self {
+        assert!(image.width > 0 && image.height > 0, "texture image is empty");
+        let mut levels = vec![image];
+        while let Some(last) = levels.last()
+            && (last.width > 1 || last.height > 1)
+        {
+            let next = downsample(last, wrap);
+            levels.push(next);
+        }
+        ImageTexture {
+            wrap,
+            filter,
+            levels,
+        }
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.levels[0]
+    }
+
+    fn nearest(&self, level: &Image, u: f64, v: f64) -> Color {
+        let x = self.wrap.wrap((u * level.width as f64).floor() as i64, level.width);
+        let y = self.wrap.wrap(((1.0 - v) * level.height as f64).floor() as i64, level.height);
+        level.get(x, y)
+    }
+
+    fn bilinear(&self, level: &Image, u: f64, v: f64) -> Color {
+        // Texel centres sit at half-integer positions
+        let x = u * level.width as f64 - 0.5;
+        let y = (1.0 - v) * level.height as f64 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let texel = |dx: i64, dy: i64| {
+            level.get(
+                self.wrap.wrap(x0 as i64 + dx, level.width),
+                self.wrap.wrap(y0 as i64 + dy, level.height),
+            )
+        };
+        let top = (1.0 - fx) * texel(0, 0) + fx * texel(1, 0);
+        let bottom = (1.0 - fx) * texel(0, 1) + fx * texel(1, 1);
+        (1.0 - fy) * top + fy * bottom
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, (u, v): (f64, f64), _p: Point3, footprint: f64) -> Color {
+        match self.filter {
+            TextureFilter::Nearest => self.nearest(&self.levels[0], u, v),
+            TextureFilter::Bilinear => self.bilinear(&self.levels[0], u, v),
+            TextureFilter::Trilinear => {
+                // Level n has texels 2^n times wider than the full image
+                let base = &self.levels[0];
+                let texels = footprint * base.width.max(base.height) as f64;
+                let level = if texels > 1.0 { texels.log2() } else { 0.0 };
+                let level = level.min((self.levels.len() - 1) as f64);
+                let lower = level.floor() as usize;
+                let blend = level - lower as f64;
+                let fine = self.bilinear(&self.levels[lower], u, v);
+                if blend == 0.0 {
+                    return fine;
+                }
+                let coarse = self.bilinear(&self.levels[lower + 1], u, v);
+                (1.0 - blend) * fine + blend * coarse
+            }
+        }
+    }
+}
+
+// Halves each dimension (rounding up) by averaging 2 x 2 blocks. For odd sizes the last
+// block reaches past the edge, which is resolved the same way lookups are.
+fn downsample(image: &Image, wrap: WrapMode) -> Image {
+    let width = image.width.div_ceil(2);
+    let height = image.height.div_ceil(2);
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = Color::default();
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                let sx = wrap.wrap((2 * x + dx) as i64, image.width);
+                let sy = wrap.wrap((2 * y + dy) as i64, image.height);
+                sum = sum + image.get(sx, sy);
+            }
+            pixels.push(sum / 4.0);
+        }
+    }
+    Image {
+        width,
+        height,
+        pixels,
+    }
+}
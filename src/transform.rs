@@ -0,0 +1,591 @@
+// transform.rs
+// Affine transforms, keyframed transforms that change with ray time, and instances that
+// place a shared shape in the scene through one
+use crate::aabb::Aabb;
+use crate::geometry::{Point3, Vec3, degrees_to_radians};
+use crate::math::Interval;
+use crate::ray::Ray;
+use crate::shapes::{HitRecord, Hittable, Shape};
+use rand::rngs::StdRng;
+use std::ops::Mul;
+use std::sync::Arc;
+
+// Row-major 4x4 matrix acting on column vectors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Matrix4 { m }
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Matrix4 { m }
+    }
+
+    // Gauss-Jordan elimination with partial pivoting; None if the matrix is singular
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inverse = Matrix4::identity().m;
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                .unwrap();
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+            let scale = 1.0 / a[column][column];
+            for j in 0..4 {
+                a[column][j] *= scale;
+                inverse[column][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != column {
+                    let factor = a[row][column];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[column][j];
+                        inverse[row][j] -= factor * inverse[column][j];
+                    }
+                }
+            }
+        }
+        Some(Matrix4 { m: inverse })
+    }
+
+    // Determinant of the upper-left 3x3 block, the linear part of an affine transform
+    pub fn linear_determinant(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn point(&self, p: Point3) -> Point3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w == 1.0 { Point3::new(x, y, z) } else { Point3::new(x, y, z) / w }
+    }
+
+    // Directions ignore the translation column
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, rhs: Matrix4) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Matrix4 { m }
+    }
+}
+
+// An invertible affine transform, stored with its inverse since rays are carried into object
+// space with the inverse and normals back out with its transpose
+#[derive(Debug, Clone, Copy)]
+pub struct Transform {
+    matrix: Matrix4,
+    inverse: Matrix4,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform {
+            matrix: Matrix4::identity(),
+            inverse: Matrix4::identity(),
+        }
+    }
+
+    // None if matrix cannot be inverted
+    pub fn new(matrix: Matrix4) -> Option<Self> {
+        Some(Transform {
+            matrix,
+            inverse: matrix.inverse()?,
+        })
+    }
+
+    pub fn translate(offset: Vec3) -> Self {
+        let mut matrix = Matrix4::identity();
+        let mut inverse = Matrix4::identity();
+        for axis in 0..3 {
+            matrix.m[axis][3] = offset[axis];
+            inverse.m[axis][3] = -offset[axis];
+        }
+        Transform { matrix, inverse }
+    }
+
+    // Factors must be non-zero
+    pub fn scale(factors: Vec3) -> Self {
+        let mut matrix = Matrix4::identity();
+        let mut inverse = Matrix4::identity();
+        for axis in 0..3 {
+            matrix.m[axis][axis] = factors[axis];
+            inverse.m[axis][axis] = 1.0 / factors[axis];
+        }
+        Transform { matrix, inverse }
+    }
+
+    // Counterclockwise by degrees about axis, looking down the axis towards the origin
+    pub fn rotate(axis: Vec3, degrees: f64) -> Self {
+        let k = axis.normalize();
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        // Rodrigues' formula: cos I + sin [k]x + (1 - cos) k k^T
+        let cross = [[0.0, -k.z, k.y], [k.z, 0.0, -k.x], [-k.y, k.x, 0.0]];
+        let mut matrix = Matrix4::identity();
+        for i in 0..3 {
+            for j in 0..3 {
+                let diagonal = if i == j { cos } else { 0.0 };
+                matrix.m[i][j] = diagonal + sin * cross[i][j] + (1.0 - cos) * k[i] * k[j];
+            }
+        }
+        // Rotations are orthogonal, so the inverse is the transpose
+        Transform {
+            matrix,
+            inverse: matrix.transpose(),
+        }
+    }
+
+    // This transform followed by next
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            matrix: next.matrix * self.matrix,
+            inverse: self.inverse * next.inverse,
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.matrix
+    }
+
+    pub fn point(&self, p: Point3) -> Point3 {
+        self.matrix.point(p)
+    }
+
+    pub fn vector(&self, v: Vec3) -> Vec3 {
+        self.matrix.vector(v)
+    }
+
+    // Normals stay perpendicular to the surface under the inverse transpose. The result is
+    // not normalized.
+    pub fn normal(&self, n: Vec3) -> Vec3 {
+        let m = &self.inverse.m;
+        Vec3::new(
+            m[0][0] * n.x + m[1][0] * n.y + m[2][0] * n.z,
+            m[0][1] * n.x + m[1][1] * n.y + m[2][1] * n.z,
+            m[0][2] * n.x + m[1][2] * n.y + m[2][2] * n.z,
+        )
+    }
+
+    pub fn bounds(&self, bounds: &Aabb) -> Aabb {
+        // Unbounded shapes stay unbounded, and empty ones empty
+        if !bounds.is_finite() {
+            return *bounds;
+        }
+        (0..8).fold(Aabb::empty(), |b, corner| {
+            let p = Point3::new(
+                if corner & 1 == 0 { bounds.min.x } else { bounds.max.x },
+                if corner & 2 == 0 { bounds.min.y } else { bounds.max.y },
+                if corner & 4 == 0 { bounds.min.z } else { bounds.max.z },
+            );
+            b.grow(self.point(p))
+        })
+    }
+}
+
+// A transform that changes with ray time, given at keyframe times and blended in between.
+// Each keyframe is split into a translation, a rotation and a remaining stretch (a polar
+// decomposition), which are interpolated separately: blending the matrices directly would
+// shrink and shear a shape halfway through a turn. Before the first keyframe and after the
+// last the transform holds still.
+#[derive(Debug, Clone)]
+pub struct AnimatedTransform {
+    keyframes: Vec<Keyframe>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Keyframe {
+    time: f64,
+    transform: Transform,
+    translation: Vec3,
+    rotation: Quaternion,
+    stretch: [[f64; 3]; 3],
+}
+
+// Steps between ray times 0 and 1 at which a moving shape's bounds are taken
+const BOUNDS_STEPS: usize = 256;
+
+impl AnimatedTransform {
+    pub fn fixed(transform: Transform) -> Self {
+        Self::new(vec![(0.0, transform)])
+    }
+
+    // Keyframes are (time, transform) pairs in any order; there must be at least one
+    pub fn new(mut keyframes: Vec<(f64, Transform)>) -> Self {
+        assert!(!keyframes.is_empty(), "an animated transform needs a keyframe");
+        keyframes.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let keyframes = keyframes
+            .into_iter()
+            .map(|(time, transform)| Keyframe::new(time, transform))
+            .collect();
+        AnimatedTransform { keyframes }
+    }
+
+    pub fn is_animated(&self) -> bool {
+        self.keyframes.len() > 1
+    }
+
+    pub fn at(&self, time: f64) -> Transform {
+        let first = &self.keyframes[0];
+        let last = &self.keyframes[self.keyframes.len() - 1];
+        if time <= first.time {
+            return first.transform;
+        }
+        if time >= last.time {
+            return last.transform;
+        }
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        let (a, b) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        let s = (time - a.time) / (b.time - a.time);
+        let translation = a.translation + s * (b.translation - a.translation);
+        let rotation = a.rotation.slerp(&b.rotation, s).matrix();
+        let mut matrix = Matrix4::identity();
+        for i in 0..3 {
+            for j in 0..3 {
+                matrix.m[i][j] = (0..3)
+                    .map(|k| {
+                        let stretch = a.stretch[k][j] + s * (b.stretch[k][j] - a.stretch[k][j]);
+                        rotation[i][k] * stretch
+                    })
+                    .sum();
+            }
+            matrix.m[i][3] = translation[i];
+        }
+        // A blend of invertible stretches can pass through a singular one
+        Transform::new(matrix).unwrap_or(if s < 0.5 { a.transform } else { b.transform })
+    }
+
+    // Bounds of the transformed box over ray times 0 to 1. They are taken at closely spaced
+    // times and padded by the furthest any corner moves in one step, to cover the arcs that
+    // corners sweep between the steps.
+    pub fn bounds(&self, bounds: &Aabb) -> Aabb {
+        if !self.is_animated() || !bounds.is_finite() {
+            return self.keyframes[0].transform.bounds(bounds);
+        }
+        let corners: Vec<Point3> = (0..8)
+            .map(|corner| {
+                Point3::new(
+                    if corner & 1 == 0 { bounds.min.x } else { bounds.max.x },
+                    if corner & 2 == 0 { bounds.min.y } else { bounds.max.y },
+                    if corner & 4 == 0 { bounds.min.z } else { bounds.max.z },
+                )
+            })
+            .collect();
+        let mut result = Aabb::empty();
+        let mut previous: Option<Vec<Point3>> = None;
+        let mut step = 0.0_f64;
+        for i in 0..=BOUNDS_STEPS {
+            let transform = self.at(i as f64 / BOUNDS_STEPS as f64);
+            let moved: Vec<Point3> = corners.iter().map(|&p| transform.point(p)).collect();
+            if let Some(previous) = &previous {
+                for (p, q) in moved.iter().zip(previous) {
+                    step = step.max((*p - *q).len());
+                }
+            }
+            result = moved.iter().fold(result, |b, &p| b.grow(p));
+            previous = Some(moved);
+        }
+        result.pad(step)
+    }
+}
+
+impl Keyframe {
+    fn new(time: f64, transform: Transform) -> Self {
+        let m = &transform.matrix.m;
+        let linear = [
+            [m[0][0], m[0][1], m[0][2]],
+            [m[1][0], m[1][1], m[1][2]],
+            [m[2][0], m[2][1], m[2][2]],
+        ];
+        // Polar decomposition M = R S: averaging R with its inverse transpose converges to
+        // the nearest rotation, or to a rotation times -1 for a mirroring transform
+        let mut rotation = linear;
+        for _ in 0..100 {
+            let Some(inverse) = inverse3(&rotation) else {
+                break;
+            };
+            let mut change = 0.0_f64;
+            for i in 0..3 {
+                for j in 0..3 {
+                    let next = 0.5 * (rotation[i][j] + inverse[j][i]);
+                    change = change.max((next - rotation[i][j]).abs());
+                    rotation[i][j] = next;
+                }
+            }
+            if change < 1e-12 {
+                break;
+            }
+        }
+        if determinant3(&rotation) < 0.0 {
+            // Mirroring goes into the stretch, so the rotation can be a quaternion
+            for row in rotation.iter_mut() {
+                for value in row.iter_mut() {
+                    *value = -*value;
+                }
+            }
+        }
+        // S = R^T M
+        let mut stretch = [[0.0; 3]; 3];
+        for (i, row) in stretch.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..3).map(|k| rotation[k][i] * linear[k][j]).sum();
+            }
+        }
+        Keyframe {
+            time,
+            transform,
+            translation: Vec3::new(m[0][3], m[1][3], m[2][3]),
+            rotation: Quaternion::from_matrix(&rotation),
+            stretch,
+        }
+    }
+}
+
+fn determinant3(m: &[[f64; 3]; 3]) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+// By cofactors; None if m is singular
+fn inverse3(m: &[[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let determinant = determinant3(m);
+    if determinant.abs() < 1e-12 {
+        return None;
+    }
+    let mut inverse = [[0.0; 3]; 3];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            // Cofactor of m[j][i], taken with cyclic indices so the sign comes out right
+            let (r0, r1) = ((j + 1) % 3, (j + 2) % 3);
+            let (c0, c1) = ((i + 1) % 3, (i + 2) % 3);
+            *value = (m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]) / determinant;
+        }
+    }
+    Some(inverse)
+}
+
+// Unit quaternion w + x i + y j + z k, for blending rotations
+#[derive(Debug, Clone, Copy)]
+struct Quaternion {
+    w: f64,
+    x: f64,
+    y: f64,
+    z: f64,
+}
+
+impl Quaternion {
+    // m must be a rotation matrix. The largest of the four components is found first, to
+    // avoid dividing by a small one.
+    fn from_matrix(m: &[[f64; 3]; 3]) -> Self {
+        let trace = m[0][0] + m[1][1] + m[2][2];
+        let q = if trace > 0.0 {
+            let s = 2.0 * (1.0 + trace).sqrt();
+            Quaternion {
+                w: 0.25 * s,
+                x: (m[2][1] - m[1][2]) / s,
+                y: (m[0][2] - m[2][0]) / s,
+                z: (m[1][0] - m[0][1]) / s,
+            }
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+            Quaternion {
+                w: (m[2][1] - m[1][2]) / s,
+                x: 0.25 * s,
+                y: (m[0][1] + m[1][0]) / s,
+                z: (m[0][2] + m[2][0]) / s,
+            }
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+            Quaternion {
+                w: (m[0][2] - m[2][0]) / s,
+                x: (m[0][1] + m[1][0]) / s,
+                y: 0.25 * s,
+                z: (m[1][2] + m[2][1]) / s,
+            }
+        } else {
+            let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+            Quaternion {
+                w: (m[1][0] - m[0][1]) / s,
+                x: (m[0][2] + m[2][0]) / s,
+                y: (m[1][2] + m[2][1]) / s,
+                z: 0.25 * s,
+            }
+        };
+        q.scaled(1.0 / q.dot(&q).sqrt())
+    }
+
+    fn matrix(&self) -> [[f64; 3]; 3] {
+        let Quaternion { w, x, y, z } = *self;
+        [
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y)],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x)],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y)],
+        ]
+    }
+
+    fn dot(&self, other: &Quaternion) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    fn scaled(&self, s: f64) -> Quaternion {
+        Quaternion {
+            w: s * self.w,
+            x: s * self.x,
+            y: s * self.y,
+            z: s * self.z,
+        }
+    }
+
+    fn add(&self, other: &Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w + other.w,
+            x: self.x + other.x,
+            y: self.y + other.y,
+            z: self.z + other.z,
+        }
+    }
+
+    // Constant-speed turn from self to other along the shorter way round, at fraction s
+    fn slerp(&self, other: &Quaternion, s: f64) -> Quaternion {
+        let mut cos = self.dot(other);
+        let mut other = *other;
+        if cos < 0.0 {
+            cos = -cos;
+            other = other.scaled(-1.0);
+        }
+        let (a, b) = if cos > 0.9995 {
+            // Nearly the same rotation: a straight blend is accurate and avoids dividing by
+            // sin(angle)
+            (1.0 - s, s)
+        } else {
+            let angle = cos.acos();
+            let sin = angle.sin();
+            (((1.0 - s) * angle).sin() / sin, (s * angle).sin() / sin)
+        };
+        let q = self.scaled(a).add(&other.scaled(b));
+        q.scaled(1.0 / q.dot(&q).sqrt())
+    }
+}
+
+// A shape placed in the scene through a transform from its own object space, which may
+// change with ray time. The shape is shared, so any number of instances of one mesh cost only
+// a transform each.
+pub struct Instance {
+    pub shape: Arc<Shape>,
+    pub transform: AnimatedTransform,
+    bounds: Aabb,
+}
+
+impl Instance {
+    pub fn new(shape: Arc<Shape>, transform: Transform) -> Self {
+        Self::animated(shape, AnimatedTransform::fixed(transform))
+    }
+
+    pub fn animated(shape: Arc<Shape>, transform: AnimatedTransform) -> Self {
+        let bounds = transform.bounds(&shape.bounding_box());
+        Instance {
+            shape,
+            transform,
+            bounds,
+        }
+    }
+
+    fn object_ray(transform: &Transform, ray: &Ray) -> Ray {
+        Ray {
+            origin: transform.inverse.point(ray.origin),
+            direction: transform.inverse.vector(ray.direction),
+            time: ray.time,
+        }
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        let transform = self.transform.at(ray.time);
+        // The object-space direction is not normalized, so ray parameters agree in both spaces
+        if !self.shape.hit(&Self::object_ray(&transform, ray), time, hit_rec) {
+            return false;
+        }
+        hit_rec.p = ray.at(hit_rec.t);
+        hit_rec.normal = transform.normal(hit_rec.normal).normalize();
+        hit_rec.geometric_normal = transform.normal(hit_rec.geometric_normal).normalize();
+        // Texture footprints shrink by the cube root of how much the transform scales volumes
+        hit_rec.uv_scale /= transform.matrix.linear_determinant().abs().cbrt();
+        true
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bounds
+    }
+
+    fn sample_direction(&self, origin: Point3, time: f64, rng: &mut StdRng) -> Option<Vec3> {
+        let transform = self.transform.at(time);
+        let direction = self
+            .shape
+            .sample_direction(transform.inverse.point(origin), time, rng)?;
+        Some(transform.vector(direction))
+    }
+
+    // A linear map A sends unit direction w to A w / |A w|, stretching solid angle by
+    // |det A| / |A w|^3, which converts the object-space density into a world-space one
+    fn pdf_value(&self, origin: Point3, direction: Vec3, time: f64) -> f64 {
+        let transform = self.transform.at(time);
+        let object_direction = transform.inverse.vector(direction.normalize());
+        let pdf = self
+            .shape
+            .pdf_value(transform.inverse.point(origin), object_direction, time);
+        let stretch = object_direction.len();
+        pdf * transform.inverse.linear_determinant().abs() / (stretch * stretch * stretch)
+    }
+}
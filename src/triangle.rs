@@ -0,0 +1,311 @@
+// triangle.rs
+// Triangles and indexed triangle meshes
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+use crate::geometry::{Point3, Vec3};
+use crate::math::Interval;
+use crate::ray::Ray;
+use crate::sampling::{Distribution1D, area_to_solid_angle};
+use crate::shapes::{HitRecord, Hittable};
+use rand::Rng;
+use rand::rngs::StdRng;
+
+// Flat triangles have zero thickness along one axis; boxes are padded so the BVH slab test
+// still sees a non-degenerate interval
+const BOX_PADDING: f64 = 1e-4;
+
+pub struct Triangle {
+    pub vertices: [Point3; 3],
+    pub normals: Option<[Vec3; 3]>, // Per-vertex shading normals, interpolated across the face
+    pub uvs: Option<[(f64, f64); 3]>, // Per-vertex texture coordinates
+}
+
+impl Triangle {
+    pub fn new(a: Point3, b: Point3, c: Point3) -> Self {
+        Triangle {
+            vertices: [a, b, c],
+            normals: None,
+            uvs: None,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        match intersect(ray, &self.vertices, time) {
+            Some((t, b1, b2)) => {
+                let attributes = VertexAttributes {
+                    normals: self.normals,
+                    uvs: self.uvs,
+                };
+                record_hit(ray, t, b1, b2, &self.vertices, &attributes, hit_rec);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [a, b, c] = self.vertices;
+        Aabb::new(a, b).grow(c).pad(BOX_PADDING)
+    }
+
+    fn sample_direction(&self, origin: Point3, _time: f64, rng: &mut StdRng) -> Option<Vec3> {
+        Some(sample_point(&self.vertices, rng) - origin)
+    }
+
+    fn pdf_value(&self, origin: Point3, direction: Vec3, time: f64) -> f64 {
+        let ray = Ray { origin, direction, time };
+        match intersect(&ray, &self.vertices, &Interval::new(0.001, f64::INFINITY)) {
+            Some((t, _, _)) => solid_angle_pdf(&ray, t, &self.vertices, area(&self.vertices)),
+            None => 0.0,
+        }
+    }
+}
+
+// Many triangles sharing one vertex buffer. Each face stores the indices of its three
+// vertices, so positions, normals and UVs are stored once however many faces use them.
+// The faces are kept in their own BVH, so the whole mesh is a single object in the World.
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>, // Either empty or one shading normal per position
+    pub uvs: Vec<(f64, f64)>, // Either empty or one texture coordinate per position
+    pub indices: Vec<[usize; 3]>,
+    bvh: Bvh,
+    face_areas: Option<Distribution1D>, // For picking faces in proportion to their area
+    area: f64,
+}
+
+impl TriangleMesh {
+    // Panics if a face refers to a vertex that does not exist, or if normals or UVs are
+    // given but do not match the number of positions
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f64, f64)>,
+        indices: Vec<[usize; 3]>,
+    ) -> Self {
+        assert!(
+            normals.is_empty() || normals.len() == positions.len(),
+            "Mesh has {} normals for {} positions",
+            normals.len(),
+            positions.len()
+        );
+        assert!(
+            uvs.is_empty() || uvs.len() == positions.len(),
+            "Mesh has {} UVs for {} positions",
+            uvs.len(),
+            positions.len()
+        );
+        if let Some(face) = indices.iter().find(|face| face.iter().any(|&i| i >= positions.len())) {
+            panic!("Mesh face {:?} indexes past {} positions", face, positions.len());
+        }
+        let mut mesh = TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            indices,
+            bvh: Bvh::default(),
+            face_areas: None,
+            area: 0.0,
+        };
+        let bounds: Vec<Aabb> = (0..mesh.indices.len())
+            .map(|face| {
+                let [a, b, c] = mesh.face_vertices(face);
+                Aabb::new(a, b).grow(c).pad(BOX_PADDING)
+            })
+            .collect();
+        mesh.bvh = Bvh::build(&bounds);
+        let face_areas: Vec<f64> = (0..mesh.indices.len())
+            .map(|face| area(&mesh.face_vertices(face)))
+            .collect();
+        mesh.area = face_areas.iter().sum();
+        if mesh.area > 0.0 {
+            mesh.face_areas = Some(Distribution1D::new(face_areas));
+        }
+        mesh
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len()
+    }
+
+    fn face_vertices(&self, face: usize) -> [Point3; 3] {
+        let [i0, i1, i2] = self.indices[face];
+        [self.positions[i0], self.positions[i1], self.positions[i2]]
+    }
+
+    fn face_attributes(&self, face: usize) -> VertexAttributes {
+        let [i0, i1, i2] = self.indices[face];
+        VertexAttributes {
+            normals: (!self.normals.is_empty())
+                .then(|| [self.normals[i0], self.normals[i1], self.normals[i2]]),
+            uvs: (!self.uvs.is_empty()).then(|| [self.uvs[i0], self.uvs[i1], self.uvs[i2]]),
+        }
+    }
+}
+
+impl Hittable for TriangleMesh {
+    fn hit(&self, ray: &Ray, time: &Interval, hit_rec: &mut HitRecord) -> bool {
+        // Only the closest face needs a full hit record, so the BVH search just tracks it
+        let mut closest = None;
+        let hit_anything = self.bvh.hit(ray, time, |face, interval| {
+            let (t, b1, b2) = intersect(ray, &self.face_vertices(face), interval)?;
+            closest = Some((face, t, b1, b2));
+            Some(t)
+        });
+        if let Some((face, t, b1, b2)) = closest {
+            let attributes = self.face_attributes(face);
+            record_hit(ray, t, b1, b2, &self.face_vertices(face), &attributes, hit_rec);
+        }
+        hit_anything
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    // Uniform over the surface of the whole mesh
+    fn sample_direction(&self, origin: Point3, _time: f64, rng: &mut StdRng) -> Option<Vec3> {
+        let (_, _, face) = self.face_areas.as_ref()?.sample(rng.r#gen());
+        Some(sample_point(&self.face_vertices(face), rng) - origin)
+    }
+
+    // Faces are sampled whether or not they are hidden behind others, so the density is that
+    // of every face the line crosses, not only the closest
+    fn pdf_value(&self, origin: Point3, direction: Vec3, time: f64) -> f64 {
+        if self.area <= 0.0 {
+            return 0.0;
+        }
+        let ray = Ray { origin, direction, time };
+        let mut pdf = 0.0;
+        // Reporting no hit keeps the search interval whole, so the BVH visits every face
+        self.bvh.hit(&ray, &Interval::new(0.001, f64::INFINITY), |face, interval| {
+            let vertices = self.face_vertices(face);
+            if let Some((t, _, _)) = intersect(&ray, &vertices, interval) {
+                pdf += solid_angle_pdf(&ray, t, &vertices, self.area);
+            }
+            None
+        });
+        pdf
+    }
+}
+
+fn area(vertices: &[Point3; 3]) -> f64 {
+    let [p0, p1, p2] = *vertices;
+    0.5 * (p1 - p0).cross(p2 - p0).len()
+}
+
+// Uniformly distributed point on the triangle
+fn sample_point(vertices: &[Point3; 3], rng: &mut StdRng) -> Point3 {
+    let [p0, p1, p2] = *vertices;
+    let s = rng.r#gen::<f64>().sqrt();
+    let u = rng.r#gen::<f64>();
+    p0 + s * (1.0 - u) * (p1 - p0) + s * u * (p2 - p0)
+}
+
+// Converts a density of 1 / area over a surface into a density over directions from the
+// ray origin, for the point the ray reaches at t on the given face
+fn solid_angle_pdf(ray: &Ray, t: f64, vertices: &[Point3; 3], area: f64) -> f64 {
+    let [p0, p1, p2] = *vertices;
+    let normal = (p1 - p0).cross(p2 - p0).normalize();
+    let distance = t * ray.direction.len();
+    let cosine = normal.dot(ray.direction.normalize()).abs();
+    area_to_solid_angle(1.0 / area, distance, cosine)
+}
+
+// Möller–Trumbore ray/triangle intersection. Returns the ray parameter and the barycentric
+// weights (b1, b2) of the second and third vertices.
+fn intersect(ray: &Ray, vertices: &[Point3; 3], time: &Interval) -> Option<(f64, f64, f64)> {
+    let [p0, p1, p2] = *vertices;
+    let edge1 = p1 - p0;
+    let edge2 = p2 - p0;
+    let pvec = ray.direction.cross(edge2);
+    let det = edge1.dot(pvec);
+    // Ray parallel to the triangle's plane
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = ray.origin - p0;
+    let b1 = tvec.dot(pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = tvec.cross(edge1);
+    let b2 = ray.direction.dot(qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(qvec) * inv_det;
+    if !time.contains(t) {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+// Optional per-vertex data of one face
+struct VertexAttributes {
+    normals: Option<[Vec3; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+}
+
+fn record_hit(
+    ray: &Ray,
+    t: f64,
+    b1: f64,
+    b2: f64,
+    vertices: &[Point3; 3],
+    attributes: &VertexAttributes,
+    hit_rec: &mut HitRecord,
+) {
+    let [p0, p1, p2] = *vertices;
+    let b0 = 1.0 - b1 - b2;
+    hit_rec.t = t;
+    hit_rec.p = ray.at(t);
+    hit_rec.barycentric = (b1, b2);
+    // Without texture coordinates the barycentrics parameterize the face
+    hit_rec.uv = match attributes.uvs {
+        Some([uv0, uv1, uv2]) => (
+            b0 * uv0.0 + b1 * uv1.0 + b2 * uv2.0,
+            b0 * uv0.1 + b1 * uv1.1 + b2 * uv2.1,
+        ),
+        None => (b1, b2),
+    };
+    // Ratio of the face's area in texture space to its area in the world
+    let uv_area = match attributes.uvs {
+        Some([uv0, uv1, uv2]) => {
+            0.5 * ((uv1.0 - uv0.0) * (uv2.1 - uv0.1) - (uv2.0 - uv0.0) * (uv1.1 - uv0.1)).abs()
+        }
+        None => 0.5,
+    };
+    let world_area = area(vertices);
+    hit_rec.uv_scale = if world_area > 0.0 {
+        (uv_area / world_area).sqrt()
+    } else {
+        0.0
+    };
+
+    let mut outward_normal = (p1 - p0).cross(p2 - p0).normalize();
+    let shading_normal = attributes
+        .normals
+        .map(|[n0, n1, n2]| b0 * n0 + b1 * n1 + b2 * n2)
+        .filter(|n| n.len() > 0.0)
+        .map(Vec3::normalize);
+    match shading_normal {
+        Some(shading_normal) => {
+            // Authored normals decide which side is the outside, whatever the winding order
+            if shading_normal.dot(outward_normal) < 0.0 {
+                outward_normal = -outward_normal;
+            }
+            hit_rec.set_face_normal(ray, outward_normal);
+            hit_rec.normal = if hit_rec.front_face {
+                shading_normal
+            } else {
+                -shading_normal
+            };
+        }
+        None => hit_rec.set_face_normal(ray, outward_normal),
+    }
+}
@@ -0,0 +1,404 @@
+// volume.rs
+// Heterogeneous media whose density varies over a voxel grid, for clouds, smoke and fire.
+// Grids are read from a dense binary file: the text lines
+//
+//   VOL1
+//   <nx> <ny> <nz> <channels>
+//
+// followed by nx * ny * nz voxels of little-endian 32-bit floats, the channels of each voxel
+// together, x varying fastest and then y. Channel 0 is density and channel 1, if present,
+// temperature in kelvin.
+use crate::aabb::Aabb;
+use crate::geometry::{Point3, Vec3};
+use crate::material::{BLACK, Color};
+use crate::math::Interval;
+use crate::ray::Ray;
+use rand::Rng;
+use rand::rngs::StdRng;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Voxels per side of one majorant cell
+const MAJORANT_BLOCK: usize = 8;
+// Blackbody colours are tabulated at this spacing up to BLACKBODY_MAX
+const BLACKBODY_STEP: f64 = 50.0;
+const BLACKBODY_MAX: f64 = 15000.0;
+
+#[derive(Debug)]
+pub enum VolumeError {
+    Io { path: PathBuf, source: io::Error },
+    Format { path: PathBuf, message: String },
+}
+
+impl fmt::Display for VolumeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VolumeError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            VolumeError::Format { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl Error for VolumeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VolumeError::Io { source, .. } => Some(source),
+            VolumeError::Format { .. } => None,
+        }
+    }
+}
+
+// Dense grid of voxels with one or more channels. Values are taken at voxel centres and
+// interpolated trilinearly in between.
+#[derive(Debug, Clone)]
+pub struct VoxelGrid {
+    pub size: [usize; 3],
+    pub channels: usize,
+    data: Vec<f32>,
+}
+
+impl VoxelGrid {
+    // data holds the channels of each voxel together, x varying fastest
+    pub fn new(size: [usize; 3], channels: usize, data: Vec<f32>) -> Self {
+        assert_eq!(data.len(), size[0] * size[1] * size[2] * channels);
+        VoxelGrid {
+            size,
+            channels,
+            data,
+        }
+    }
+
+    pub fn voxel(&self, x: usize, y: usize, z: usize, channel: usize) -> f64 {
+        let index = (z * self.size[1] + y) * self.size[0] + x;
+        self.data[index * self.channels + channel] as f64
+    }
+
+    // Channel at local position u in [0, 1]^3 across the grid; voxels at the edge extend to
+    // the faces of the grid
+    pub fn lookup(&self, u: Vec3, channel: usize) -> f64 {
+        let mut base = [0; 3];
+        let mut fraction = [0.0; 3];
+        for axis in 0..3 {
+            let n = self.size[axis];
+            let q = (u[axis] * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            base[axis] = (q.floor() as usize).min(n.saturating_sub(2));
+            fraction[axis] = q - base[axis] as f64;
+        }
+        let corner = |axis: usize, step: usize| (base[axis] + step).min(self.size[axis] - 1);
+        let mut value = 0.0;
+        for step in 0..8 {
+            let (sx, sy, sz) = (step & 1, (step >> 1) & 1, (step >> 2) & 1);
+            let weight = (if sx == 1 { fraction[0] } else { 1.0 - fraction[0] })
+                * (if sy == 1 { fraction[1] } else { 1.0 - fraction[1] })
+                * (if sz == 1 { fraction[2] } else { 1.0 - fraction[2] });
+            if weight > 0.0 {
+                value += weight * self.voxel(corner(0, sx), corner(1, sy), corner(2, sz), channel);
+            }
+        }
+        value
+    }
+
+    // Largest value of channel that lookup can return anywhere in the local box from lo to hi
+    fn max_over(&self, lo: Vec3, hi: Vec3, channel: usize) -> f64 {
+        let mut range = [(0, 0); 3];
+        for axis in 0..3 {
+            let n = self.size[axis] as f64;
+            let first = (lo[axis] * n - 0.5).floor().max(0.0) as usize;
+            let last = ((hi[axis] * n - 0.5).floor() + 1.0).clamp(0.0, n - 1.0) as usize;
+            range[axis] = (first.min(self.size[axis] - 1), last);
+        }
+        let mut max = 0.0_f64;
+        for z in range[2].0..=range[2].1 {
+            for y in range[1].0..=range[1].1 {
+                for x in range[0].0..=range[0].1 {
+                    max = max.max(self.voxel(x, y, z, channel));
+                }
+            }
+        }
+        max
+    }
+}
+
+pub fn load_grid(path: &Path) -> Result<VoxelGrid, VolumeError> {
+    let data = std::fs::read(path).map_err(|source| VolumeError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    read_grid(&data).map_err(|message| VolumeError::Format {
+        path: path.to_path_buf(),
+        message,
+    })
+}
+
+fn read_grid(data: &[u8]) -> Result<VoxelGrid, String> {
+    let mut lines = data.splitn(3, |&b| b == b'\n');
+    if lines.next().map(|line| line.trim_ascii()) != Some(b"VOL1".as_slice()) {
+        return Err("not a VOL1 voxel grid".to_string());
+    }
+    let header = lines.next().ok_or("missing grid size")?;
+    let numbers: Vec<usize> = String::from_utf8_lossy(header)
+        .split_whitespace()
+        .map(|token| token.parse().map_err(|_| format!("invalid grid size '{}'", token)))
+        .collect::<Result<_, _>>()?;
+    let &[nx, ny, nz, channels] = numbers.as_slice() else {
+        return Err("expected the grid size as nx ny nz channels".to_string());
+    };
+    if nx == 0 || ny == 0 || nz == 0 || channels == 0 {
+        return Err("grid has no voxels".to_string());
+    }
+    let count = nx
+        .checked_mul(ny)
+        .and_then(|n| n.checked_mul(nz))
+        .and_then(|n| n.checked_mul(channels))
+        .ok_or("grid is too large")?;
+    let body = lines.next().unwrap_or(&[]);
+    if body.len() < 4 * count {
+        return Err(format!("expected {} values but the file holds {}", count, body.len() / 4));
+    }
+    let values = body[..4 * count]
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        .collect();
+    Ok(VoxelGrid::new([nx, ny, nz], channels, values))
+}
+
+// A medium filling an axis-aligned box, with its density read from a voxel grid times
+// density_scale. If the grid has a temperature channel the medium also glows, in proportion
+// to its density as hot soot does, with the colour of a blackbody at that temperature and a
+// brightness that follows the fourth power of temperature relative to 1000 K, times
+// emission_scale.
+//
+// Free paths are sampled by delta tracking and transmittance estimated by ratio tracking:
+// both sample tentative collisions at the rate of a majorant at least as large as the density,
+// and treat the fraction density / majorant of them as real. Majorants are kept for coarse
+// blocks of voxels, so empty space is skipped and dense regions do not slow sparse ones.
+#[derive(Debug, Clone)]
+pub struct GridMedium {
+    pub grid: VoxelGrid,
+    pub bounds: Aabb,
+    pub density_scale: f64,
+    pub emission_scale: f64,
+    majorant_size: [usize; 3],
+    majorants: Vec<f64>,
+    blackbody: Vec<Color>, // At steps of BLACKBODY_STEP from 0 K; empty if nothing glows
+}
+
+impl GridMedium {
+    pub fn new(
+        grid: VoxelGrid,
+        min: Point3,
+        max: Point3,
+        density_scale: f64,
+        emission_scale: f64,
+    ) -> Self {
+        let majorant_size = grid.size.map(|n| n.div_ceil(MAJORANT_BLOCK));
+        let [mx, my, mz] = majorant_size;
+        let mut majorants = Vec::with_capacity(mx * my * mz);
+        for z in 0..mz {
+            for y in 0..my {
+                for x in 0..mx {
+                    let cell = Vec3::new(x as f64, y as f64, z as f64);
+                    let scale = Vec3::new(mx as f64, my as f64, mz as f64);
+                    let lo = Vec3::new(cell.x / scale.x, cell.y / scale.y, cell.z / scale.z);
+                    let hi = Vec3::new(
+                        (cell.x + 1.0) / scale.x,
+                        (cell.y + 1.0) / scale.y,
+                        (cell.z + 1.0) / scale.z,
+                    );
+                    majorants.push(density_scale * grid.max_over(lo, hi, 0));
+                }
+            }
+        }
+        let blackbody = if grid.channels > 1 && emission_scale > 0.0 {
+            let steps = (BLACKBODY_MAX / BLACKBODY_STEP) as usize;
+            (0..=steps).map(|i| blackbody(i as f64 * BLACKBODY_STEP)).collect()
+        } else {
+            vec![]
+        };
+        GridMedium {
+            grid,
+            bounds: Aabb::new(min, max),
+            density_scale,
+            emission_scale,
+            majorant_size,
+            majorants,
+            blackbody,
+        }
+    }
+
+    // Position of p across the bounds, in [0, 1]^3 inside them
+    fn local(&self, p: Point3) -> Vec3 {
+        let extent = self.bounds.max - self.bounds.min;
+        let offset = p - self.bounds.min;
+        Vec3::new(offset.x / extent.x, offset.y / extent.y, offset.z / extent.z)
+    }
+
+    pub fn density(&self, p: Point3) -> f64 {
+        let u = self.local(p);
+        if (0..3).any(|axis| !(0.0..=1.0).contains(&u[axis])) {
+            return 0.0;
+        }
+        self.density_scale * self.grid.lookup(u, 0).max(0.0)
+    }
+
+    // Radiance given off per unit distance at p, where the density is density
+    pub fn emission(&self, p: Point3, density: f64) -> Color {
+        if self.blackbody.is_empty() || density <= 0.0 {
+            return BLACK;
+        }
+        let kelvin = self.grid.lookup(self.local(p), 1);
+        if kelvin <= 0.0 {
+            return BLACK;
+        }
+        let position = (kelvin / BLACKBODY_STEP).min((self.blackbody.len() - 1) as f64);
+        let index = (position as usize).min(self.blackbody.len() - 2);
+        let fraction = position - index as f64;
+        let color = (1.0 - fraction) * self.blackbody[index] + fraction * self.blackbody[index + 1];
+        let brightness = (kelvin / 1000.0).powi(4);
+        (self.emission_scale * density * brightness) * color
+    }
+
+    // Ray parameter of the first real collision within interval, as for ConstantMedium. The
+    // light given off before it is estimated at each tentative collision and pushed to glow
+    // with its ray parameter.
+    pub fn sample_distance(
+        &self,
+        ray: &Ray,
+        interval: &Interval,
+        glow: &mut Vec<(f64, Color)>,
+        rng: &mut StdRng,
+    ) -> Option<f64> {
+        let mut result = None;
+        self.track(ray, interval, rng, |t, p, majorant, rng| {
+            let density = self.density(p);
+            if !self.blackbody.is_empty() {
+                glow.push((t, self.emission(p, density) / majorant));
+            }
+            if rng.r#gen::<f64>() * majorant < density {
+                result = Some(t);
+                false
+            } else {
+                true
+            }
+        });
+        result
+    }
+
+    pub fn transmittance(&self, ray: &Ray, interval: &Interval, rng: &mut StdRng) -> f64 {
+        let mut transmittance = 1.0;
+        self.track(ray, interval, rng, |_, p, majorant, _| {
+            transmittance *= 1.0 - self.density(p) / majorant;
+            transmittance > 0.0
+        });
+        transmittance.max(0.0)
+    }
+
+    // Calls visit with the ray parameter, position and majorant of each tentative collision
+    // within interval in order, until it returns false. The ray is stepped through the
+    // majorant cells it crosses, with collisions sampled afresh in each, since exponential
+    // distances do not remember how far they have come.
+    fn track(
+        &self,
+        ray: &Ray,
+        interval: &Interval,
+        rng: &mut StdRng,
+        mut visit: impl FnMut(f64, Point3, f64, &mut StdRng) -> bool,
+    ) {
+        let Some((t_enter, t_exit)) = self.bounds.clip(ray, interval) else {
+            return;
+        };
+        let speed = ray.direction.len();
+        // The ray in majorant cell units, where cell (i, j, k) spans [i, i + 1] and so on
+        let size = self.majorant_size;
+        let origin = self.local(ray.origin);
+        let extent = self.bounds.max - self.bounds.min;
+        let mut cell = [0_i64; 3];
+        let mut next = [f64::INFINITY; 3];
+        let mut delta = [f64::INFINITY; 3];
+        let mut step = [0_i64; 3];
+        let start = self.local(ray.at(t_enter));
+        for axis in 0..3 {
+            let n = size[axis] as f64;
+            let o = origin[axis] * n;
+            let d = ray.direction[axis] / extent[axis] * n;
+            cell[axis] = ((start[axis] * n).floor() as i64).clamp(0, size[axis] as i64 - 1);
+            if d > 0.0 {
+                step[axis] = 1;
+                next[axis] = (cell[axis] as f64 + 1.0 - o) / d;
+                delta[axis] = 1.0 / d;
+            } else if d < 0.0 {
+                step[axis] = -1;
+                next[axis] = (cell[axis] as f64 - o) / d;
+                delta[axis] = -1.0 / d;
+            }
+        }
+        let mut t = t_enter;
+        loop {
+            let axis = (0..3).min_by(|&a, &b| next[a].total_cmp(&next[b])).unwrap();
+            let cell_end = next[axis].max(t).min(t_exit);
+            let [x, y, z] = cell.map(|c| c as usize);
+            let index = (z * size[1] + y) * size[0] + x;
+            let majorant = self.majorants[index];
+            if majorant > 0.0 {
+                loop {
+                    t += -(1.0 - rng.r#gen::<f64>()).ln() / (majorant * speed);
+                    if t >= cell_end {
+                        break;
+                    }
+                    if !visit(t, ray.at(t), majorant, rng) {
+                        return;
+                    }
+                }
+            }
+            if cell_end >= t_exit {
+                return;
+            }
+            t = cell_end;
+            cell[axis] += step[axis];
+            if !(0..size[axis] as i64).contains(&cell[axis]) {
+                return;
+            }
+            next[axis] += delta[axis];
+        }
+    }
+}
+
+// Linear sRGB colour of a blackbody at the given temperature, scaled to unit luminance. The
+// Planck spectrum is integrated against the CIE 1931 colour matching functions, in the
+// multi-lobe Gaussian fit of Wyman, Sloan and Shirley (2013). Channels that come out negative,
+// outside the sRGB gamut, are clipped.
+pub fn blackbody(kelvin: f64) -> Color {
+    if kelvin <= 0.0 {
+        return BLACK;
+    }
+    let lobe = |lambda: f64, mean: f64, below: f64, above: f64| {
+        let x = (lambda - mean) / if lambda < mean { below } else { above };
+        (-0.5 * x * x).exp()
+    };
+    let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+    for step in 0..=80 {
+        let lambda = 380.0 + 5.0 * step as f64; // nm
+        let metres = lambda * 1e-9;
+        // Planck's law up to a constant factor; hc / k = 1.4388e-2 m K
+        let radiance = 1.0 / (metres.powi(5) * ((1.4388e-2 / (metres * kelvin)).exp() - 1.0));
+        x += radiance
+            * (1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+                - 0.065 * lobe(lambda, 501.1, 20.4, 26.2));
+        y += radiance
+            * (0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1));
+        z += radiance
+            * (1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8));
+    }
+    // Below about 50 K the exponent overflows and nothing visible is left
+    if y <= 0.0 || !y.is_finite() {
+        return BLACK;
+    }
+    let (x, z) = (x / y, z / y);
+    Color::new(
+        (3.2406 * x - 1.5372 - 0.4986 * z).max(0.0),
+        (-0.9689 * x + 1.8758 + 0.0415 * z).max(0.0),
+        (0.0557 * x - 0.2040 + 1.0570 * z).max(0.0),
+    )
+}
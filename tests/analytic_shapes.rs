@@ -0,0 +1,157 @@
+// tests/analytic_shapes.rs
+// Checks the closed-form intersections of cylinders, cones, tori and quadrics against
+// marching each ray through an implicit description of the same surface
+mod common;
+
+use common::{check, marched};
+use raytracer::geometry::{Point3, Vec3};
+use raytracer::math::Interval;
+use raytracer::quadric::{Cone, Cylinder, Quadric, Torus};
+use raytracer::ray::Ray;
+use raytracer::shapes::{Hittable, World};
+
+// Height along the axis and distance from it of p
+fn axial(p: Point3, base: Point3, axis: Vec3) -> (f64, f64) {
+    let offset = p - base;
+    let y = offset.dot(axis);
+    (y, (offset - y * axis).len())
+}
+
+fn check_cylinder(capped: bool) {
+    let (base, top, radius) = (Point3::new(0.3, -0.5, 0.2), Point3::new(-0.4, 1.2, 0.6), 0.7);
+    let (axis, height) = ((top - base).normalize(), (top - base).len());
+    let reference = marched(
+        move |p| {
+            let (y, rho) = axial(p, base, axis);
+            if capped { (rho - radius).max(-y).max(y - height) } else { rho - radius }
+        },
+        // Capped, the field's zero set is exactly the surface
+        move |p| capped || (0.0..=height).contains(&axial(p, base, axis).0),
+    );
+    let name = if capped { "capped cylinder" } else { "open cylinder" };
+    let cylinder = Cylinder::new(base, top, radius, capped).expect("valid cylinder");
+    check(name, &cylinder, &reference, 18);
+}
+
+fn check_cone(capped: bool) {
+    let (base, apex, radius) = (Point3::new(-0.2, -0.8, 0.1), Point3::new(0.5, 1.0, -0.3), 0.9);
+    let (axis, height) = ((apex - base).normalize(), (apex - base).len());
+    let slope = radius / height;
+    let reference = marched(
+        move |p| {
+            let (y, rho) = axial(p, base, axis);
+            let side = rho - slope * (height - y);
+            if capped { side.max(-y) } else { side }
+        },
+        // Capped, the field's zero set is exactly the surface
+        move |p| capped || (0.0..=height).contains(&axial(p, base, axis).0),
+    );
+    let name = if capped { "capped cone" } else { "open cone" };
+    let cone = Cone::new(base, apex, radius, capped).expect("valid cone");
+    check(name, &cone, &reference, 18);
+}
+
+fn check_torus(name: &str, major: f64, minor: f64) {
+    let (center, axis) = (Point3::new(0.1, 0.2, -0.3), Vec3::new(0.3, 1.0, 0.2).normalize());
+    let reference = marched(
+        move |p| {
+            let (y, rho) = axial(p, center, axis);
+            ((rho - major) * (rho - major) + y * y).sqrt() - minor
+        },
+        |_| true,
+    );
+    let torus = Torus::new(center, axis, major, minor).expect("valid torus");
+    check(name, &torus, &reference, 18);
+}
+
+fn check_quadric(name: &str, coefficients: [f64; 10], min: Point3, max: Point3) {
+    let quadric = Quadric::new(coefficients, min, max);
+    let (field, bounds) = (quadric.clone(), quadric.bounds);
+    let reference = marched(
+        move |p| field.value(p),
+        move |p| (0..3).all(|axis| bounds.min[axis] <= p[axis] && p[axis] <= bounds.max[axis]),
+    );
+    check(name, &quadric, &reference, 18);
+}
+
+#[test]
+fn capped_cylinder() {
+    check_cylinder(true);
+}
+
+#[test]
+fn open_cylinder() {
+    check_cylinder(false);
+}
+
+#[test]
+fn capped_cone() {
+    check_cone(true);
+}
+
+#[test]
+fn open_cone() {
+    check_cone(false);
+}
+
+#[test]
+fn torus() {
+    check_torus("torus", 1.0, 0.3);
+}
+
+#[test]
+fn thin_torus() {
+    check_torus("thin torus", 1.5, 0.1);
+}
+
+// x^2 + z^2 - y^2 = 0.25
+#[test]
+fn hyperboloid() {
+    check_quadric(
+        "hyperboloid",
+        [1.0, -1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -0.25],
+        Point3::new(-1.5, -1.0, -1.5),
+        Point3::new(1.5, 1.0, 1.5),
+    );
+}
+
+// x^2 / 4 + y^2 + 4 z^2 = 1, rotated by the xy term
+#[test]
+fn ellipsoid() {
+    check_quadric(
+        "ellipsoid",
+        [0.25, 1.0, 4.0, 0.3, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0],
+        Point3::new(-3.0, -3.0, -3.0),
+        Point3::new(3.0, 3.0, 3.0),
+    );
+}
+
+// y = x^2 - z^2, a saddle
+#[test]
+fn hyperbolic_paraboloid() {
+    check_quadric(
+        "hyperbolic paraboloid",
+        [1.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0],
+        Point3::new(-1.5, -1.0, -1.5),
+        Point3::new(1.5, 1.0, 1.5),
+    );
+}
+
+// The plane y = 0, hit exactly at the centre of its bounds, where the spherical uv mapping
+// has no direction to work from
+#[test]
+fn quadric_hit_at_centre_of_bounds() {
+    let plane = Quadric::new(
+        [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0],
+        Point3::new(-1.0, -1.0, -1.0),
+        Point3::new(1.0, 1.0, 1.0),
+    );
+    let ray = Ray {
+        origin: Point3::new(0.0, 1.0, 0.0),
+        direction: Vec3::new(0.0, -1.0, 0.0),
+        time: 0.0,
+    };
+    let mut hit_rec = World::new().new_hitrecord();
+    assert!(plane.hit(&ray, &Interval::new(0.001, f64::INFINITY), &mut hit_rec));
+    assert!(hit_rec.uv.0.is_finite() && hit_rec.uv.1.is_finite());
+}
@@ -0,0 +1,166 @@
+// tests/common/mod.rs
+// Ground truth for intersection tests. A shape is also described by a signed function of
+// position, negative inside, and each ray is marched through that function in small steps,
+// bisecting the first sign change. The shape's first hits must agree with it in distance and
+// normal for every ray.
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use raytracer::aabb::Aabb;
+use raytracer::geometry::{Point3, Vec3};
+use raytracer::math::Interval;
+use raytracer::ray::Ray;
+use raytracer::shapes::{Hittable, World};
+
+const RAY_COUNT: usize = 4000;
+const MARCH_STEP: f64 = 1e-3;
+// Step taken where the field is within MARCH_STEP of zero, so that slivers thinner than
+// MARCH_STEP, such as where a ray clips the rim of a lens, are not stepped over
+const FINE_STEP: f64 = 1e-5;
+// Rays are only followed through the shape's bounding box grown by this much, so that a box
+// cut too tight still shows up as hits the shape misses
+const MARCH_MARGIN: f64 = 0.1;
+
+// Where the first hit along a ray within an interval lies: the ray parameter and the outward
+// normal
+pub type Reference = Box<dyn Fn(&Ray, &Interval) -> Option<(f64, Vec3)>>;
+
+// First crossing of field's zero set that lies on the surface. on_surface rules out the
+// parts of the zero set an open surface does not cover.
+pub fn marched<F, S>(field: F, on_surface: S) -> Reference
+where
+    F: Fn(Point3) -> f64 + 'static,
+    S: Fn(Point3) -> bool + 'static,
+{
+    Box::new(move |ray, interval| {
+        let t = march(&field, &on_surface, ray, interval)?;
+        Some((t, gradient(&field, ray.at(t)).normalize()))
+    })
+}
+
+fn march(
+    field: &dyn Fn(Point3) -> f64,
+    on_surface: &dyn Fn(Point3) -> bool,
+    ray: &Ray,
+    interval: &Interval,
+) -> Option<f64> {
+    let length = ray.direction.len();
+    let mut t0 = interval.min;
+    let mut f0 = field(ray.at(t0));
+    while t0 < interval.max {
+        let step = if f0.abs() < MARCH_STEP { FINE_STEP } else { MARCH_STEP };
+        let t1 = t0 + step / length;
+        let f1 = field(ray.at(t1));
+        if (f0 <= 0.0) != (f1 <= 0.0) {
+            let (mut low, mut high) = (t0, t1);
+            for _ in 0..60 {
+                let mid = 0.5 * (low + high);
+                if (field(ray.at(mid)) <= 0.0) == (f0 <= 0.0) {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+            let t = 0.5 * (low + high);
+            if on_surface(ray.at(t)) {
+                return Some(t);
+            }
+        }
+        t0 = t1;
+        f0 = f1;
+    }
+    None
+}
+
+fn gradient(field: &dyn Fn(Point3) -> f64, p: Point3) -> Vec3 {
+    let h = 1e-6;
+    let partial = |axis: Vec3| (field(p + h * axis) - field(p - h * axis)) / (2.0 * h);
+    Vec3::new(
+        partial(Vec3::new(1.0, 0.0, 0.0)),
+        partial(Vec3::new(0.0, 1.0, 0.0)),
+        partial(Vec3::new(0.0, 0.0, 1.0)),
+    )
+}
+
+// Rays from all around the shape towards points in its bounding box, so most of them hit,
+// and some rays starting inside the box
+fn random_rays(rng: &mut StdRng, shape: &dyn Hittable) -> Vec<Ray> {
+    let bounds = shape.bounding_box();
+    let point_in_bounds = |rng: &mut StdRng| {
+        Point3::new(
+            rng.gen_range(bounds.min.x..bounds.max.x),
+            rng.gen_range(bounds.min.y..bounds.max.y),
+            rng.gen_range(bounds.min.z..bounds.max.z),
+        )
+    };
+    (0..RAY_COUNT)
+        .map(|i| {
+            let target = point_in_bounds(rng);
+            if i % 4 == 0 {
+                let origin = point_in_bounds(rng);
+                Ray {
+                    origin,
+                    direction: Vec3::sample_unit_vector(rng),
+                    time: 0.0,
+                }
+            } else {
+                let origin = bounds.centroid() + 6.0 * Vec3::sample_unit_vector(rng);
+                Ray {
+                    origin,
+                    direction: target - origin,
+                    time: 0.0,
+                }
+            }
+        })
+        .collect()
+}
+
+// Fires random rays at shape and compares its first hits with the reference's
+pub fn check(name: &str, shape: &dyn Hittable, reference: &Reference, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let world = World::new();
+    let bounds = shape.bounding_box();
+    let margin = Vec3::new(MARCH_MARGIN, MARCH_MARGIN, MARCH_MARGIN);
+    let region = Aabb::new(bounds.min - margin, bounds.max + margin);
+    let mut hits = 0;
+    let mut distance_errors = 0;
+    let mut normal_errors = 0;
+    for ray in &random_rays(&mut rng, shape) {
+        let mut hit_rec = world.new_hitrecord();
+        let interval = Interval::new(0.001, f64::INFINITY);
+        let hit = shape.hit(ray, &interval, &mut hit_rec);
+        let truth = match region.clip(ray, &interval) {
+            Some((t0, t1)) => reference(ray, &Interval::new(t0, t1)),
+            None => None,
+        };
+        match (hit.then_some(hit_rec.t), truth) {
+            (Some(t), Some((truth, outward))) => {
+                hits += 1;
+                let length = ray.direction.len();
+                if (t - truth).abs() * length > 1e-6 * (1.0 + truth * length) {
+                    distance_errors += 1;
+                    continue;
+                }
+                // The outward normal also tells which side the ray arrived from
+                let facing = hit_rec.normal.dot(ray.direction) <= 0.0;
+                let front_face = outward.dot(ray.direction) < 0.0;
+                if hit_rec.normal.dot(outward).abs() < 0.999
+                    || !facing
+                    || front_face != hit_rec.front_face
+                {
+                    normal_errors += 1;
+                }
+            }
+            (None, None) => {}
+            _ => distance_errors += 1,
+        }
+    }
+    assert!(hits > RAY_COUNT / 10, "{}: only {} rays hit, too few to test anything", name, hits);
+    assert!(
+        distance_errors == 0 && normal_errors == 0,
+        "{}: {} of {} hits disagree in distance and {} in normal",
+        name,
+        distance_errors,
+        hits,
+        normal_errors
+    );
+}
@@ -0,0 +1,166 @@
+// tests/csg.rs
+// Checks CSG solids against marching each ray through the same solid described by a signed
+// function, combined with min for union, max for intersection and max(a, -b) for difference
+mod common;
+
+use common::{check, marched};
+use raytracer::csg::{Csg, CsgOperation};
+use raytracer::geometry::{Point3, Vec3};
+use raytracer::planar::Cuboid;
+use raytracer::quadric::{Cylinder, Torus};
+use raytracer::math::Interval;
+use raytracer::ray::Ray;
+use raytracer::shapes::{Hittable, Shape, Sphere, World};
+use std::rc::Rc;
+use std::sync::Arc;
+
+// A solid both as a shape and as a signed function of position, negative inside
+struct Solid {
+    shape: Arc<Shape>,
+    field: Rc<dyn Fn(Point3) -> f64>,
+}
+
+fn sphere(center: Point3, radius: f64) -> Solid {
+    Solid {
+        shape: Arc::new(Shape::Sphere(Sphere::new(center.x, center.y, center.z, radius))),
+        field: Rc::new(move |p| (p - center).len() - radius),
+    }
+}
+
+fn cuboid(min: Point3, max: Point3) -> Solid {
+    Solid {
+        shape: Arc::new(Shape::Box(Cuboid::new(min, max).expect("the box is not flat"))),
+        field: Rc::new(move |p| {
+            (0..3)
+                .map(|axis| (min[axis] - p[axis]).max(p[axis] - max[axis]))
+                .fold(f64::NEG_INFINITY, f64::max)
+        }),
+    }
+}
+
+fn cylinder(base: Point3, top: Point3, radius: f64) -> Solid {
+    let (axis, height) = ((top - base).normalize(), (top - base).len());
+    Solid {
+        shape: Arc::new(Shape::Cylinder(
+            Cylinder::new(base, top, radius, true).expect("valid cylinder"),
+        )),
+        field: Rc::new(move |p| {
+            let offset = p - base;
+            let y = offset.dot(axis);
+            let rho = (offset - y * axis).len();
+            (rho - radius).max(-y).max(y - height)
+        }),
+    }
+}
+
+fn torus(center: Point3, axis: Vec3, major: f64, minor: f64) -> Solid {
+    let axis = axis.normalize();
+    Solid {
+        shape: Arc::new(Shape::Torus(
+            Torus::new(center, axis, major, minor).expect("valid torus"),
+        )),
+        field: Rc::new(move |p| {
+            let offset = p - center;
+            let y = offset.dot(axis);
+            let rho = (offset - y * axis).len();
+            ((rho - major) * (rho - major) + y * y).sqrt() - minor
+        }),
+    }
+}
+
+fn combine(operation: CsgOperation, left: Solid, right: Solid) -> Solid {
+    let (a, b) = (left.field, right.field);
+    let field: Rc<dyn Fn(Point3) -> f64> = match operation {
+        CsgOperation::Union => Rc::new(move |p| a(p).min(b(p))),
+        CsgOperation::Intersection => Rc::new(move |p| a(p).max(b(p))),
+        CsgOperation::Difference => Rc::new(move |p| a(p).max(-b(p))),
+    };
+    let csg = Csg::new(operation, vec![left.shape], vec![right.shape]);
+    Solid {
+        shape: Arc::new(Shape::Csg(csg)),
+        field,
+    }
+}
+
+fn check_solid(name: &str, solid: Solid) {
+    let field = solid.field;
+    check(name, solid.shape.as_ref(), &marched(move |p| field(p), |_| true), 23);
+}
+
+// Rays that clip the rim pass through slivers of the lens far thinner than the march step
+#[test]
+fn lens() {
+    let lens = combine(
+        CsgOperation::Intersection,
+        sphere(Point3::new(0.0, 0.0, -0.8), 1.2),
+        sphere(Point3::new(0.0, 0.0, 0.8), 1.2),
+    );
+    check_solid("lens", lens);
+}
+
+#[test]
+fn sphere_minus_box() {
+    let bitten = combine(
+        CsgOperation::Difference,
+        sphere(Point3::new(0.1, -0.2, 0.0), 1.0),
+        cuboid(Point3::new(0.0, 0.0, 0.0), Point3::new(2.0, 2.0, 2.0)),
+    );
+    check_solid("sphere minus box", bitten);
+}
+
+// A block with two crossing holes, the drills being a union of their own
+#[test]
+fn drilled_block() {
+    let drills = combine(
+        CsgOperation::Union,
+        cylinder(Point3::new(-2.0, 0.0, 0.0), Point3::new(2.0, 0.0, 0.0), 0.3),
+        cylinder(Point3::new(0.0, 0.0, -2.0), Point3::new(0.0, 0.0, 2.0), 0.25),
+    );
+    let drilled = combine(
+        CsgOperation::Difference,
+        cuboid(Point3::new(-1.0, -0.5, -1.0), Point3::new(1.0, 0.5, 1.0)),
+        drills,
+    );
+    check_solid("drilled block", drilled);
+}
+
+#[test]
+fn sphere_and_torus() {
+    let ringed = combine(
+        CsgOperation::Union,
+        sphere(Point3::new(0.0, 0.0, 0.0), 0.8),
+        torus(Point3::new(0.0, 0.1, 0.0), Vec3::new(0.2, 1.0, 0.1), 1.0, 0.3),
+    );
+    check_solid("sphere and torus", ringed);
+}
+
+// A ray through only one of the lens's spheres misses the lens, and must not disturb the
+// hit already found on a sphere behind it
+#[test]
+fn miss_leaves_earlier_hit() {
+    let lens = Csg::new(
+        CsgOperation::Intersection,
+        vec![Arc::new(Shape::Sphere(Sphere::new(-0.5, 0.0, 0.0, 1.0)))],
+        vec![Arc::new(Shape::Sphere(Sphere::new(0.5, 0.0, 0.0, 1.0)))],
+    );
+    let mut world = World::new();
+    world.add_object(Shape::Sphere(Sphere::new(0.0, 0.0, -10.0, 2.0)), 0);
+    world.add_object(Shape::Csg(lens), 0);
+    let ray = Ray {
+        origin: Point3::new(-0.45, 0.8, 5.0),
+        direction: Vec3::new(0.0, -0.08, -1.0),
+        time: 0.0,
+    };
+    let interval = Interval::new(0.001, f64::INFINITY);
+    let mut linear = world.new_hitrecord();
+    assert!(world.hit_linear(&ray, &interval, &mut linear));
+    world.build_bvh();
+    let mut bvh = world.new_hitrecord();
+    assert!(world.hit(&ray, &interval, &mut bvh));
+    assert!(
+        (linear.t - bvh.t).abs() < 1e-9 && linear.p.z < -7.0,
+        "the far sphere is hit at t = {}, but the linear search found t = {}",
+        bvh.t,
+        linear.t
+    );
+}
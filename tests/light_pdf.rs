@@ -0,0 +1,125 @@
+// tests/light_pdf.rs
+// Checks that a light's pdf_value is the density of the directions its sample_direction
+// picks. Averaging 1 / pdf over sampled directions gives the solid angle the samples can
+// reach, which must match the share of uniformly random directions that hit the shape.
+// Averaging the pdf over uniformly random directions must give one.
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use raytracer::geometry::{Point3, Vec3};
+use raytracer::math::Interval;
+use raytracer::planar::{Cuboid, Disk, Quad};
+use raytracer::ray::Ray;
+use raytracer::shapes::{Hittable, Shape, Sphere, World};
+use raytracer::triangle::TriangleMesh;
+use std::f64::consts::PI;
+
+const SAMPLE_COUNT: usize = 200_000;
+// Standard errors two estimates of the same quantity may differ by
+const TOLERANCE: f64 = 4.5;
+
+// Mean and standard error of the mean
+fn statistics(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, (variance / n).sqrt())
+}
+
+fn agree(a: (f64, f64), b: (f64, f64)) -> bool {
+    (a.0 - b.0).abs() <= TOLERANCE * (a.1 * a.1 + b.1 * b.1).sqrt() + 1e-9
+}
+
+fn check(name: &str, shape: &Shape, origin: Point3) {
+    let mut rng = StdRng::seed_from_u64(17);
+    let world = World::new();
+
+    // Solid angle by sampling the light, where every sample is weighted by 1 / pdf
+    let sampled: Vec<f64> = (0..SAMPLE_COUNT)
+        .map(|_| {
+            let direction = shape
+                .sample_direction(origin, 0.0, &mut rng)
+                .expect("the light can be sampled");
+            let pdf = shape.pdf_value(origin, direction, 0.0);
+            assert!(pdf.is_finite() && pdf > 0.0, "{}: sampled a direction of zero pdf", name);
+            1.0 / pdf
+        })
+        .collect();
+
+    // Solid angle by the share of all directions that hit the light, and the pdf's integral
+    let mut hits = Vec::with_capacity(SAMPLE_COUNT);
+    let mut pdf_integral = Vec::with_capacity(SAMPLE_COUNT);
+    for _ in 0..SAMPLE_COUNT {
+        let direction = Vec3::sample_unit_vector(&mut rng);
+        let ray = Ray {
+            origin,
+            direction,
+            time: 0.0,
+        };
+        let mut hit_rec = world.new_hitrecord();
+        let hit = shape.hit(&ray, &Interval::new(0.001, f64::INFINITY), &mut hit_rec);
+        hits.push(if hit { 4.0 * PI } else { 0.0 });
+        pdf_integral.push(4.0 * PI * shape.pdf_value(origin, direction, 0.0));
+    }
+
+    let (sampled, hits, pdf_integral) =
+        (statistics(&sampled), statistics(&hits), statistics(&pdf_integral));
+    assert!(
+        agree(sampled, hits),
+        "{}: solid angle {:.4} by sampling but {:.4} by hitting",
+        name,
+        sampled.0,
+        hits.0
+    );
+    assert!(
+        agree(pdf_integral, (1.0, 0.0)),
+        "{}: the pdf integrates to {:.4}",
+        name,
+        pdf_integral.0
+    );
+}
+
+#[test]
+fn sphere_pdf_matches_samples() {
+    let shape = Shape::Sphere(Sphere::new(0.3, 0.2, -2.0, 0.7));
+    check("sphere", &shape, Point3::new(0.0, 0.0, 0.0));
+}
+
+#[test]
+fn quad_pdf_matches_samples() {
+    let quad = Quad::new(
+        Point3::new(-0.5, 1.0, -0.5),
+        Vec3::new(1.0, 0.0, 0.0),
+        Vec3::new(0.2, 0.0, 1.0),
+    );
+    let shape = Shape::Quad(quad.expect("the quad has area"));
+    check("quad", &shape, Point3::new(0.1, 0.0, 0.2));
+}
+
+#[test]
+fn disk_pdf_matches_samples() {
+    let disk = Disk::new(Point3::new(0.0, 1.0, -1.0), Vec3::new(0.0, -1.0, 1.0), 0.6);
+    let shape = Shape::Disk(disk.expect("the disk has area"));
+    check("disk", &shape, Point3::new(0.0, 0.0, 0.0));
+}
+
+// Faces hidden behind others are still sampled, which the pdf has to count
+#[test]
+fn box_pdf_matches_samples() {
+    let cuboid = Cuboid::new(Point3::new(-0.5, 1.5, -0.5), Point3::new(0.5, 2.5, 0.5));
+    let shape = Shape::Box(cuboid.expect("the box is not flat"));
+    check("box", &shape, Point3::new(0.3, 0.0, 0.1));
+}
+
+// Closed, so every direction that reaches the mesh crosses at least two faces
+#[test]
+fn mesh_pdf_matches_samples() {
+    let positions = vec![
+        Point3::new(0.0, 1.5, 0.0),
+        Point3::new(0.8, 1.5, 0.2),
+        Point3::new(0.1, 1.6, 0.9),
+        Point3::new(0.3, 2.4, 0.4),
+    ];
+    let indices = vec![[0, 2, 1], [0, 1, 3], [1, 2, 3], [2, 0, 3]];
+    let shape = Shape::Mesh(TriangleMesh::new(positions, Vec::new(), Vec::new(), indices));
+    check("mesh", &shape, Point3::new(0.2, 0.0, 0.1));
+}
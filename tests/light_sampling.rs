@@ -0,0 +1,128 @@
+// tests/light_sampling.rs
+// Renders small images at equal sample counts with and without light sampling. Both
+// estimators converge to the same image, so their averages must agree, but sampling the
+// lights directly should leave far less noise.
+use raytracer::film::Film;
+use raytracer::geometry::{Point3, Vec3};
+use raytracer::material::DiffuseLight;
+use raytracer::scene::{CameraSettings, load_scene, parse_scene};
+use raytracer::shapes::{Shape, World};
+use raytracer::triangle::TriangleMesh;
+use std::path::Path;
+
+const WIDTH: u32 = 16;
+
+fn render(settings: &CameraSettings, world: &World, samples: u32, light_sampling: bool) -> Film {
+    let mut settings = settings.clone();
+    settings.image_width = WIDTH;
+    settings.samples = samples;
+    let mut camera = settings.build();
+    camera.seed = Some(samples as u64 + light_sampling as u64);
+    camera.light_sampling = light_sampling;
+    camera.render(world)
+}
+
+// Root mean square difference from the reference over every pixel and channel
+fn rms_error(film: &Film, reference: &Film) -> f64 {
+    let pixels = film.linear();
+    let reference = reference.linear();
+    let squared_sum: f64 = pixels
+        .iter()
+        .zip(&reference)
+        .map(|(p, r)| {
+            let d = *p - *r;
+            d.dot(d)
+        })
+        .sum();
+    (squared_sum / (3 * pixels.len()) as f64).sqrt()
+}
+
+fn mean(film: &Film) -> f64 {
+    let pixels = film.linear();
+    pixels.iter().map(|p| p.x + p.y + p.z).sum::<f64>() / (3 * pixels.len()) as f64
+}
+
+// The Cornell box, lit by its small ceiling light
+#[test]
+fn light_sampling_reduces_noise() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/cornell.toml");
+    let scene = load_scene(&path).expect("failed to load the Cornell box");
+    let reference = render(&scene.camera, &scene.world, 512, true);
+    let bsdf = render(&scene.camera, &scene.world, 16, false);
+    let nee = render(&scene.camera, &scene.world, 16, true);
+    let (bsdf_error, nee_error) = (rms_error(&bsdf, &reference), rms_error(&nee, &reference));
+    assert!(
+        nee_error < bsdf_error,
+        "light sampling leaves RMS error {:.4} against {:.4} without it",
+        nee_error,
+        bsdf_error
+    );
+}
+
+// Sphere, box and mesh lights, each of which some directions cross more than once. A light
+// pdf that is wrong for any of them biases the image rendered with light sampling.
+#[test]
+fn non_planar_lights_match_bsdf_sampling() {
+    let source = r#"
+        [camera]
+        aspect_ratio = 1.0
+        image_width = 16
+        location = [0.0, 2.0, -6.0]
+        view_target = [0.0, 0.5, 0.0]
+        vfov = 50.0
+        max_depth = 4
+
+        [background]
+        type = "void"
+
+        [materials.white]
+        type = "lambertian"
+        albedo = [0.7, 0.7, 0.7]
+
+        [materials.light]
+        type = "diffuse_light"
+        emit = [4.0, 4.0, 4.0]
+
+        [[objects]]
+        type = "quad"
+        corner = [-4.0, 0.0, -4.0]
+        u = [0.0, 0.0, 8.0]
+        v = [8.0, 0.0, 0.0]
+        material = "white"
+
+        [[objects]]
+        type = "sphere"
+        center = [-1.5, 1.8, 0.5]
+        radius = 0.5
+        material = "light"
+
+        [[objects]]
+        type = "box"
+        min = [0.8, 1.2, 0.0]
+        max = [1.8, 2.2, 1.0]
+        material = "light"
+    "#;
+    let mut scene = parse_scene(source, Path::new("lights.toml")).expect("failed to parse");
+    let light = scene.world.add_material(DiffuseLight {
+        emit: Vec3::new(4.0, 4.0, 4.0),
+    });
+    let positions = vec![
+        Point3::new(-0.5, 2.4, -1.0),
+        Point3::new(0.5, 2.4, -0.8),
+        Point3::new(0.0, 2.5, 0.0),
+        Point3::new(0.0, 3.3, -0.5),
+    ];
+    let indices = vec![[0, 1, 2], [0, 3, 1], [1, 3, 2], [2, 3, 0]];
+    let mesh = TriangleMesh::new(positions, Vec::new(), Vec::new(), indices);
+    scene.world.add_object(Shape::Mesh(mesh), light);
+    scene.world.build_bvh();
+
+    let bsdf = mean(&render(&scene.camera, &scene.world, 256, false));
+    let nee = mean(&render(&scene.camera, &scene.world, 64, true));
+    assert!(
+        (bsdf - nee).abs() < 0.03 * bsdf,
+        "mean radiance is {:.4} with light sampling but {:.4} without",
+        nee,
+        bsdf
+    );
+}
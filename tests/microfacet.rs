@@ -0,0 +1,175 @@
+// tests/microfacet.rs
+// Checks the GGX conductor. Under white Fresnel, the light a surface reflects from one
+// direction is estimated twice: from its own samples of visible facet normals, and by
+// integrating eval over uniformly sampled directions. The two must agree, as must the share
+// of samples kept and the integral of pdf, within a few standard errors, and neither may
+// exceed one. The Fresnel presets are also checked at normal incidence, where the complex
+// Fresnel equations reduce to ((n - 1)^2 + k^2) / ((n + 1)^2 + k^2).
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use raytracer::geometry::Vec3;
+use raytracer::material::{Conductor, ConductorFresnel, ConductorPreset, Material, WHITE};
+use raytracer::ray::Ray;
+use raytracer::shapes::{HitRecord, World};
+use raytracer::texture::solid;
+use std::f64::consts::PI;
+
+const SAMPLE_COUNT: usize = 200_000;
+// Standard errors two estimates of the same quantity may differ by
+const TOLERANCE: f64 = 4.5;
+
+// Mean and standard error of the mean
+fn statistics(values: &[f64]) -> (f64, f64) {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+    (mean, (variance / n).sqrt())
+}
+
+fn agree(a: (f64, f64), b: (f64, f64)) -> bool {
+    (a.0 - b.0).abs() <= TOLERANCE * (a.1 * a.1 + b.1 * b.1).sqrt() + 1e-9
+}
+
+// A uniformly distributed direction above the surface
+fn sample_hemisphere(rng: &mut StdRng) -> Vec3 {
+    let v = Vec3::sample_unit_vector(rng);
+    Vec3::new(v.x, v.y, v.z.abs())
+}
+
+// (reflected by sampling, reflected by integrating, share kept, integral of pdf)
+fn check(
+    conductor: &Conductor,
+    hit_rec: &HitRecord,
+    wo: Vec3,
+    rng: &mut StdRng,
+) -> [(f64, f64); 4] {
+    let ray_in = Ray {
+        origin: hit_rec.p + wo,
+        direction: -wo,
+        time: 0.0,
+    };
+    let mut sampled = Vec::with_capacity(SAMPLE_COUNT);
+    let mut kept = Vec::with_capacity(SAMPLE_COUNT);
+    for _ in 0..SAMPLE_COUNT {
+        match conductor.scatter(&ray_in, hit_rec, rng) {
+            Some(record) => {
+                sampled.push(record.weight(hit_rec).x);
+                kept.push(1.0);
+            }
+            None => {
+                sampled.push(0.0);
+                kept.push(0.0);
+            }
+        }
+    }
+    let mut integrated = Vec::with_capacity(SAMPLE_COUNT);
+    let mut pdf_integral = Vec::with_capacity(SAMPLE_COUNT);
+    for _ in 0..SAMPLE_COUNT {
+        let wi = sample_hemisphere(rng);
+        let f = conductor.eval(hit_rec, wo, wi).x;
+        integrated.push(2.0 * PI * f * wi.z);
+        pdf_integral.push(2.0 * PI * conductor.pdf(hit_rec, wo, wi));
+    }
+    [
+        statistics(&sampled),
+        statistics(&integrated),
+        statistics(&kept),
+        statistics(&pdf_integral),
+    ]
+}
+
+fn surface() -> HitRecord {
+    let world = World::new();
+    let mut hit_rec = world.new_hitrecord();
+    hit_rec.normal = Vec3::new(0.0, 0.0, 1.0);
+    hit_rec.geometric_normal = hit_rec.normal;
+    hit_rec.front_face = true;
+    hit_rec
+}
+
+#[test]
+fn ggx_sampling_matches_eval_and_pdf() {
+    let hit_rec = surface();
+    let mut rng = StdRng::seed_from_u64(25);
+    let cases: [(f64, f64, f64); 6] = [
+        (0.3, 0.3, 0.0),
+        (0.3, 0.3, 60.0),
+        (0.6, 0.6, 30.0),
+        (1.0, 1.0, 75.0),
+        (0.3, 0.8, 45.0),
+        (0.8, 0.4, 80.0),
+    ];
+    for (roughness_u, roughness_v, angle) in cases {
+        let conductor = Conductor {
+            fresnel: ConductorFresnel::Schlick(solid(WHITE)),
+            roughness_u: solid(Vec3::new(roughness_u, roughness_u, roughness_u)),
+            roughness_v: solid(Vec3::new(roughness_v, roughness_v, roughness_v)),
+            tangent: Vec3::new(1.0, 1.0, 0.0),
+        };
+        let theta = angle.to_radians();
+        let wo = Vec3::new(theta.sin() * 0.6, theta.sin() * 0.8, theta.cos());
+        let [sampled, integrated, kept, pdf_integral] =
+            check(&conductor, &hit_rec, wo, &mut rng);
+        let case = format!("roughness {} x {} at {} degrees", roughness_u, roughness_v, angle);
+        assert!(
+            agree(sampled, integrated),
+            "{}: sampled reflectance {:.4} disagrees with the integral of eval {:.4}",
+            case,
+            sampled.0,
+            integrated.0
+        );
+        assert!(
+            agree(kept, pdf_integral),
+            "{}: {:.4} of samples kept but the pdf integrates to {:.4}",
+            case,
+            kept.0,
+            pdf_integral.0
+        );
+        assert!(sampled.0 <= 1.0 + 1e-9, "{}: reflects more light than arrives", case);
+    }
+}
+
+#[test]
+fn presets_match_the_fresnel_equations() {
+    let hit_rec = surface();
+    let mut rng = StdRng::seed_from_u64(25);
+    for preset in [ConductorPreset::Gold, ConductorPreset::Copper, ConductorPreset::Aluminium] {
+        let conductor = Conductor::new(preset.fresnel(), solid(Vec3::default()));
+        let ConductorFresnel::Complex { eta, k } = conductor.fresnel else {
+            panic!("{:?} is not given by a complex index of refraction", preset);
+        };
+        let head_on = |n: f64, k: f64| ((n - 1.0).powi(2) + k * k) / ((n + 1.0).powi(2) + k * k);
+        let expected = Vec3::new(head_on(eta.x, k.x), head_on(eta.y, k.y), head_on(eta.z, k.z));
+        // A mirror reflects exactly the Fresnel colour
+        let ray_in = Ray {
+            origin: hit_rec.p + hit_rec.normal,
+            direction: -hit_rec.normal,
+            time: 0.0,
+        };
+        let record = conductor
+            .scatter(&ray_in, &hit_rec, &mut rng)
+            .expect("a mirror always reflects");
+        assert!(
+            (0..3).all(|axis| (record.value[axis] - expected[axis]).abs() < 1e-9),
+            "{:?}: Fresnel reflectance at normal incidence is {:?}, not {:?}",
+            preset,
+            record.value,
+            expected
+        );
+        // Towards grazing incidence every conductor becomes a perfect mirror
+        let grazing = Vec3::new(1.0, 0.0, 1e-6).normalize();
+        let ray_in = Ray {
+            origin: hit_rec.p + grazing,
+            direction: -grazing,
+            time: 0.0,
+        };
+        let record = conductor
+            .scatter(&ray_in, &hit_rec, &mut rng)
+            .expect("a mirror always reflects");
+        assert!(
+            (0..3).all(|axis| record.value[axis] > 0.999),
+            "{:?}: Fresnel reflectance does not reach one at grazing incidence",
+            preset
+        );
+    }
+}
@@ -0,0 +1,134 @@
+// tests/obj.rs
+// Checks that OBJ and MTL files load into meshes and materials, and that every error names the
+// file and line it was found on
+use raytracer::image::ImageError;
+use raytracer::material::{Color, Lambertian};
+use raytracer::obj::{ObjError, load_obj};
+use raytracer::shapes::World;
+use raytracer::texture::solid;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Writes files into a directory of their own and returns its path
+fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("obj").join(test);
+    fs::create_dir_all(&directory).expect("failed to create the test directory");
+    for (name, contents) in files {
+        fs::write(directory.join(name), contents).expect("failed to write a test file");
+    }
+    directory
+}
+
+fn load(path: &Path) -> Result<(World, usize), ObjError> {
+    let mut world = World::new();
+    let default_material = world.add_material(Lambertian {
+        albedo: solid(Color::new(0.7, 0.7, 0.7)),
+    });
+    let triangle_count = load_obj(path, &mut world, default_material)?;
+    Ok((world, triangle_count))
+}
+
+// The file, line and message of a parse error
+fn parse_error(result: Result<(World, usize), ObjError>) -> (PathBuf, usize, String) {
+    match result {
+        Err(ObjError::Parse {
+            path,
+            line,
+            message,
+        }) => (path, line, message),
+        Err(e) => panic!("expected a parse error, found '{}'", e),
+        Ok(_) => panic!("expected a parse error, but the file loaded"),
+    }
+}
+
+#[test]
+fn groups_and_materials_become_meshes() {
+    let obj = "mtllib box.mtl\n\
+               v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0 0 1\n\
+               vt 0 0\nvt 1 0\nvt 1 1\n\
+               # A quad before any usemtl uses the default material\n\
+               f 1 2 3 4\n\
+               usemtl red\n\
+               f 1/1 2/2 5/3\n\
+               g lid\n\
+               f -5 -4 -1\n";
+    let mtl = "newmtl red\nKd 0.8 0.1 0.1\n\nnewmtl lamp\nKe 4 4 4\n";
+    let directory = write_files("meshes", &[("box.obj", obj), ("box.mtl", mtl)]);
+    let (world, triangle_count) = load(&directory.join("box.obj")).expect("failed to load");
+    assert_eq!(triangle_count, 4);
+    // The library's red and lamp are registered last, straight after the default material
+    let red = world.materials.len() - 2;
+    let materials: Vec<usize> = world.objects.iter().map(|(_, material)| *material).collect();
+    assert_eq!(materials, [red - 1, red, red]);
+}
+
+#[test]
+fn obj_errors_name_the_line() {
+    let cases = [
+        ("v 0 0 0\nv 1 x 0\n", 2, "expected a number, found 'x'"),
+        ("v 0 0\n", 1, "expected a number, found end of line"),
+        ("v 0 0 0\nv 1 0 0\nv 0 1 0\n\nf 1 2 4\n", 5, "vertex index 4 out of range"),
+        ("v 0 0 0\nv 1 0 0\nf 1 0 2\n", 3, "vertex index 0 out of range"),
+        ("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1/1 2/1 3/1\n", 4, "texture coordinate index 1"),
+        ("v 0 0 0\nv 1 0 0\nf 1 2\n", 3, "face has 2 vertices"),
+        ("v 0 0 0\nf 1/1/1/1 1 1\n", 2, "malformed face vertex '1/1/1/1'"),
+        ("v 0 0 0\nf a 1 1\n", 2, "invalid vertex index 'a'"),
+        ("# A comment\nusemtl steel\n", 2, "unknown material 'steel'"),
+    ];
+    for (index, (obj, expected_line, expected_message)) in cases.into_iter().enumerate() {
+        let directory = write_files(&format!("obj_error_{}", index), &[("mesh.obj", obj)]);
+        let path = directory.join("mesh.obj");
+        let (error_path, line, message) = parse_error(load(&path));
+        assert_eq!(error_path, path);
+        assert_eq!(line, expected_line, "{:?} gave '{}'", obj, message);
+        assert!(message.contains(expected_message), "{:?} gave '{}'", obj, message);
+    }
+}
+
+#[test]
+fn mtl_errors_name_the_line() {
+    let cases = [
+        ("Kd 1 0 0\n", 1, "'Kd' before any newmtl"),
+        ("newmtl\n", 1, "newmtl without a name"),
+        ("newmtl red\nKd 1 0\n", 2, "expected a number, found end of line"),
+        ("newmtl red\n# Texture\nmap_Kd\n", 3, "map_Kd without a file name"),
+        ("newmtl red\n\nnewmtl red\n", 3, "material 'red' is defined twice"),
+    ];
+    for (index, (mtl, expected_line, expected_message)) in cases.into_iter().enumerate() {
+        let files = [("mesh.obj", "mtllib mesh.mtl\n"), ("mesh.mtl", mtl)];
+        let directory = write_files(&format!("mtl_error_{}", index), &files);
+        let (error_path, line, message) = parse_error(load(&directory.join("mesh.obj")));
+        assert_eq!(error_path, directory.join("mesh.mtl"));
+        assert_eq!(line, expected_line, "{:?} gave '{}'", mtl, message);
+        assert!(message.contains(expected_message), "{:?} gave '{}'", mtl, message);
+    }
+}
+
+#[test]
+fn missing_files_are_named() {
+    let directory = write_files(
+        "missing",
+        &[
+            ("no_library.obj", "mtllib absent.mtl\n"),
+            ("no_texture.obj", "mtllib texture.mtl\n"),
+            ("texture.mtl", "newmtl label\nmap_Kd absent.png\n"),
+        ],
+    );
+    for (obj, missing) in [
+        ("absent.obj", "absent.obj"),
+        ("no_library.obj", "absent.mtl"),
+    ] {
+        match load(&directory.join(obj)) {
+            Err(ObjError::Io { path, .. }) => assert_eq!(path, directory.join(missing)),
+            Err(e) => panic!("{}: expected a missing file error, found '{}'", obj, e),
+            Ok(_) => panic!("{}: loaded despite the missing file", obj),
+        }
+    }
+    match load(&directory.join("no_texture.obj")) {
+        Err(ObjError::Image(ImageError::Io { path, .. })) => {
+            assert_eq!(path, directory.join("absent.png"))
+        }
+        Err(e) => panic!("expected a missing texture error, found '{}'", e),
+        Ok(_) => panic!("loaded despite the missing texture"),
+    }
+}
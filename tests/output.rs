@@ -0,0 +1,216 @@
+// tests/output.rs
+// Writes small images in every output format and reads them back, checking the header and the
+// decoded pixels
+use raytracer::image::{load_hdr, load_png};
+use raytracer::material::Color;
+use raytracer::output::{OutputFormat, write_image};
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+const WIDTH: u32 = 3;
+const HEIGHT: u32 = 2;
+
+// Row by row from the top left, each pixel different so that flipped or transposed images fail
+fn pixels() -> Vec<Color> {
+    vec![
+        Color::new(0.0, 0.25, 0.5),
+        Color::new(1.0, 0.0, 0.0),
+        Color::new(0.2, 0.4, 0.6),
+        Color::new(0.0, 1.0, 0.0),
+        Color::new(0.0, 0.0, 1.0),
+        Color::new(0.75, 0.1, 0.3),
+    ]
+}
+
+// Linear radiance, with values beyond 1 that must not be clamped
+fn radiance() -> Vec<Color> {
+    vec![
+        Color::new(0.0, 0.25, 0.5),
+        Color::new(12.0, 0.0, 0.0),
+        Color::new(0.2, 0.4, 0.6),
+        Color::new(0.0, 1000.0, 0.5),
+        Color::new(0.0, 0.0, 1.0),
+        Color::new(3.5, 1.25, 0.003),
+    ]
+}
+
+fn write(name: &str, format: OutputFormat, pixels: &[Color]) -> PathBuf {
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("output");
+    fs::create_dir_all(&directory).expect("failed to create the test directory");
+    let path = directory.join(name);
+    write_image(&path, format, WIDTH, HEIGHT, pixels).expect("failed to write the image");
+    path
+}
+
+// tolerance is relative to the largest channel of the pixel, or 1 if that is smaller, since
+// RGBE shares one exponent between the channels
+fn assert_close(found: &[Color], expected: &[Color], tolerance: f64) {
+    assert_eq!(found.len(), expected.len());
+    for (i, (found, expected)) in found.iter().zip(expected).enumerate() {
+        let d = *found - *expected;
+        let scale = expected.x.max(expected.y).max(expected.z).max(1.0);
+        assert!(
+            d.x.abs().max(d.y.abs()).max(d.z.abs()) <= tolerance * scale,
+            "pixel {} is {:?}, not {:?}",
+            i,
+            found,
+            expected
+        );
+    }
+}
+
+fn check_png(name: &str, format: OutputFormat, bit_depth: png::BitDepth, tolerance: f64) {
+    let path = write(name, format, &pixels());
+    let decoder = png::Decoder::new(File::open(&path).expect("failed to open the image"));
+    let reader = decoder.read_info().expect("failed to read the PNG header");
+    let info = reader.info();
+    assert_eq!((info.width, info.height), (WIDTH, HEIGHT));
+    assert_eq!(info.color_type, png::ColorType::Rgb);
+    assert_eq!(info.bit_depth, bit_depth);
+    let image = load_png(&path).expect("failed to decode the image");
+    assert_close(&image.pixels, &pixels(), tolerance);
+}
+
+#[test]
+fn png_round_trips() {
+    check_png("image.png", OutputFormat::Png, png::BitDepth::Eight, 1.0 / 255.0);
+}
+
+#[test]
+fn png16_round_trips() {
+    check_png("image16.png", OutputFormat::Png16, png::BitDepth::Sixteen, 0.5 / 65535.0);
+}
+
+#[test]
+fn hdr_round_trips() {
+    let path = write("image.hdr", OutputFormat::Hdr, &radiance());
+    let data = fs::read(&path).expect("failed to read the image");
+    let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 3\n";
+    assert!(data.starts_with(header), "unexpected header");
+    assert_eq!(data.len(), header.len() + 4 * radiance().len());
+    let image = load_hdr(&path).expect("failed to decode the image");
+    assert_eq!((image.width, image.height), (WIDTH as usize, HEIGHT as usize));
+    // 8-bit mantissas, truncated
+    assert_close(&image.pixels, &radiance(), 1.0 / 128.0);
+}
+
+#[test]
+fn pfm_round_trips() {
+    let path = write("image.pfm", OutputFormat::Pfm, &radiance());
+    let data = fs::read(&path).expect("failed to read the image");
+    let header = b"PF\n3 2\n-1.0\n";
+    assert!(data.starts_with(header), "unexpected header");
+    let floats: Vec<f64> = data[header.len()..]
+        .chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()) as f64)
+        .collect();
+    assert_eq!(floats.len(), 3 * radiance().len());
+    // Rows are stored bottom to top
+    let pixels: Vec<Color> = floats
+        .chunks_exact(3 * WIDTH as usize)
+        .rev()
+        .flat_map(|row| row.chunks_exact(3).map(|c| Color::new(c[0], c[1], c[2])))
+        .collect();
+    assert_close(&pixels, &radiance(), 1e-7);
+}
+
+// Reads a null-terminated string and moves position past it
+fn exr_string(data: &[u8], position: &mut usize) -> String {
+    let end = *position + data[*position..].iter().position(|&b| b == 0).unwrap();
+    let string = String::from_utf8(data[*position..end].to_vec()).unwrap();
+    *position = end + 1;
+    string
+}
+
+// The attributes of an OpenEXR header, each a name, type name and value, and the position just
+// past the header
+fn exr_header(data: &[u8]) -> (Vec<(String, String, Vec<u8>)>, usize) {
+    let mut position = 8;
+    let mut attributes = vec![];
+    loop {
+        let name = exr_string(data, &mut position);
+        if name.is_empty() {
+            return (attributes, position);
+        }
+        let type_name = exr_string(data, &mut position);
+        let size = i32::from_le_bytes(data[position..position + 4].try_into().unwrap()) as usize;
+        position += 4;
+        attributes.push((name, type_name, data[position..position + size].to_vec()));
+        position += size;
+    }
+}
+
+fn half_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f64;
+    sign * match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        0x1f => f64::INFINITY,
+        _ => (1.0 + mantissa / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
+fn check_exr(name: &str, format: OutputFormat, type_code: i32, tolerance: f64) {
+    let path = write(name, format, &radiance());
+    let data = fs::read(&path).expect("failed to read the image");
+    let word = |i: usize| i32::from_le_bytes(data[i..i + 4].try_into().unwrap());
+    assert_eq!(word(0), 20000630, "not an OpenEXR file");
+    assert_eq!(word(4), 2, "not a single-part scanline file");
+
+    let (attributes, header_end) = exr_header(&data);
+    let attribute = |name: &str| -> &[u8] {
+        let (_, _, value) = attributes
+            .iter()
+            .find(|(n, _, _)| n == name)
+            .unwrap_or_else(|| panic!("no {} attribute", name));
+        value
+    };
+    // Channels B, G and R with the pixel type, each an 18 byte entry, and a final null
+    let channels = attribute("channels");
+    assert_eq!(channels.len(), 3 * 18 + 1);
+    for (i, channel) in [b'B', b'G', b'R'].into_iter().enumerate() {
+        let entry = &channels[18 * i..18 * (i + 1)];
+        assert_eq!(&entry[..2], &[channel, 0]);
+        assert_eq!(i32::from_le_bytes(entry[2..6].try_into().unwrap()), type_code);
+    }
+    assert_eq!(attribute("compression"), &[0]);
+    let window: Vec<u8> = [0i32, 0, WIDTH as i32 - 1, HEIGHT as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+    assert_eq!(attribute("dataWindow"), &window[..]);
+
+    // Each scanline block, found through the offset table, holds the row's B, G and R values
+    let sample_size = if type_code == 1 { 2 } else { 4 };
+    let mut pixels = vec![];
+    for y in 0..HEIGHT as usize {
+        let entry = header_end + 8 * y;
+        let offset = u64::from_le_bytes(data[entry..entry + 8].try_into().unwrap()) as usize;
+        assert_eq!(word(offset), y as i32);
+        assert_eq!(word(offset + 4) as usize, 3 * WIDTH as usize * sample_size);
+        let sample = |channel: usize, x: usize| {
+            let i = offset + 8 + (channel * WIDTH as usize + x) * sample_size;
+            let bytes = &data[i..i + sample_size];
+            match sample_size {
+                2 => half_to_f64(u16::from_le_bytes(bytes.try_into().unwrap())),
+                _ => f32::from_le_bytes(bytes.try_into().unwrap()) as f64,
+            }
+        };
+        for x in 0..WIDTH as usize {
+            pixels.push(Color::new(sample(2, x), sample(1, x), sample(0, x)));
+        }
+    }
+    assert_close(&pixels, &radiance(), tolerance);
+}
+
+#[test]
+fn exr_round_trips() {
+    // Half floats have 11 significant bits
+    check_exr("image.exr", OutputFormat::Exr, 1, 1.0 / 2048.0);
+}
+
+#[test]
+fn exr32_round_trips() {
+    check_exr("image32.exr", OutputFormat::ExrFloat, 2, 1e-7);
+}
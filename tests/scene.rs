@@ -0,0 +1,312 @@
+// tests/scene.rs
+// Checks that scene files describing shapes that cannot be rendered are rejected, and that
+// every other mistake in a scene file is reported as its own error
+use raytracer::image::ImageError;
+use raytracer::obj::ObjError;
+use raytracer::scene::{SceneError, load_scene, parse_scene};
+use raytracer::volume::VolumeError;
+use std::path::{Path, PathBuf};
+
+// Relative paths in test scenes resolve into this directory, which does not exist
+const SCENE_PATH: &str = "missing/scene.toml";
+
+// Parses a scene with a camera and a white material, followed by rest
+fn parse(rest: &str) -> Result<(), SceneError> {
+    let source = format!(
+        r#"
+        [camera]
+        aspect_ratio = 1.0
+        image_width = 8
+        location = [0.0, 0.0, -1.0]
+        view_target = [0.0, 0.0, 0.0]
+        vfov = 40.0
+
+        [materials.white]
+        type = "lambertian"
+        albedo = [0.7, 0.7, 0.7]
+
+        {}
+        "#,
+        rest
+    );
+    parse_scene(&source, Path::new(SCENE_PATH)).map(|_| ())
+}
+
+fn parse_object(object: &str) -> Result<(), SceneError> {
+    parse(&format!("[[objects]]\n{}\nmaterial = \"white\"", object))
+}
+
+#[test]
+fn flat_shapes_need_area() {
+    let degenerate = [
+        r#"type = "quad"
+        corner = [0.0, 0.0, 0.0]
+        u = [1.0, 0.0, 0.0]
+        v = [2.0, 0.0, 0.0]"#,
+        r#"type = "quad"
+        corner = [0.0, 0.0, 0.0]
+        u = [0.0, 0.0, 0.0]
+        v = [0.0, 1.0, 0.0]"#,
+        r#"type = "disk"
+        center = [0.0, 0.0, 0.0]
+        normal = [0.0, 0.0, 0.0]
+        radius = 1.0"#,
+        r#"type = "disk"
+        center = [0.0, 0.0, 0.0]
+        normal = [0.0, 1.0, 0.0]
+        radius = 0.0"#,
+        r#"type = "plane"
+        point = [0.0, 0.0, 0.0]
+        normal = [0.0, 0.0, 0.0]"#,
+        r#"type = "box"
+        min = [0.0, 0.0, 0.0]
+        max = [1.0, 0.0, 1.0]"#,
+    ];
+    for object in degenerate {
+        assert!(
+            matches!(parse_object(object), Err(SceneError::DegenerateShape { .. })),
+            "accepted {}",
+            object
+        );
+    }
+    let valid = r#"type = "quad"
+        corner = [0.0, 0.0, 0.0]
+        u = [1.0, 0.0, 0.0]
+        v = [0.0, 1.0, 0.0]"#;
+    assert!(parse_object(valid).is_ok());
+}
+
+#[test]
+fn curved_shapes_need_an_axis_and_radii() {
+    let degenerate = [
+        r#"type = "cylinder"
+        base = [0.0, 1.0, 0.0]
+        top = [0.0, 1.0, 0.0]
+        radius = 0.5"#,
+        r#"type = "cylinder"
+        base = [0.0, 0.0, 0.0]
+        top = [0.0, 1.0, 0.0]
+        radius = -0.5"#,
+        r#"type = "cone"
+        base = [0.0, 0.0, 0.0]
+        apex = [0.0, 0.0, 0.0]
+        radius = 0.5"#,
+        r#"type = "torus"
+        center = [0.0, 0.0, 0.0]
+        axis = [0.0, 0.0, 0.0]
+        major_radius = 1.0
+        minor_radius = 0.2"#,
+        r#"type = "torus"
+        center = [0.0, 0.0, 0.0]
+        major_radius = 0.2
+        minor_radius = 1.0"#,
+        r#"type = "torus"
+        center = [0.0, 0.0, 0.0]
+        major_radius = 1.0
+        minor_radius = 0.0"#,
+    ];
+    for object in degenerate {
+        assert!(
+            matches!(parse_object(object), Err(SceneError::DegenerateShape { .. })),
+            "accepted {}",
+            object
+        );
+    }
+    let valid = r#"type = "torus"
+        center = [0.0, 0.0, 0.0]
+        major_radius = 1.0
+        minor_radius = 0.2"#;
+    assert!(parse_object(valid).is_ok());
+}
+
+// A small sphere in the white material, for the shapes table
+const BALL: &str = r#"type = "sphere"
+        center = [0.0, 0.0, 0.0]
+        radius = 0.5
+        material = "white""#;
+
+fn missing(file: &str) -> PathBuf {
+    Path::new(SCENE_PATH).with_file_name(file)
+}
+
+#[test]
+fn missing_scene_file_is_named() {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenes/absent.toml");
+    match load_scene(&path) {
+        Err(SceneError::Io { path: error_path, .. }) => assert_eq!(error_path, path),
+        Err(e) => panic!("expected a missing file error, found '{}'", e),
+        Ok(_) => panic!("loaded a scene that does not exist"),
+    }
+}
+
+#[test]
+fn parse_errors_give_the_location() {
+    // The text after the shared header starts on line 13. Field errors point at their table,
+    // and value errors at the value.
+    let cases = [
+        ("[materials.red]\ntype = \"lambertian\"\nalbdo = [1.0, 0.0, 0.0]", 13, "albdo"),
+        ("[[objects]]\ntype = \"blob\"\nmaterial = \"white\"", 14, "blob"),
+        ("[[objects]]\ntype = \"sphere\"\nradius = 1.0\nmaterial = \"white\"", 13, "center"),
+        ("[background]\ntype = \"solid\"\ncolor = [0.0, 0.0 0.0]", 15, "invalid array"),
+    ];
+    for (rest, expected_line, expected_message) in cases {
+        match parse(rest) {
+            Err(SceneError::Parse { path, source }) => {
+                assert_eq!(path, Path::new(SCENE_PATH));
+                let message = source.to_string();
+                assert!(message.contains(expected_message), "{:?} gave '{}'", rest, message);
+                assert!(
+                    message.contains(&format!("line {}", expected_line)),
+                    "{:?} gave '{}'",
+                    rest,
+                    message
+                );
+            }
+            Err(e) => panic!("{:?}: expected a parse error, found '{}'", rest, e),
+            Ok(_) => panic!("{:?}: parsed", rest),
+        }
+    }
+}
+
+#[test]
+fn unknown_material_lists_the_known_ones() {
+    let object = r#"type = "sphere"
+        center = [0.0, 0.0, 0.0]
+        radius = 0.5"#;
+    match parse(&format!("[[objects]]\n{}\nmaterial = \"red\"", object)) {
+        Err(SceneError::UnknownMaterial {
+            object,
+            name,
+            known,
+            ..
+        }) => {
+            assert_eq!(object, "object 1 (sphere)");
+            assert_eq!(name, "red");
+            assert_eq!(known, ["white"]);
+        }
+        Err(e) => panic!("expected an unknown material error, found '{}'", e),
+        Ok(_) => panic!("parsed"),
+    }
+}
+
+#[test]
+fn unknown_shape_lists_the_known_ones() {
+    let rest = format!(
+        "[shapes.nut]\n{}\n\n[[objects]]\ntype = \"instance\"\nshape = \"bolt\"\ntransform = []",
+        BALL
+    );
+    match parse(&rest) {
+        Err(SceneError::UnknownShape {
+            object,
+            name,
+            known,
+            ..
+        }) => {
+            assert_eq!(object, "object 1 (instance)");
+            assert_eq!(name, "bolt");
+            assert_eq!(known, ["nut"]);
+        }
+        Err(e) => panic!("expected an unknown shape error, found '{}'", e),
+        Ok(_) => panic!("parsed"),
+    }
+}
+
+#[test]
+fn shapes_cannot_contain_themselves() {
+    let rest = format!(
+        r#"[shapes.ball]
+        {}
+
+        [shapes.left]
+        type = "csg"
+        operation = "union"
+        left = "ball"
+        right = "right"
+        material = "white"
+
+        [shapes.right]
+        type = "instance"
+        shape = "left"
+        transform = [{{ translate = [1.0, 0.0, 0.0] }}]
+
+        [[objects]]
+        type = "instance"
+        shape = "right"
+        transform = []"#,
+        BALL
+    );
+    match parse(&rest) {
+        Err(SceneError::ShapeCycle { name, .. }) => assert_eq!(name, "right"),
+        Err(e) => panic!("expected a shape cycle error, found '{}'", e),
+        Ok(_) => panic!("parsed"),
+    }
+}
+
+#[test]
+fn transforms_must_be_invertible() {
+    let transforms = [
+        "transform = [{ scale = [1.0, 0.0, 1.0] }]",
+        "transform = [{ matrix = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], \
+         [1.0, 1.0, 0.0, 0.0], [0.0, 0.0, 0.0, 1.0]] }]",
+        "transform = []\nkeyframes = [{ time = 0.0, transform = [] }, \
+         { time = 1.0, transform = [{ scale = 0.0 }] }]",
+    ];
+    for transform in transforms {
+        let rest = format!(
+            "[shapes.ball]\n{}\n\n[[objects]]\ntype = \"instance\"\nshape = \"ball\"\n{}",
+            BALL, transform
+        );
+        match parse(&rest) {
+            Err(SceneError::SingularTransform { object, .. }) => {
+                assert_eq!(object, "object 1 (instance)")
+            }
+            Err(e) => panic!("{}: expected a singular transform error, found '{}'", transform, e),
+            Ok(_) => panic!("{}: parsed", transform),
+        }
+    }
+}
+
+#[test]
+fn missing_mesh_is_named() {
+    match parse("[[objects]]\ntype = \"mesh\"\npath = \"absent.obj\"") {
+        Err(SceneError::Obj(ObjError::Io { path, .. })) => assert_eq!(path, missing("absent.obj")),
+        Err(e) => panic!("expected a missing mesh error, found '{}'", e),
+        Ok(_) => panic!("parsed"),
+    }
+}
+
+#[test]
+fn missing_images_are_named() {
+    let cases = [
+        (
+            "[materials.label]\ntype = \"lambertian\"\n\
+             albedo = { type = \"image\", path = \"absent.png\" }",
+            "absent.png",
+        ),
+        ("[background]\ntype = \"environment\"\npath = \"absent.hdr\"", "absent.hdr"),
+    ];
+    for (rest, file) in cases {
+        match parse(rest) {
+            Err(SceneError::Image(ImageError::Io { path, .. })) => assert_eq!(path, missing(file)),
+            Err(e) => panic!("{:?}: expected a missing image error, found '{}'", rest, e),
+            Ok(_) => panic!("{:?}: parsed", rest),
+        }
+    }
+}
+
+#[test]
+fn missing_volume_is_named() {
+    let rest = r#"[[media]]
+        type = "grid"
+        path = "absent.vol"
+        min = [-1.0, -1.0, -1.0]
+        max = [1.0, 1.0, 1.0]
+        material = "white""#;
+    match parse(rest) {
+        Err(SceneError::Volume(VolumeError::Io { path, .. })) => {
+            assert_eq!(path, missing("absent.vol"))
+        }
+        Err(e) => panic!("expected a missing volume error, found '{}'", e),
+        Ok(_) => panic!("parsed"),
+    }
+}
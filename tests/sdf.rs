@@ -0,0 +1,154 @@
+// tests/sdf.rs
+// Checks sphere tracing of distance-function shapes. Primitives are checked against the
+// analytic shapes they match, the capsule against a CSG union of a cylinder and two spheres.
+// Blends and repeats are checked against marching the same distance function.
+mod common;
+
+use common::{Reference, check, marched};
+use raytracer::csg::{Csg, CsgOperation};
+use raytracer::geometry::{Point3, Vec3};
+use raytracer::planar::Cuboid;
+use raytracer::quadric::{Cylinder, Torus};
+use raytracer::sdf::{Sdf, SdfNode};
+use raytracer::shapes::{Hittable, Shape, Sphere, World};
+use std::sync::Arc;
+
+// The first hit of a shape known to be right
+fn analytic(shape: Shape) -> Reference {
+    let world = World::new();
+    Box::new(move |ray, interval| {
+        let mut hit_rec = world.new_hitrecord();
+        shape.hit(ray, interval, &mut hit_rec).then(|| {
+            let outward = if hit_rec.front_face { hit_rec.normal } else { -hit_rec.normal };
+            (hit_rec.t, outward)
+        })
+    })
+}
+
+fn check_marched(name: &str, node: SdfNode) {
+    let sdf = Sdf::new(node.clone());
+    let field = Sdf::new(node);
+    check(name, &sdf, &marched(move |p| field.distance(p), |_| true), 24);
+}
+
+#[test]
+fn sphere() {
+    let center = Point3::new(0.2, -0.1, 0.3);
+    let sdf = Sdf::new(SdfNode::Sphere {
+        center,
+        radius: 0.8,
+    });
+    let sphere = Sphere::new(center.x, center.y, center.z, 0.8);
+    check("sphere", &sdf, &analytic(Shape::Sphere(sphere)), 24);
+}
+
+#[test]
+fn cuboid() {
+    let center = Point3::new(0.2, -0.1, 0.3);
+    let half_size = Vec3::new(0.9, 0.5, 0.7);
+    let sdf = Sdf::new(SdfNode::Box { center, half_size });
+    let cuboid = Cuboid::new(center - half_size, center + half_size).expect("the box is not flat");
+    check("box", &sdf, &analytic(Shape::Box(cuboid)), 24);
+}
+
+#[test]
+fn torus() {
+    let center = Point3::new(0.2, -0.1, 0.3);
+    let sdf = Sdf::new(SdfNode::Torus {
+        center,
+        major_radius: 1.0,
+        minor_radius: 0.25,
+    });
+    let torus = Torus::new(center, Vec3::new(0.0, 1.0, 0.0), 1.0, 0.25).expect("valid torus");
+    check("torus", &sdf, &analytic(Shape::Torus(torus)), 24);
+}
+
+#[test]
+fn capsule() {
+    let (a, b, radius) = (Point3::new(-0.6, -0.4, 0.1), Point3::new(0.5, 0.7, -0.2), 0.35);
+    let sdf = Sdf::new(SdfNode::Capsule { a, b, radius });
+    let capsule = Csg::new(
+        CsgOperation::Union,
+        vec![Arc::new(Shape::Cylinder(
+            Cylinder::new(a, b, radius, true).expect("valid cylinder"),
+        ))],
+        vec![
+            Arc::new(Shape::Sphere(Sphere::new(a.x, a.y, a.z, radius))),
+            Arc::new(Shape::Sphere(Sphere::new(b.x, b.y, b.z, radius))),
+        ],
+    );
+    check("capsule", &sdf, &analytic(Shape::Csg(capsule)), 24);
+}
+
+#[test]
+fn smooth_union() {
+    let blob = SdfNode::SmoothUnion {
+        smoothness: 0.4,
+        parts: vec![
+            SdfNode::Sphere {
+                center: Point3::new(-0.3, 0.0, 0.0),
+                radius: 0.6,
+            },
+            SdfNode::Sphere {
+                center: Point3::new(0.5, 0.2, 0.1),
+                radius: 0.45,
+            },
+            SdfNode::Capsule {
+                a: Point3::new(0.0, 0.0, 0.0),
+                b: Point3::new(0.2, 1.0, -0.3),
+                radius: 0.15,
+            },
+        ],
+    };
+    check_marched("smooth union", blob);
+}
+
+#[test]
+fn smooth_subtract() {
+    let carved = SdfNode::SmoothSubtract {
+        smoothness: 0.1,
+        parts: vec![
+            SdfNode::Box {
+                center: Point3::new(0.0, 0.0, 0.0),
+                half_size: Vec3::new(0.8, 0.8, 0.8),
+            },
+            SdfNode::Sphere {
+                center: Point3::new(0.0, 0.0, 0.0),
+                radius: 1.0,
+            },
+        ],
+    };
+    check_marched("smooth subtract", carved);
+}
+
+#[test]
+fn smooth_intersect() {
+    let rounded = SdfNode::SmoothIntersect {
+        smoothness: 0.2,
+        parts: vec![
+            SdfNode::Box {
+                center: Point3::new(0.0, 0.0, 0.0),
+                half_size: Vec3::new(0.7, 0.7, 0.7),
+            },
+            SdfNode::Sphere {
+                center: Point3::new(0.0, 0.0, 0.0),
+                radius: 0.9,
+            },
+        ],
+    };
+    check_marched("smooth intersect", rounded);
+}
+
+#[test]
+fn repeat() {
+    let lattice = SdfNode::Repeat {
+        spacing: Vec3::new(0.6, 0.0, 0.6),
+        count: Some([2, 0, 2]),
+        part: Box::new(SdfNode::Torus {
+            center: Point3::new(0.0, 0.0, 0.0),
+            major_radius: 0.2,
+            minor_radius: 0.06,
+        }),
+    };
+    check_marched("repeat", lattice);
+}